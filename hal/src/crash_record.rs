@@ -0,0 +1,234 @@
+//! # Crash records
+//!
+//! A [`CrashRecord`] preserves the state of the processor at the time of a
+//! hard fault or a panic across the following reset, so that the cause of a
+//! crash in the field can be reported at the next boot.
+//!
+//! The record is stored in the `.uninit` RAM section provided by
+//! `cortex-m-rt`, which is not initialized at start-up and therefore keeps its
+//! contents across a reset, as long as the device stays powered. A magic
+//! number and a checksum distinguish a valid record from the random contents
+//! of RAM after a power-on reset.
+//!
+//! The record is written by [`record_hard_fault`] and [`record_panic`], which
+//! then reset the processor. They are installed by calling them from the
+//! application's `HardFault` and panic handlers:
+//!
+//! ```
+//! use atsamd_hal::crash_record;
+//!
+//! #[exception]
+//! unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+//!     crash_record::record_hard_fault(frame.pc(), frame.lr(), frame.xpsr())
+//! }
+//!
+//! #[panic_handler]
+//! fn panic(_: &core::panic::PanicInfo) -> ! {
+//!     crash_record::record_panic()
+//! }
+//! ```
+//!
+//! An application-defined tag, e.g. the current state of a state machine,
+//! can be set at any time with [`set_tag`], and is saved in the record. At
+//! the next boot, the record is read and cleared with [`take`]:
+//!
+//! ```
+//! if let Some(crash) = crash_record::take(reset_cause(&peripherals.RSTC)) {
+//!     log_crash(&crash);
+//! }
+//! ```
+
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::SCB;
+
+use crate::ResetCause;
+
+/// Marks a valid record, and is cleared once the record has been taken
+const MAGIC: u32 = 0xC7A5_4EC0;
+
+/// The kind of crash described by a [`CrashRecord`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CrashKind {
+    /// A HardFault exception, including escalated faults
+    HardFault,
+    /// A panic
+    Panic,
+}
+
+/// State of the processor at the time of a crash
+///
+/// The fault status registers are only available on SAMD5x/E5x devices, and
+/// read as zero on SAMD11/SAMD21 devices.
+#[derive(Clone, Copy, Debug)]
+pub struct CrashRecord {
+    /// The kind of crash
+    pub kind: CrashKind,
+    /// Program counter of the faulting instruction, or of the call to
+    /// [`record_panic`]
+    pub pc: u32,
+    /// Link register at the time of the crash
+    pub lr: u32,
+    /// Program status register at the time of the crash
+    pub xpsr: u32,
+    /// Configurable Fault Status Register
+    pub cfsr: u32,
+    /// HardFault Status Register
+    pub hfsr: u32,
+    /// MemManage Fault Address Register
+    pub mmfar: u32,
+    /// BusFault Address Register
+    pub bfar: u32,
+    /// Tag set with [`set_tag`] before the crash
+    pub tag: u32,
+    /// Cause of the reset that followed the crash
+    pub reset_cause: ResetCause,
+}
+
+/// In-memory layout of the record
+#[repr(C)]
+struct RawRecord {
+    magic: u32,
+    kind: u32,
+    pc: u32,
+    lr: u32,
+    xpsr: u32,
+    cfsr: u32,
+    hfsr: u32,
+    mmfar: u32,
+    bfar: u32,
+    tag: u32,
+    checksum: u32,
+}
+
+impl RawRecord {
+    fn checksum(&self) -> u32 {
+        [
+            self.magic, self.kind, self.pc, self.lr, self.xpsr, self.cfsr, self.hfsr, self.mmfar,
+            self.bfar, self.tag,
+        ]
+        .iter()
+        .fold(0x811C_9DC5, |acc: u32, word| {
+            (acc ^ word).wrapping_mul(0x0100_0193)
+        })
+    }
+}
+
+#[link_section = ".uninit.atsamd_hal.crash_record"]
+static mut RECORD: MaybeUninit<RawRecord> = MaybeUninit::uninit();
+
+#[inline]
+fn record_ptr() -> *mut RawRecord {
+    // `MaybeUninit<T>` has the same layout as `T`. Taking the address of a
+    // `static mut` only requires `unsafe` on older compilers.
+    #[allow(unused_unsafe)]
+    unsafe {
+        ptr::addr_of_mut!(RECORD) as *mut RawRecord
+    }
+}
+
+static TAG: AtomicU32 = AtomicU32::new(0);
+
+/// Set the tag saved in the next crash record
+pub fn set_tag(tag: u32) {
+    TAG.store(tag, Ordering::Relaxed);
+}
+
+/// Returns the current tag
+pub fn tag() -> u32 {
+    TAG.load(Ordering::Relaxed)
+}
+
+/// Record a hard fault, and reset the processor
+///
+/// `pc`, `lr` and `xpsr` are taken from the exception frame stacked on entry
+/// to the `HardFault` handler.
+pub fn record_hard_fault(pc: u32, lr: u32, xpsr: u32) -> ! {
+    write(CrashKind::HardFault, pc, lr, xpsr)
+}
+
+/// Record a panic, and reset the processor
+pub fn record_panic() -> ! {
+    let pc = cortex_m::register::pc::read();
+    let lr = cortex_m::register::lr::read();
+    write(CrashKind::Panic, pc, lr, 0)
+}
+
+fn write(kind: CrashKind, pc: u32, lr: u32, xpsr: u32) -> ! {
+    cortex_m::interrupt::disable();
+    let (cfsr, hfsr, mmfar, bfar) = fault_status();
+    let mut record = RawRecord {
+        magic: MAGIC,
+        kind: kind as u32,
+        pc,
+        lr,
+        xpsr,
+        cfsr,
+        hfsr,
+        mmfar,
+        bfar,
+        tag: tag(),
+        checksum: 0,
+    };
+    record.checksum = record.checksum();
+    // SAFETY: Interrupts are disabled and the processor is reset right
+    // after, so nothing else can access the record
+    unsafe { ptr::write_volatile(record_ptr(), record) };
+    SCB::sys_reset()
+}
+
+#[cfg(feature = "min-samd51g")]
+fn fault_status() -> (u32, u32, u32, u32) {
+    // SAFETY: Only reads the fault status registers
+    let scb = unsafe { &*SCB::PTR };
+    (
+        scb.cfsr.read(),
+        scb.hfsr.read(),
+        scb.mmfar.read(),
+        scb.bfar.read(),
+    )
+}
+
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+fn fault_status() -> (u32, u32, u32, u32) {
+    (0, 0, 0, 0)
+}
+
+/// Read and clear the crash record left by the last reset
+///
+/// `reset_cause` is the cause of the last reset, as returned by
+/// [`reset_cause`](crate::reset_cause), and is stored in the returned record.
+/// Returns `None` if no crash was recorded since the last power-on reset.
+pub fn take(reset_cause: ResetCause) -> Option<CrashRecord> {
+    // SAFETY: The record is only written right before a reset. Reading it
+    // with a volatile access yields whatever the RAM contains, which is
+    // validated below. Clearing the magic number, the first field,
+    // invalidates the record.
+    let raw = unsafe {
+        let raw = ptr::read_volatile(record_ptr());
+        ptr::write_volatile(record_ptr() as *mut u32, 0);
+        raw
+    };
+    if raw.magic != MAGIC || raw.checksum != raw.checksum() {
+        return None;
+    }
+    let kind = match raw.kind {
+        0 => CrashKind::HardFault,
+        1 => CrashKind::Panic,
+        _ => return None,
+    };
+    Some(CrashRecord {
+        kind,
+        pc: raw.pc,
+        lr: raw.lr,
+        xpsr: raw.xpsr,
+        cfsr: raw.cfsr,
+        hfsr: raw.hfsr,
+        mmfar: raw.mmfar,
+        bfar: raw.bfar,
+        tag: raw.tag,
+        reset_cause,
+    })
+}
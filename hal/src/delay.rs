@@ -5,6 +5,7 @@ use cortex_m::peripheral::SYST;
 
 use crate::clock::GenericClockController;
 use crate::ehal::blocking::delay::{DelayMs, DelayUs};
+use crate::reclock::Reclock;
 use crate::time::Hertz;
 
 /// System timer (SysTick) as a delay provider
@@ -30,6 +31,13 @@ impl Delay {
     }
 }
 
+impl Reclock for Delay {
+    /// Update the CPU clock frequency after gclk0 has been changed
+    fn clock_changed(&mut self, freq: Hertz) {
+        self.sysclock = freq;
+    }
+}
+
 impl DelayMs<u32> for Delay {
     fn delay_ms(&mut self, ms: u32) {
         self.delay_us(ms * 1_000);
@@ -14,6 +14,9 @@
 //! If needed, [`dynpin`] can be used to erase the type-level differences
 //! between pins. However, by doing so, pins must now be tracked at run-time,
 //! and each pin has a non-zero memory footprint.
+//!
+//! Several pins of the same PORT group can be collected into a
+//! [`PinGroup`](group::PinGroup), which reads or writes all of them at once.
 
 pub mod pin;
 pub use pin::*;
@@ -21,4 +24,7 @@ pub use pin::*;
 pub mod dynpin;
 pub use dynpin::*;
 
+pub mod group;
+pub use group::*;
+
 mod reg;
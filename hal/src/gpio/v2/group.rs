@@ -0,0 +1,267 @@
+//! # Atomic operations on groups of pins
+//!
+//! A [`PinGroup`] owns a set of pins in the same [`DynGroup`], and changes or
+//! reads all of them with a single register access. This is useful for
+//! bit-banged parallel buses, where every data line must change at the same
+//! time.
+//!
+//! Type-level [`Pin`]s in the same mode are grouped as a tuple with
+//! [`PinGroup::from_pins`], and keep their type:
+//!
+//! ```
+//! let mut data = PinGroup::from_pins((
+//!     pins.pa16.into_push_pull_output(),
+//!     pins.pa17.into_push_pull_output(),
+//!     pins.pa18.into_push_pull_output(),
+//!     pins.pa19.into_push_pull_output(),
+//! ))
+//! .unwrap();
+//! // Values are aligned to the bit positions of the pins within the group
+//! data.write(0b1010 << 16).unwrap();
+//! let (pa16, pa17, pa18, pa19) = data.free();
+//! ```
+//!
+//! A collection of [`DynPin`]s, such as an array, is grouped with
+//! [`PinGroup::new`]. Its pins can also change mode together, e.g. to turn a
+//! bidirectional bus around:
+//!
+//! ```
+//! let mut data = PinGroup::new([
+//!     pins.pa16.into_push_pull_output().into(),
+//!     pins.pa17.into_push_pull_output().into(),
+//!     pins.pa18.into_push_pull_output().into(),
+//!     pins.pa19.into_push_pull_output().into(),
+//! ])
+//! .unwrap();
+//! data.write(0b1010 << 16).unwrap();
+//! data.into_mode(DYN_FLOATING_INPUT);
+//! let bits = data.read().unwrap();
+//! ```
+
+use crate::typelevel::Sealed;
+
+use super::dynpin::*;
+use super::pin::{Pin, PinId, PinMode};
+use super::reg::RegisterInterface;
+
+//==============================================================================
+//  GroupRegisters
+//==============================================================================
+
+/// Provide a register interface for the [`PORT`](crate::pac::PORT) group of a
+/// [`PinGroup`]
+///
+/// Only the mask registers are used through this interface, and only with
+/// the bits of the pins owned by the [`PinGroup`].
+struct GroupRegisters {
+    id: DynPinId,
+}
+
+// [`GroupRegisters`] is only used to write the bits of the pins owned by its
+// [`PinGroup`], so this implementation is safe.
+unsafe impl RegisterInterface for GroupRegisters {
+    #[inline]
+    fn id(&self) -> DynPinId {
+        self.id
+    }
+}
+
+//==============================================================================
+//  TypedPins
+//==============================================================================
+
+/// Tuples of type-level [`Pin`]s in the same mode
+///
+/// This trait is implemented for tuples of one to eight [`Pin`]s, which can
+/// be grouped with [`PinGroup::from_pins`].
+pub trait TypedPins: Sealed {
+    /// Mode shared by the pins
+    type Mode: PinMode;
+    /// IDs of the pins
+    const IDS: &'static [DynPinId];
+}
+
+macro_rules! typed_pins {
+    ($($I:ident),+) => {
+        impl<$($I: PinId,)+ M: PinMode> Sealed for ($(Pin<$I, M>,)+) {}
+
+        impl<$($I: PinId,)+ M: PinMode> TypedPins for ($(Pin<$I, M>,)+) {
+            type Mode = M;
+            const IDS: &'static [DynPinId] = &[$($I::DYN),+];
+        }
+    };
+}
+
+typed_pins!(I0);
+typed_pins!(I0, I1);
+typed_pins!(I0, I1, I2);
+typed_pins!(I0, I1, I2, I3);
+typed_pins!(I0, I1, I2, I3, I4);
+typed_pins!(I0, I1, I2, I3, I4, I5);
+typed_pins!(I0, I1, I2, I3, I4, I5, I6);
+typed_pins!(I0, I1, I2, I3, I4, I5, I6, I7);
+
+//==============================================================================
+//  PinGroup
+//==============================================================================
+
+/// A set of pins in the same [`DynGroup`], accessed together
+///
+/// `P` is either a collection of [`DynPin`]s, such as an array, or a tuple of
+/// [`Pin`]s implementing [`TypedPins`]. Values written to and read from the
+/// group are aligned to the bit positions of the pins within their
+/// [`PORT`](crate::pac::PORT) group. Bits of other pins are ignored on writes
+/// and cleared on reads.
+pub struct PinGroup<P> {
+    pins: P,
+    regs: GroupRegisters,
+    mask: u32,
+    mode: DynPinMode,
+}
+
+/// Compute the mask of a set of pins, if they are all in the same group
+#[inline]
+fn group_mask(ids: impl Iterator<Item = DynPinId>) -> Option<(DynPinId, u32)> {
+    let mut first = None;
+    let mut mask = 0;
+    for id in ids {
+        let first = *first.get_or_insert(id);
+        if id.group != first.group {
+            return None;
+        }
+        mask |= 1 << id.num;
+    }
+    first.map(|id| (id, mask))
+}
+
+impl<P: TypedPins> PinGroup<P> {
+    /// Create a new [`PinGroup`] from a tuple of [`Pin`]s
+    ///
+    /// Returns [`Error::InvalidPinType`] and the pins if they are not all in
+    /// the same group.
+    #[inline]
+    pub fn from_pins(pins: P) -> Result<Self, (Error, P)> {
+        match group_mask(P::IDS.iter().copied()) {
+            Some((id, mask)) => Ok(PinGroup {
+                pins,
+                regs: GroupRegisters { id },
+                mask,
+                mode: P::Mode::DYN,
+            }),
+            None => Err((Error::InvalidPinType, pins)),
+        }
+    }
+}
+
+impl<P> PinGroup<P>
+where
+    P: AsRef<[DynPin]> + AsMut<[DynPin]>,
+{
+    /// Create a new [`PinGroup`] from a collection of [`DynPin`]s
+    ///
+    /// Returns [`Error::InvalidPinType`] and the pins if the collection is
+    /// empty, or if the pins are not all in the same group and mode.
+    #[inline]
+    pub fn new(pins: P) -> Result<Self, (Error, P)> {
+        let mode = match pins.as_ref().first() {
+            Some(pin) => pin.mode(),
+            None => return Err((Error::InvalidPinType, pins)),
+        };
+        if pins.as_ref().iter().any(|pin| pin.mode() != mode) {
+            return Err((Error::InvalidPinType, pins));
+        }
+        match group_mask(pins.as_ref().iter().map(DynPin::id)) {
+            Some((id, mask)) => Ok(PinGroup {
+                pins,
+                regs: GroupRegisters { id },
+                mask,
+                mode,
+            }),
+            None => Err((Error::InvalidPinType, pins)),
+        }
+    }
+
+    /// Convert every pin to the requested [`DynPinMode`], e.g. to turn a
+    /// bidirectional bus around
+    #[inline]
+    pub fn into_mode(&mut self, mode: DynPinMode) {
+        for pin in self.pins.as_mut() {
+            pin.into_mode(mode);
+        }
+        self.mode = mode;
+    }
+}
+
+impl<P> PinGroup<P> {
+    /// Release the pins
+    #[inline]
+    pub fn free(self) -> P {
+        self.pins
+    }
+
+    /// Return the mask of the pins within their group
+    #[inline]
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Return the mode of the pins
+    #[inline]
+    pub fn mode(&self) -> DynPinMode {
+        self.mode
+    }
+
+    #[inline]
+    fn check_output(&self) -> Result<(), Error> {
+        match self.mode {
+            DynPinMode::Output(_) => Ok(()),
+            _ => Err(Error::InvalidPinType),
+        }
+    }
+
+    /// Drive every pin of the group to the corresponding bit of `bits`
+    ///
+    /// All pins change with a single write to the `OUTTGL` register.
+    #[inline]
+    pub fn write(&mut self, bits: u32) -> Result<(), Error> {
+        self.check_output()?;
+        let toggle = (self.regs.read_out_group() ^ bits) & self.mask;
+        self.regs.toggle_mask(toggle);
+        Ok(())
+    }
+
+    /// Set the pins of the group selected by `mask`
+    #[inline]
+    pub fn set_mask(&mut self, mask: u32) -> Result<(), Error> {
+        self.check_output()?;
+        self.regs.set_mask(mask & self.mask);
+        Ok(())
+    }
+
+    /// Clear the pins of the group selected by `mask`
+    #[inline]
+    pub fn clear_mask(&mut self, mask: u32) -> Result<(), Error> {
+        self.check_output()?;
+        self.regs.clear_mask(mask & self.mask);
+        Ok(())
+    }
+
+    /// Toggle the pins of the group selected by `mask`
+    #[inline]
+    pub fn toggle_mask(&mut self, mask: u32) -> Result<(), Error> {
+        self.check_output()?;
+        self.regs.toggle_mask(mask & self.mask);
+        Ok(())
+    }
+
+    /// Read the input level of every pin of the group
+    ///
+    /// The pins must be in an input or readable output mode.
+    #[inline]
+    pub fn read(&self) -> Result<u32, Error> {
+        match self.mode {
+            DynPinMode::Input(_) | DYN_READABLE_OUTPUT => Ok(self.regs.read_group() & self.mask),
+            _ => Err(Error::InvalidPinType),
+        }
+    }
+}
@@ -660,6 +660,84 @@ where
     }
 }
 
+//==============================================================================
+//  Input sampling and PORT events
+//==============================================================================
+
+impl<I, C> Pin<I, Input<C>>
+where
+    I: PinId,
+    C: InputConfig,
+{
+    /// Read whether continuous sampling of the input is enabled
+    #[inline]
+    pub fn get_continuous_sampling(&self) -> bool {
+        self.regs.read_sampling()
+    }
+
+    /// Enable or disable continuous sampling of the input
+    ///
+    /// By default, the input is only sampled on demand, when it is read.
+    /// Continuous sampling removes the synchronization delay from each read,
+    /// at the cost of a higher power consumption. The setting is kept when
+    /// the pin changes mode.
+    #[inline]
+    pub fn set_continuous_sampling(&mut self, enable: bool) {
+        self.regs.write_sampling(enable);
+    }
+}
+
+/// One of the four PORT event inputs
+///
+/// Each input is connected to the `PORT_EVx` user of the event system, and
+/// can drive one pin in each group.
+#[cfg(feature = "min-samd51g")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PortEvent {
+    Ev0 = 0,
+    Ev1,
+    Ev2,
+    Ev3,
+}
+
+/// Action applied to an output pin when it receives a PORT event
+#[cfg(feature = "min-samd51g")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PortEventAction {
+    /// Drive the pin with the level of the event
+    Out = 0,
+    /// Set the pin
+    Set,
+    /// Clear the pin
+    Clear,
+    /// Toggle the pin
+    Toggle,
+}
+
+#[cfg(feature = "min-samd51g")]
+impl<I, C> Pin<I, Output<C>>
+where
+    I: PinId,
+    C: OutputConfig,
+{
+    /// Drive the pin from a PORT event input
+    ///
+    /// Each event input drives a single pin per group. Attaching another pin
+    /// of the same group to `event` detaches this one.
+    #[inline]
+    pub fn enable_event_action(&mut self, event: PortEvent, action: PortEventAction) {
+        self.regs.write_event_action(event as u8, action as u8);
+    }
+
+    /// Detach the pin from a PORT event input
+    ///
+    /// The event input is only disabled if it still drives this pin.
+    #[inline]
+    pub fn disable_event_action(&mut self, event: PortEvent) {
+        self.regs.clear_event_action(event as u8);
+    }
+}
+
 //==============================================================================
 //  PinMode conversions
 //==============================================================================
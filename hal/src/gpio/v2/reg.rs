@@ -6,7 +6,8 @@ use crate::pac::port::{
 
 #[cfg(feature = "min-samd51g")]
 use crate::pac::port::group::{
-    CTRL, DIR, DIRCLR, DIRSET, DIRTGL, IN, OUT, OUTCLR, OUTSET, OUTTGL, PINCFG, PMUX, WRCONFIG,
+    CTRL, DIR, DIRCLR, DIRSET, DIRTGL, EVCTRL, IN, OUT, OUTCLR, OUTSET, OUTTGL, PINCFG, PMUX,
+    WRCONFIG,
 };
 
 use crate::pac::PORT;
@@ -178,7 +179,10 @@ pub(super) struct GROUP {
     in_: IN,
     ctrl: CTRL,
     wrconfig: WRCONFIG,
+    #[cfg(any(feature = "samd11", feature = "samd21"))]
     _padding1: [u8; 4],
+    #[cfg(feature = "min-samd51g")]
+    evctrl: EVCTRL,
     pmux: [PMUX; 16],
     pincfg: [PINCFG; 32],
     _padding2: [u8; 32],
@@ -227,8 +231,8 @@ pub(super) unsafe trait RegisterInterface {
     const GROUPS: *const GROUP = PORT::ptr() as *const _;
 
     #[inline]
-    fn group(&self) -> &GROUP {
-        let offset = match self.id().group {
+    fn group_index(&self) -> usize {
+        match self.id().group {
             DynGroup::A => 0,
             #[cfg(any(feature = "samd21", feature = "min-samd51g"))]
             DynGroup::B => 1,
@@ -236,12 +240,16 @@ pub(super) unsafe trait RegisterInterface {
             DynGroup::C => 2,
             #[cfg(feature = "min-samd51p")]
             DynGroup::D => 3,
-        };
+        }
+    }
+
+    #[inline]
+    fn group(&self) -> &GROUP {
         // Safety: It is safe to create shared references to each PAC register
         // or register block, because all registers are wrapped in
         // `UnsafeCell`s. We should never create unique references to the
         // registers, to prevent any risk of UB.
-        unsafe { &*Self::GROUPS.add(offset) }
+        unsafe { &*Self::GROUPS.add(self.group_index()) }
     }
 
     #[inline]
@@ -367,4 +375,143 @@ pub(super) unsafe trait RegisterInterface {
     fn write_drive_strength(&mut self, bit: bool) {
         self.pincfg().modify(|_, w| w.drvstr().bit(bit));
     }
+
+    /// Set the output of every pin in `mask` with a single write
+    #[inline]
+    fn set_mask(&mut self, mask: u32) {
+        // Safety: OUTSET is a "mask" register, and the caller guarantees that
+        // it controls every pin in the mask
+        unsafe { self.group().outset.write(|w| w.bits(mask)) };
+    }
+
+    /// Clear the output of every pin in `mask` with a single write
+    #[inline]
+    fn clear_mask(&mut self, mask: u32) {
+        // Safety: OUTCLR is a "mask" register, and the caller guarantees that
+        // it controls every pin in the mask
+        unsafe { self.group().outclr.write(|w| w.bits(mask)) };
+    }
+
+    /// Toggle the output of every pin in `mask` with a single write
+    #[inline]
+    fn toggle_mask(&mut self, mask: u32) {
+        // Safety: OUTTGL is a "mask" register, and the caller guarantees that
+        // it controls every pin in the mask
+        unsafe { self.group().outtgl.write(|w| w.bits(mask)) };
+    }
+
+    /// Read the input level of the whole group
+    #[inline]
+    fn read_group(&self) -> u32 {
+        self.group().in_.read().bits()
+    }
+
+    /// Read back the output level of the whole group
+    #[inline]
+    fn read_out_group(&self) -> u32 {
+        self.group().out.read().bits()
+    }
+
+    /// Read whether continuous sampling of the input is enabled
+    #[inline]
+    fn read_sampling(&self) -> bool {
+        sampling(self.group_index(), self.group()) & self.mask_32() != 0
+    }
+
+    /// Enable or disable continuous sampling of the input
+    ///
+    /// The `CTRL` register is shared by every pin in the group, so it is
+    /// updated within a critical section.
+    #[inline]
+    fn write_sampling(&mut self, bit: bool) {
+        let index = self.group_index();
+        let mask = self.mask_32();
+        cortex_m::interrupt::free(|_| {
+            let bits = sampling(index, self.group());
+            let bits = if bit { bits | mask } else { bits & !mask };
+            set_sampling(index, self.group(), bits);
+        });
+    }
+
+    /// Configure PORT event input `channel` to drive this pin with `action`
+    ///
+    /// The `EVCTRL` register is shared by every pin in the group, so it is
+    /// updated within a critical section.
+    #[cfg(feature = "min-samd51g")]
+    #[inline]
+    fn write_event_action(&mut self, channel: u8, action: u8) {
+        let shift = channel * 8;
+        let bits = (self.id().num & 0x1F) as u32 | (action as u32) << 5 | 1 << 7;
+        cortex_m::interrupt::free(|_| {
+            self.group().evctrl.modify(|r, w| {
+                let cleared = r.bits() & !(0xFF << shift);
+                // Safety: Only the byte of this event input is modified
+                unsafe { w.bits(cleared | bits << shift) }
+            });
+        });
+    }
+
+    /// Disable PORT event input `channel`, if it currently drives this pin
+    #[cfg(feature = "min-samd51g")]
+    #[inline]
+    fn clear_event_action(&mut self, channel: u8) {
+        let shift = channel * 8;
+        let pid = (self.id().num & 0x1F) as u32;
+        cortex_m::interrupt::free(|_| {
+            self.group().evctrl.modify(|r, w| {
+                let byte = (r.bits() >> shift) & 0xFF;
+                let bits = if byte & 0x1F == pid {
+                    r.bits() & !(1 << (shift + 7))
+                } else {
+                    r.bits()
+                };
+                // Safety: Only the PORTEI bit of this event input is cleared
+                unsafe { w.bits(bits) }
+            });
+        });
+    }
+}
+
+/// Read the `CTRL.SAMPLING` bits of a group
+#[cfg(feature = "min-samd51g")]
+#[inline]
+fn sampling(_index: usize, group: &GROUP) -> u32 {
+    group.ctrl.read().sampling().bits()
+}
+
+/// Write the `CTRL.SAMPLING` bits of a group
+#[cfg(feature = "min-samd51g")]
+#[inline]
+fn set_sampling(_index: usize, group: &GROUP, bits: u32) {
+    // Safety: Every bit pattern is valid
+    group.ctrl.write(|w| unsafe { w.sampling().bits(bits) });
+}
+
+/// Shadow copy of the `CTRL.SAMPLING` bits of each group, because the
+/// register is write-only on SAMD11 and SAMD21 devices
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+static mut SAMPLING: [u32; 2] = [0; 2];
+
+/// Read the `CTRL.SAMPLING` bits of a group
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+#[inline]
+fn sampling(index: usize, _group: &GROUP) -> u32 {
+    // Safety: The shadow copy is only written within a critical section
+    cortex_m::interrupt::free(|_| unsafe {
+        let shadow = core::ptr::addr_of!(SAMPLING) as *const u32;
+        core::ptr::read_volatile(shadow.add(index))
+    })
+}
+
+/// Write the `CTRL.SAMPLING` bits of a group
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+#[inline]
+fn set_sampling(index: usize, group: &GROUP, bits: u32) {
+    // Safety: Called from within a critical section. Every bit pattern is
+    // valid.
+    unsafe {
+        let shadow = core::ptr::addr_of_mut!(SAMPLING) as *mut u32;
+        core::ptr::write_volatile(shadow.add(index), bits);
+        group.ctrl.write(|w| w.sampling().bits(bits));
+    }
 }
@@ -96,12 +96,15 @@ macro_rules! dbgprint {
     ($($arg:tt)*) => {{}};
 }
 
+#[cfg(feature = "device")]
+pub mod crash_record;
 #[cfg(feature = "device")]
 pub mod delay;
 #[cfg(feature = "device")]
 pub mod gpio;
 #[cfg(feature = "device")]
 pub mod prelude;
+pub mod reclock;
 #[cfg(feature = "device")]
 pub mod rtc;
 #[cfg(feature = "device")]
@@ -116,12 +119,17 @@ pub mod timer_traits;
 #[cfg(all(feature = "unproven", feature = "dma"))]
 pub mod dmac;
 
-#[cfg(all(feature = "usb", feature = "samd11"))]
-compile_error!("'usb' is enabled, but USB isn't supported on SAMD11");
+#[cfg(all(feature = "usb", feature = "samd11c"))]
+compile_error!("'usb' is enabled, but USB isn't supported on SAMD11C");
 
 #[cfg(all(
     feature = "usb",
-    not(any(feature = "samd21", feature = "min-samd51g", feature = "library"))
+    not(any(
+        feature = "samd11d",
+        feature = "samd21",
+        feature = "min-samd51g",
+        feature = "library"
+    ))
 ))]
 compile_error!("The 'usb' feature is enabled, but not a chip with USB support");
 
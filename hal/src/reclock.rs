@@ -0,0 +1,34 @@
+//! Notification of clock frequency changes
+//!
+//! Drivers compute their baud rates, prescalers and delay loops from the
+//! frequency of their clock when they are configured. When that frequency is
+//! changed at run-time, for example by switching the CPU clock with
+//! [`GenericClockController::set_gclk0`], every driver clocked from the
+//! affected generator must be told about the new frequency. Drivers that can
+//! recompute their settings implement the [`Reclock`] trait.
+//!
+//! ```
+//! let gclk0 = clocks.set_gclk0(ClockSource::DFLL, 1, &mut peripherals.NVMCTRL).unwrap();
+//! let freq: Hertz = gclk0.into();
+//! delay.clock_changed(freq);
+//! spi.reconfigure(|c| c.clock_changed(freq));
+//! ```
+//!
+//! Drivers are not notified automatically. It is up to the caller to keep
+//! track of which drivers share a clock generator.
+//!
+//! [`GenericClockController::set_gclk0`]: crate::clock::GenericClockController::set_gclk0
+
+use crate::time::Hertz;
+
+/// A driver that can adapt to a change of its clock frequency
+pub trait Reclock {
+    /// Inform the driver that its clock now runs at `freq`
+    ///
+    /// Implementations recompute any register setting that depends on the
+    /// clock frequency, so that the driver keeps its configured baud rate,
+    /// period or delay. Drivers that must be disabled to change these
+    /// settings, like the SERCOM drivers, implement this trait on their
+    /// disabled configuration type.
+    fn clock_changed(&mut self, freq: Hertz);
+}
@@ -309,6 +309,7 @@ use bitflags::bitflags;
 use embedded_hal::spi;
 pub use embedded_hal::spi::{Phase, Polarity, MODE_0, MODE_1, MODE_2, MODE_3};
 
+use crate::reclock::Reclock;
 use crate::sercom::v2::*;
 use crate::time::Hertz;
 use crate::typelevel::{Is, NoneT, Sealed};
@@ -988,6 +989,24 @@ where
     }
 }
 
+impl<P, M, Z> Reclock for Config<P, M, Z>
+where
+    P: ValidPads,
+    M: OpMode,
+    Z: Size,
+{
+    /// Update the stored GCLK frequency, and recompute the BAUD register to
+    /// keep the current baud rate
+    ///
+    /// Use [`Spi::reconfigure`] to call this method on an enabled [`Spi`].
+    #[inline]
+    fn clock_changed(&mut self, freq: Hertz) {
+        let baud = self.regs.get_baud(self.freq);
+        self.freq = freq;
+        self.regs.set_baud(freq, baud);
+    }
+}
+
 //=============================================================================
 // AnyConfig
 //=============================================================================
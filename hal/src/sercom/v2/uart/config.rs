@@ -6,6 +6,7 @@ use super::{
 };
 use crate::{
     pac,
+    reclock::Reclock,
     sercom::v2::*,
     time::Hertz,
     typelevel::{Is, Sealed},
@@ -46,6 +47,7 @@ where
     pads: P,
     chsize: PhantomData<C>,
     freq: Hertz,
+    baud: Option<(Hertz, BaudMode)>,
 }
 
 /// Clock type needed to create a new [`Config`]. [`PM`](pac::PM) for thumbv6m
@@ -96,6 +98,7 @@ impl<P: ValidPads> Config<P> {
             pads,
             chsize: PhantomData,
             freq: freq.into(),
+            baud: None,
         }
     }
 }
@@ -116,6 +119,7 @@ where
             pads: self.pads,
             chsize: PhantomData,
             freq: self.freq,
+            baud: self.baud,
         }
     }
 
@@ -283,6 +287,8 @@ where
     /// Note that 3x oversampling is not supported.
     #[inline]
     pub fn set_baud<B: Into<Hertz>>(&mut self, baud: B, mode: BaudMode) {
+        let baud = baud.into();
+        self.baud = Some((baud, mode));
         self.registers.set_baud(self.freq, baud, mode);
     }
 
@@ -410,6 +416,24 @@ where
     }
 }
 
+impl<P, C> Reclock for Config<P, C>
+where
+    P: ValidPads,
+    C: CharSize,
+{
+    /// Update the stored GCLK frequency, and recompute the `BAUD` register
+    /// for the last baud rate set with [`set_baud`](Config::set_baud)
+    ///
+    /// Use [`Uart::reconfigure`] to call this method on an enabled [`Uart`].
+    #[inline]
+    fn clock_changed(&mut self, freq: Hertz) {
+        self.freq = freq;
+        if let Some((baud, mode)) = self.baud {
+            self.registers.set_baud(freq, baud, mode);
+        }
+    }
+}
+
 //=============================================================================
 // AnyConfig
 //=============================================================================
@@ -4,7 +4,9 @@ use crate::clock::GenericClockController;
 use crate::gpio::v1;
 use crate::gpio::v2::*;
 use crate::hal::adc::{Channel, OneShot};
-use crate::pac::{adc, ADC, PM};
+use crate::pac::{adc, ADC, PM, SYSCTRL};
+
+use super::calibration;
 
 /// Samples per reading
 pub use adc::avgctrl::SAMPLENUM_A as SampleRate;
@@ -25,6 +27,9 @@ pub struct Adc<ADC> {
     adc: ADC,
 }
 
+#[cfg(feature = "dma")]
+pub mod dma;
+
 impl Adc<ADC> {
     /// Create a new `Adc` instance. The default configuration is:
     /// * 1/32 prescaler
@@ -123,6 +128,86 @@ impl Adc<ADC> {
         while self.adc.status.read().syncbusy().bit_is_set() {}
     }
 
+    /// Perform a differential conversion of `pos` relative to `neg`.
+    ///
+    /// The result is a signed, two's complement value that is negative
+    /// whenever the voltage on `neg` exceeds the voltage on `pos`.
+    pub fn read_differential<P, N>(&mut self, _pos: &mut P, _neg: &mut N) -> i16
+    where
+        P: Channel<ADC, ID = u8>,
+        N: NegativeChannel<ADC>,
+    {
+        let pos = P::channel();
+        let neg = N::channel();
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.adc.inputctrl.modify(|_, w| unsafe {
+            w.muxpos().bits(pos);
+            w.muxneg().bits(neg)
+        });
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.ctrlb.modify(|_, w| w.diffmode().set_bit());
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.power_up();
+        let result = self.convert();
+        self.power_down();
+
+        // Go back to single-ended mode with the negative input grounded
+        self.adc.ctrlb.modify(|_, w| w.diffmode().clear_bit());
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.inputctrl.modify(|_, w| w.muxneg().gnd());
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        result as i16
+    }
+
+    /// Read the CPU temperature in degrees Celsius.
+    ///
+    /// The temperature sensor is sampled against the internal 1V reference,
+    /// and the result is corrected using the factory values stored in the NVM
+    /// temperature log row, following the two-pass method from Atmel
+    /// application note AT11481. The reference, gain, resolution and
+    /// averaging settings of the ADC are restored afterwards.
+    pub fn read_cpu_temperature(&mut self, sysctrl: &mut SYSCTRL) -> f32 {
+        let refctrl = self.adc.refctrl.read().bits();
+        let inputctrl = self.adc.inputctrl.read().bits();
+        let ctrlb = self.adc.ctrlb.read().bits();
+        let avgctrl = self.adc.avgctrl.read().bits();
+
+        sysctrl.vref.modify(|_, w| w.tsen().set_bit());
+        self.reference(Reference::INT1V);
+        self.gain(Gain::_1X);
+        self.resolution(Resolution::_12BIT);
+        self.samples(SampleRate::_1);
+
+        let result = self.read_channel(TEMPERATURE_CHANNEL);
+
+        self.adc.avgctrl.write(|w| unsafe { w.bits(avgctrl) });
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.ctrlb.write(|w| unsafe { w.bits(ctrlb) });
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.inputctrl.write(|w| unsafe { w.bits(inputctrl) });
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.refctrl.write(|w| unsafe { w.bits(refctrl) });
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        temperature_from_adc(result)
+    }
+
+    fn read_channel(&mut self, chan: u8) -> u16 {
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.adc
+            .inputctrl
+            .modify(|_, w| unsafe { w.muxpos().bits(chan) });
+        self.power_up();
+        let result = self.convert();
+        self.power_down();
+
+        result
+    }
+
     fn convert(&mut self) -> u16 {
         self.adc.swtrig.modify(|_, w| w.start().set_bit());
         while self.adc.intflag.read().resrdy().bit_is_clear() {}
@@ -149,20 +234,300 @@ where
     type Error = ();
 
     fn read(&mut self, _pin: &mut PIN) -> nb::Result<WORD, Self::Error> {
-        let chan = PIN::channel();
+        Ok(self.read_channel(PIN::channel()).into())
+    }
+}
+
+/// A set of ADC inputs with consecutive channel numbers, converted one after
+/// the other by an input scan.
+///
+/// This trait is implemented for tuples of 2 to 16 [`Channel`]s. The
+/// channels must be listed in increasing, consecutive order (e.g. the pins
+/// for AIN4, AIN5, AIN6 and AIN7).
+pub trait ScanChannels<const N: usize> {
+    /// Channel numbers of the inputs, in scan order
+    fn channels() -> [u8; N];
+}
+
+macro_rules! scan_channels {
+    (
+        $(
+            $N:literal: ($($C:ident),+)
+        ),+
+    ) => {
+        $(
+            impl<$($C),+> ScanChannels<$N> for ($($C,)+)
+            where
+                $($C: Channel<ADC, ID = u8>),+
+            {
+                fn channels() -> [u8; $N] {
+                    [$($C::channel()),+]
+                }
+            }
+        )+
+    }
+}
+
+scan_channels! {
+    2: (C0, C1),
+    3: (C0, C1, C2),
+    4: (C0, C1, C2, C3),
+    5: (C0, C1, C2, C3, C4),
+    6: (C0, C1, C2, C3, C4, C5),
+    7: (C0, C1, C2, C3, C4, C5, C6),
+    8: (C0, C1, C2, C3, C4, C5, C6, C7),
+    9: (C0, C1, C2, C3, C4, C5, C6, C7, C8),
+    10: (C0, C1, C2, C3, C4, C5, C6, C7, C8, C9),
+    11: (C0, C1, C2, C3, C4, C5, C6, C7, C8, C9, C10),
+    12: (C0, C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11),
+    13: (C0, C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12),
+    14: (C0, C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12, C13),
+    15: (C0, C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12, C13, C14),
+    16: (C0, C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12, C13, C14, C15)
+}
+
+/// An ADC converting `N` consecutive inputs per sweep, using the input scan
+/// feature (`INPUTCTRL.INPUTSCAN`/`INPUTOFFSET`).
+///
+/// Sweeps can be performed in a blocking fashion with [`read`](Self::read),
+/// driven by the `ADC` interrupt with [`start`](Self::start) and
+/// [`service_interrupt_ready`](Self::service_interrupt_ready), or with DMA
+/// (see the `dma` module, available with the `dma` feature).
+///
+/// The scanned pins are held by the `ScanAdc`, so that they stay in analog
+/// mode, and are given back by [`free`](Self::free).
+///
+/// ```
+/// let mut scan = adc.into_scan((a0, a1, a2, a3, a4, a5, a6, a7));
+/// let results: [u16; 8] = scan.read();
+/// let (adc, (a0, a1, a2, a3, a4, a5, a6, a7)) = scan.free();
+/// ```
+pub struct ScanAdc<S, const N: usize> {
+    adc: Adc<ADC>,
+    channels: S,
+    results: [u16; N],
+    next: usize,
+}
+
+impl Adc<ADC> {
+    /// Configure the ADC to scan the inputs of `pins`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channels are not consecutive.
+    pub fn into_scan<S, const N: usize>(mut self, pins: S) -> ScanAdc<S, N>
+    where
+        S: ScanChannels<N>,
+    {
+        let channels = S::channels();
+        for (offset, chan) in channels.iter().enumerate() {
+            assert!(
+                *chan as usize == channels[0] as usize + offset,
+                "scanned ADC channels must be consecutive"
+            );
+        }
+
         while self.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.inputctrl.modify(|_, w| unsafe {
+            w.muxpos().bits(channels[0]);
+            w.inputscan().bits(N as u8 - 1);
+            w.inputoffset().bits(0)
+        });
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+        // Discard the first conversion, since the first conversion after the
+        // reference is changed must not be used.
+        self.power_up();
+        self.adc.swtrig.modify(|_, w| w.start().set_bit());
+        while self.adc.intflag.read().resrdy().bit_is_clear() {}
+        self.adc.intflag.write(|w| w.resrdy().set_bit());
+        self.power_down();
+
+        ScanAdc {
+            adc: self,
+            channels: pins,
+            results: [0; N],
+            next: 0,
+        }
+    }
+}
 
+impl<S, const N: usize> ScanAdc<S, N> {
+    /// Perform one sweep over the scanned inputs, blocking until every input
+    /// has been converted.
+    pub fn read(&mut self) -> [u16; N] {
+        self.restart();
+        self.adc.power_up();
+        for result in self.results.iter_mut() {
+            self.adc.adc.swtrig.modify(|_, w| w.start().set_bit());
+            while self.adc.adc.intflag.read().resrdy().bit_is_clear() {}
+            self.adc.adc.intflag.write(|w| w.resrdy().set_bit());
+            *result = self.adc.adc.result.read().result().bits();
+        }
+        self.adc.power_down();
+
+        self.results
+    }
+
+    /// Start a sweep over the scanned inputs, with the `ADC` interrupt
+    /// signalling the completion of each conversion.
+    ///
+    /// [`service_interrupt_ready`](Self::service_interrupt_ready) must be
+    /// called from the interrupt handler; it starts the following conversion
+    /// and returns the results once the sweep is done.
+    pub fn start(&mut self) {
+        self.restart();
+        self.adc.adc.intflag.write(|w| w.resrdy().set_bit());
+        self.adc.adc.intenset.write(|w| w.resrdy().set_bit());
+        self.adc.power_up();
+        self.adc.adc.swtrig.modify(|_, w| w.start().set_bit());
+    }
+
+    /// Service the `ADC` interrupt during a sweep started with
+    /// [`start`](Self::start).
+    ///
+    /// Returns the results once every input has been converted, after which
+    /// the ADC is powered down and its interrupt disabled.
+    pub fn service_interrupt_ready(&mut self) -> Option<[u16; N]> {
+        if self.adc.adc.intflag.read().resrdy().bit_is_clear() {
+            return None;
+        }
+        self.adc.adc.intflag.write(|w| w.resrdy().set_bit());
+        self.results[self.next] = self.adc.adc.result.read().result().bits();
+        self.next += 1;
+
+        if self.next < N {
+            self.adc.adc.swtrig.modify(|_, w| w.start().set_bit());
+            None
+        } else {
+            self.adc.adc.intenclr.write(|w| w.resrdy().set_bit());
+            self.adc.power_down();
+            Some(self.results)
+        }
+    }
+
+    /// Stop scanning, and return the [`Adc`] configured for single
+    /// conversions, along with the scanned pins.
+    pub fn free(mut self) -> (Adc<ADC>, S) {
+        self.adc.adc.intenclr.write(|w| w.resrdy().set_bit());
+        self.adc.power_down();
+        self.adc.adc.ctrlb.modify(|_, w| w.freerun().clear_bit());
+        while self.adc.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.adc.inputctrl.modify(|_, w| unsafe {
+            w.inputscan().bits(0);
+            w.inputoffset().bits(0)
+        });
+        while self.adc.adc.status.read().syncbusy().bit_is_set() {}
+        (self.adc, self.channels)
+    }
+
+    /// Rewind the scan to its first input
+    fn restart(&mut self) {
+        self.next = 0;
+        while self.adc.adc.status.read().syncbusy().bit_is_set() {}
         self.adc
+            .adc
             .inputctrl
-            .modify(|_, w| unsafe { w.muxpos().bits(chan) });
-        self.power_up();
-        let result = self.convert();
-        self.power_down();
+            .modify(|_, w| unsafe { w.inputoffset().bits(0) });
+        while self.adc.adc.status.read().syncbusy().bit_is_set() {}
+    }
+}
+
+/// Mux input of the internal temperature sensor
+const TEMPERATURE_CHANNEL: u8 = 0x18;
+
+/// Convert a 12-bit reading of the temperature sensor, taken with the 1V
+/// reference and unity gain, to a temperature in degrees Celsius.
+fn temperature_from_adc(result: u16) -> f32 {
+    let room_temp = calibration::room_temp_val_int() as f32
+        + decimal_to_fraction(calibration::room_temp_val_dec());
+    let hot_temp = calibration::hot_temp_val_int() as f32
+        + decimal_to_fraction(calibration::hot_temp_val_dec());
+    let room_int1v = 1.0 - calibration::room_int1v_val() as f32 / 1000.0;
+    let hot_int1v = 1.0 - calibration::hot_int1v_val() as f32 / 1000.0;
+    let room_vadc = calibration::room_adc_val() as f32 * room_int1v / 4095.0;
+    let hot_vadc = calibration::hot_adc_val() as f32 * hot_int1v / 4095.0;
+    let slope = (hot_temp - room_temp) / (hot_vadc - room_vadc);
+
+    // First pass: assume an ideal 1V reference
+    let vadc = result as f32 / 4095.0;
+    let coarse_temp = room_temp + slope * (vadc - room_vadc);
+
+    // Second pass: interpolate the actual reference voltage at the coarse
+    // temperature, and use it to correct the reading
+    let int1v =
+        room_int1v + (hot_int1v - room_int1v) * (coarse_temp - room_temp) / (hot_temp - room_temp);
+    let vadc = result as f32 * int1v / 4095.0;
+    room_temp + slope * (vadc - room_vadc)
+}
+
+/// The temperature log row stores the decimal part of a temperature as the
+/// digits following the decimal point.
+fn decimal_to_fraction(val: u8) -> f32 {
+    let val = val as f32;
+    if val < 10.0 {
+        val / 10.0
+    } else {
+        val / 100.0
+    }
+}
+
+/// Marker trait for [`Channel`]s that can also be connected to the negative
+/// input of the ADC for differential conversions.
+///
+/// Only the analog inputs AIN0 to AIN7 are available on the negative input
+/// multiplexer.
+pub trait NegativeChannel<ADC>: Channel<ADC, ID = u8> {}
+
+macro_rules! adc_negative_pins {
+    (
+        $(
+            $PinId:ident
+        ),+
+    ) => {
+        $(
+            impl NegativeChannel<ADC> for Pin<$PinId, AlternateB> {}
+        )+
+    }
+}
 
-        Ok(result.into())
+macro_rules! adc_internal_channels {
+    (
+        $(
+            $(#[$attr:meta])*
+            $Name:ident: $CHAN:expr
+        ),+
+    ) => {
+        $(
+            $(#[$attr])*
+            pub struct $Name;
+
+            impl Channel<ADC> for $Name {
+                type ID = u8;
+                fn channel() -> u8 { $CHAN }
+            }
+        )+
     }
 }
 
+adc_internal_channels! {
+    /// Uncalibrated temperature sensor
+    ///
+    /// `SYSCTRL.VREF.TSEN` must be set for the sensor to be powered. See
+    /// [`Adc::read_cpu_temperature`] for a calibrated reading.
+    Temperature: TEMPERATURE_CHANNEL,
+    /// Internal bandgap voltage
+    ///
+    /// `SYSCTRL.VREF.BGOUTEN` must be set for the bandgap to be routed to
+    /// the ADC.
+    Bandgap: 0x19,
+    /// Core supply voltage (VDDCORE), scaled by 1/4
+    ScaledCoreVcc: 0x1A,
+    /// I/O supply voltage (VDDIO), scaled by 1/4
+    ScaledIoVcc: 0x1B,
+    /// DAC output
+    DacOutput: 0x1C
+}
+
 macro_rules! adc_pins {
     (
         $(
@@ -192,6 +557,16 @@ where
     }
 }
 
+/// Implement [`NegativeChannel`] for [`v1::Pin`]s based on the
+/// implementations for `v2` [`Pin`]s
+#[allow(deprecated)]
+impl<I> NegativeChannel<ADC> for v1::Pin<I, v1::PfB>
+where
+    I: PinId,
+    Pin<I, AlternateB>: NegativeChannel<ADC>,
+{
+}
+
 #[cfg(feature = "samd11")]
 adc_pins! {
     PA02: 0,
@@ -232,3 +607,28 @@ adc_pins! {
     PB06: 14,
     PB07: 15
 }
+
+#[cfg(feature = "samd11")]
+adc_negative_pins! {
+    PA02,
+    PA04,
+    PA05,
+    PA14,
+    PA15
+}
+
+#[cfg(feature = "samd21")]
+adc_negative_pins! {
+    PA02,
+    PA03,
+    PA04,
+    PA05,
+    PA06,
+    PA07
+}
+
+#[cfg(feature = "min-samd21g")]
+adc_negative_pins! {
+    PB08,
+    PB09
+}
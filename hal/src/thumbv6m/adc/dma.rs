@@ -0,0 +1,120 @@
+//! Use the DMA Controller to transfer ADC conversion results into memory
+//!
+//! [`Adc`] and [`ScanAdc`] implement [`Buffer`], so their `RESULT` register
+//! can be used as the source of a DMA [`Transfer`].
+//!
+//! [`ScanAdc::read_with_dma`] runs the ADC in free-running mode and lets the
+//! DMA collect every result of the scan, so complete sweeps are captured
+//! without any CPU involvement:
+//!
+//! ```
+//! let results: &'static mut [u16; 8] = cortex_m::singleton!(: [u16; 8] = [0; 8]).unwrap();
+//!
+//! let scan = adc.into_scan((a0, a1, a2, a3, a4, a5, a6, a7));
+//! let xfer = scan.read_with_dma(results, chan0, false, |_| {});
+//! let (chan0, mut scan, results) = xfer.wait();
+//! scan.stop_dma();
+//! ```
+
+use super::{Adc, ScanAdc};
+use crate::{
+    dmac::{
+        self,
+        channel::{AnyChannel, Busy, CallbackStatus, Channel, InterruptFlags, Ready},
+        transfer::BufferPair,
+        Buffer, Transfer, TriggerAction, TriggerSource,
+    },
+    pac::ADC,
+};
+
+/// DMA trigger source raised when a conversion result is ready
+pub const DMA_RESULT_TRIGGER: TriggerSource = TriggerSource::ADC_RESRDY;
+
+unsafe impl Buffer for Adc<ADC> {
+    type Beat = u16;
+
+    #[inline]
+    fn dma_ptr(&mut self) -> *mut Self::Beat {
+        self.adc.result.as_ptr() as *mut _
+    }
+
+    #[inline]
+    fn incrementing(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        1
+    }
+}
+
+unsafe impl<S, const N: usize> Buffer for ScanAdc<S, N> {
+    type Beat = u16;
+
+    #[inline]
+    fn dma_ptr(&mut self) -> *mut Self::Beat {
+        self.adc.dma_ptr()
+    }
+
+    #[inline]
+    fn incrementing(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        1
+    }
+}
+
+impl<S, const N: usize> ScanAdc<S, N> {
+    /// Start scanning the inputs continuously, and transform the [`ScanAdc`]
+    /// into a DMA [`Transfer`] writing each result into `buf`.
+    ///
+    /// `buf` should hold a whole number of sweeps (i.e. its length should be
+    /// a multiple of `N`), in which case results are stored in scan order. If
+    /// `circular` is `true`, the transfer wraps around to the start of `buf`
+    /// once it is full, and keeps running until it is stopped. The
+    /// `ScanAdc` is recovered with [`Transfer::wait`] or [`Transfer::stop`],
+    /// after which [`stop_dma`](Self::stop_dma) must be called.
+    #[inline]
+    pub fn read_with_dma<Ch, B, W>(
+        mut self,
+        buf: B,
+        mut channel: Ch,
+        circular: bool,
+        waker: W,
+    ) -> Transfer<Channel<Ch::Id, Busy>, BufferPair<Self, B>, W>
+    where
+        Ch: AnyChannel<Status = Ready>,
+        B: Buffer<Beat = u16> + 'static,
+        W: FnOnce(CallbackStatus) + 'static,
+    {
+        channel
+            .as_mut()
+            .enable_interrupts(InterruptFlags::new().with_tcmpl(true));
+
+        self.restart();
+        self.adc.adc.ctrlb.modify(|_, w| w.freerun().set_bit());
+        while self.adc.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc.power_up();
+        self.adc.adc.swtrig.modify(|_, w| w.start().set_bit());
+
+        // SAFETY: We use new_unchecked to avoid having to pass a 'static self as the
+        // source buffer. This is safe as long as we guarantee the destination
+        // buffer is static.
+        unsafe { dmac::Transfer::new_unchecked(channel, self, buf, circular) }
+            .with_waker(waker)
+            .begin(DMA_RESULT_TRIGGER, TriggerAction::BEAT)
+    }
+
+    /// Stop the free-running conversions started by
+    /// [`read_with_dma`](Self::read_with_dma) and power down the ADC.
+    #[inline]
+    pub fn stop_dma(&mut self) {
+        self.adc.power_down();
+        self.adc.adc.ctrlb.modify(|_, w| w.freerun().clear_bit());
+        while self.adc.adc.status.read().syncbusy().bit_is_set() {}
+    }
+}
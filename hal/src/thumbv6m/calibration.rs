@@ -1,6 +1,8 @@
 //! NVM Software Calibration Area Mapping
 // For samd11, see 9.5 NVM Software Calibration Area Mapping, page 24
 // For samd21, see 10.3.2 NVM Software Calibration Area Mapping, page 46
+// The temperature log row (samd11: 9.6, samd21: 10.3.3) immediately follows
+// the calibration area, at 0x806030.
 
 use core::ptr;
 
@@ -59,3 +61,49 @@ pub fn usb_trim_cal() -> u8 {
     #[cfg(feature = "samd21")]
     return cal_with_errata(4, 23, 7, 7, 3) as u8;
 }
+
+/// Integer part of the room temperature (°C) at which the temperature log
+/// row was recorded.
+pub fn room_temp_val_int() -> u8 {
+    cal(0x10, 0, 0xff) as u8
+}
+
+/// Decimal part of the room temperature at which the temperature log row was
+/// recorded.
+pub fn room_temp_val_dec() -> u8 {
+    cal(0x10, 8, 0xf) as u8
+}
+
+/// Integer part of the hot temperature (°C) at which the temperature log row
+/// was recorded.
+pub fn hot_temp_val_int() -> u8 {
+    cal(0x10, 12, 0xff) as u8
+}
+
+/// Decimal part of the hot temperature at which the temperature log row was
+/// recorded.
+pub fn hot_temp_val_dec() -> u8 {
+    cal(0x10, 20, 0xf) as u8
+}
+
+/// Deviation of the 1V reference from its ideal value at room temperature,
+/// in mV.
+pub fn room_int1v_val() -> i8 {
+    cal(0x10, 24, 0xff) as u8 as i8
+}
+
+/// Deviation of the 1V reference from its ideal value at hot temperature, in
+/// mV.
+pub fn hot_int1v_val() -> i8 {
+    cal(0x14, 0, 0xff) as u8 as i8
+}
+
+/// 12-bit ADC conversion of the temperature sensor at room temperature.
+pub fn room_adc_val() -> u16 {
+    cal(0x14, 8, 0xfff) as u16
+}
+
+/// 12-bit ADC conversion of the temperature sensor at hot temperature.
+pub fn hot_adc_val() -> u16 {
+    cal(0x14, 20, 0xfff) as u16
+}
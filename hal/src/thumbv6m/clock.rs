@@ -6,12 +6,14 @@
 use crate::pac::gclk::clkctrl::GEN_A::*;
 use crate::pac::gclk::clkctrl::ID_A::*;
 use crate::pac::gclk::genctrl::SRC_A::*;
+use crate::pac::sysctrl::dpllctrlb::REFCLK_A;
 use crate::pac::{self, GCLK, NVMCTRL, PM, SYSCTRL};
-use crate::time::{Hertz, U32Ext};
+use crate::time::Hertz;
 
 pub type ClockId = pac::gclk::clkctrl::ID_A;
 pub type ClockGenId = pac::gclk::clkctrl::GEN_A;
 pub type ClockSource = pac::gclk::genctrl::SRC_A;
+pub type DpllLockTimeout = pac::sysctrl::dpllctrlb::LTIME_A;
 
 /// Represents a configured clock generator.
 /// Can be converted into the effective clock frequency.
@@ -30,6 +32,171 @@ impl Into<Hertz> for GClock {
     }
 }
 
+/// Configuration of the external multipurpose oscillator (XOSC), to be
+/// passed to `GenericClockController::configure_xosc`.
+///
+/// The oscillator either drives a crystal connected between the XIN and XOUT
+/// pins, or takes an external clock signal on the XIN pin.
+#[derive(Clone, Copy, Debug)]
+pub struct XoscConfig {
+    freq: Hertz,
+    crystal: bool,
+    startup: u8,
+    amplitude_control: bool,
+    on_demand: bool,
+    run_standby: bool,
+}
+
+impl XoscConfig {
+    /// Configuration for a crystal of frequency `freq` (0.4 to 32MHz)
+    /// connected between XIN and XOUT.
+    pub fn crystal(freq: impl Into<Hertz>) -> Self {
+        let freq = freq.into();
+        if freq.0 < 400_000 || freq.0 > 32_000_000 {
+            panic!("invalid crystal frequency {}", freq.0);
+        }
+        Self::new(freq, true)
+    }
+
+    /// Configuration for an external clock signal of frequency `freq` (up
+    /// to 32MHz) applied to XIN. XOUT is free to be used as a GPIO.
+    pub fn external_clock(freq: impl Into<Hertz>) -> Self {
+        let freq = freq.into();
+        if freq.0 == 0 || freq.0 > 32_000_000 {
+            panic!("invalid external clock frequency {}", freq.0);
+        }
+        Self::new(freq, false)
+    }
+
+    fn new(freq: Hertz, crystal: bool) -> Self {
+        Self {
+            freq,
+            crystal,
+            startup: 10,
+            amplitude_control: false,
+            on_demand: false,
+            run_standby: false,
+        }
+    }
+
+    /// Set the start-up time to `2^startup` OSCULP32K cycles (`startup` is
+    /// at most 15). The oscillator is not reported ready until it has
+    /// elapsed.
+    pub fn startup(mut self, startup: u8) -> Self {
+        if startup > 15 {
+            panic!("invalid startup time {}", startup);
+        }
+        self.startup = startup;
+        self
+    }
+
+    /// Enable the automatic amplitude gain control (AMPGC), which reduces
+    /// the amplitude of the crystal oscillation (and thus the power
+    /// consumption) once it has started up.
+    pub fn amplitude_control(mut self, enable: bool) -> Self {
+        self.amplitude_control = enable;
+        self
+    }
+
+    /// Only run the oscillator when a peripheral or clock generator
+    /// requests it.
+    pub fn on_demand(mut self, enable: bool) -> Self {
+        self.on_demand = enable;
+        self
+    }
+
+    /// Keep the oscillator running in standby sleep mode.
+    pub fn run_standby(mut self, enable: bool) -> Self {
+        self.run_standby = enable;
+        self
+    }
+
+    /// Returns the recommended `GAIN` value for the crystal frequency
+    fn gain(&self) -> u8 {
+        match self.freq.0 {
+            0..=2_000_000 => 0,
+            2_000_001..=4_000_000 => 1,
+            4_000_001..=8_000_000 => 2,
+            8_000_001..=16_000_000 => 3,
+            _ => 4,
+        }
+    }
+}
+
+/// Reference clock of the FDPLL96M fractional digital phase-locked loop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DpllReference {
+    /// The external 32kHz crystal oscillator, which must be running
+    Xosc32k,
+    /// The external oscillator configured with
+    /// `GenericClockController::configure_xosc`, divided by `2 * (div + 1)`
+    Xosc {
+        /// The 11-bit divider applied to the oscillator frequency
+        div: u16,
+    },
+    /// A configured clock generator, connected to the DPLL through the
+    /// FDPLL peripheral channel
+    Gclk(ClockGenId),
+}
+
+/// Configuration of the FDPLL96M, to be passed to
+/// `GenericClockController::configure_dpll96m`.
+///
+/// The output frequency is `fref * (ldr + 1 + ldrfrac / 16)`, where `fref`
+/// is the frequency of the reference clock (after division, for the XOSC
+/// reference). `fref` must be between 32kHz and 2MHz, and the output
+/// frequency between 48MHz and 96MHz.
+#[derive(Clone, Copy, Debug)]
+pub struct DpllConfig {
+    reference: DpllReference,
+    ldr: u16,
+    ldrfrac: u8,
+    lock_bypass: bool,
+    lock_timeout: Option<DpllLockTimeout>,
+}
+
+impl DpllConfig {
+    /// Configuration for the DPLL, with the given reference clock and
+    /// integer (`ldr`, 12 bits) and fractional (`ldrfrac`, 4 bits) parts of
+    /// the loop divider ratio
+    pub fn new(reference: DpllReference, ldr: u16, ldrfrac: u8) -> Self {
+        if ldr >= 2_u16.pow(12) || ldrfrac >= 16 {
+            panic!("invalid DPLL ratio {}.{}", ldr, ldrfrac);
+        }
+        Self {
+            reference,
+            ldr,
+            ldrfrac,
+            lock_bypass: false,
+            lock_timeout: None,
+        }
+    }
+
+    /// Always output the DPLL clock, regardless of the lock status. The
+    /// clock is then usable as soon as it is ready, but its frequency may
+    /// not have settled.
+    pub fn lock_bypass(mut self, enable: bool) -> Self {
+        self.lock_bypass = enable;
+        self
+    }
+
+    /// Give up if the DPLL has not locked within the given time. The lock
+    /// timer is clocked through the FDPLL32K peripheral channel, which is
+    /// connected to the 32kHz `GCLK1`.
+    pub fn lock_timeout(mut self, timeout: DpllLockTimeout) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+}
+
+/// Compute the output frequency of the DPLL, given its reference frequency
+/// and its integer and fractional loop divider ratios
+fn dpll_output_freq(reference: Hertz, ldr: u16, ldrfrac: u8) -> Hertz {
+    // fCK = fCKR * (LDR + 1 + LDRFRAC / 16)
+    let ratio = 16 * (ldr as u64 + 1) + ldrfrac as u64;
+    Hertz((reference.0 as u64 * ratio / 16) as u32)
+}
+
 struct State {
     gclk: GCLK,
 }
@@ -86,7 +253,8 @@ impl State {
             w.divsel().clear_bit();
             w.idc().bit(improve_duty_cycle);
             w.genen().set_bit();
-            w.oe().set_bit()
+            // The GCLK_IO pin is an input when used as the source
+            w.oe().bit(src != GCLKIN)
         });
         self.wait_for_sync();
     }
@@ -126,6 +294,9 @@ pub struct GenericClockController {
     state: State,
     gclks: [Hertz; 8],
     used_clocks: u64,
+    xosc_freq: Hertz,
+    dpll_freq: Hertz,
+    gclkin_freqs: [Hertz; 8],
 }
 
 impl GenericClockController {
@@ -137,7 +308,7 @@ impl GenericClockController {
         sysctrl: &mut SYSCTRL,
         nvmctrl: &mut NVMCTRL,
     ) -> Self {
-        Self::new_48mhz_from_32khz(gclk, pm, sysctrl, nvmctrl, false)
+        Self::new_48mhz_from_32khz(gclk, pm, sysctrl, nvmctrl, DfllReference::Osc32k)
     }
 
     /// Reset the clock controller, configure the system to run
@@ -148,7 +319,25 @@ impl GenericClockController {
         sysctrl: &mut SYSCTRL,
         nvmctrl: &mut NVMCTRL,
     ) -> Self {
-        Self::new_48mhz_from_32khz(gclk, pm, sysctrl, nvmctrl, true)
+        Self::new_48mhz_from_32khz(gclk, pm, sysctrl, nvmctrl, DfllReference::Xosc32k)
+    }
+
+    /// Reset the clock controller, configure the system to run
+    /// at 48Mhz and reset various clock dividers.
+    ///
+    /// The DFLL48M runs in USB clock recovery mode: once the device is
+    /// attached to a host, it locks to the 1 kHz start-of-frame packets,
+    /// which is accurate enough for full speed USB without a crystal. Until
+    /// then, it runs open loop from its factory calibration. GCLK1 is still
+    /// fed by the internal 32khz oscillator.
+    #[cfg(not(feature = "samd11c"))]
+    pub fn with_usb_clock_recovery(
+        gclk: GCLK,
+        pm: &mut PM,
+        sysctrl: &mut SYSCTRL,
+        nvmctrl: &mut NVMCTRL,
+    ) -> Self {
+        Self::new_48mhz_from_32khz(gclk, pm, sysctrl, nvmctrl, DfllReference::UsbSof)
     }
 
     fn new_48mhz_from_32khz(
@@ -156,8 +345,9 @@ impl GenericClockController {
         pm: &mut PM,
         sysctrl: &mut SYSCTRL,
         nvmctrl: &mut NVMCTRL,
-        use_external_crystal: bool,
+        reference: DfllReference,
     ) -> Self {
+        let use_external_crystal = reference == DfllReference::Xosc32k;
         let mut state = State { gclk };
 
         set_flash_to_half_auto_wait_state(nvmctrl);
@@ -182,7 +372,7 @@ impl GenericClockController {
         // Feed 32khz into the DFLL48
         state.enable_clock_generator(DFLL48, GCLK1);
         // Enable the DFLL48
-        configure_and_enable_dfll48m(sysctrl, use_external_crystal);
+        configure_and_enable_dfll48m(sysctrl, reference);
         // Feed DFLL48 into the main clock
         state.set_gclk_divider_and_source(GCLK0, 1, DFLL48M, true);
         // We are now running at 48Mhz
@@ -210,6 +400,9 @@ impl GenericClockController {
                 Hertz(0),
             ],
             used_clocks: 1u64 << u8::from(ClockId::DFLL48),
+            xosc_freq: Hertz(0),
+            dpll_freq: Hertz(0),
+            gclkin_freqs: [Hertz(0); 8],
         }
     }
 
@@ -261,6 +454,9 @@ impl GenericClockController {
                 Hertz(0),
             ],
             used_clocks: 0,
+            xosc_freq: Hertz(0),
+            dpll_freq: Hertz(0),
+            gclkin_freqs: [Hertz(0); 8],
         }
     }
 
@@ -302,8 +498,12 @@ impl GenericClockController {
     /// this function doesn't expose that functionality at this time.
     /// `improve_duty_cycle` is a boolean that, when set to true, enables
     /// a 5o/50 duty cycle for odd divider values.
+    /// The `XOSC` and `GCLKIN` sources must first be set up with
+    /// `configure_xosc` or `configure_gclkin`. `DPLL96M` is assumed to run at
+    /// 96 MHz unless it was set up with `configure_dpll96m`.
     /// Returns a `GClock` for the configured clock generator.
-    /// Returns `None` if the clock generator has already been configured.
+    /// Returns `None` if the clock generator has already been configured,
+    /// or if the source has not been set up.
     pub fn configure_gclk_divider_and_source(
         &mut self,
         gclk: ClockGenId,
@@ -315,18 +515,208 @@ impl GenericClockController {
         if self.gclks[idx].0 != 0 {
             return None;
         }
+        let freq = self.source_freq(idx, src);
+        if freq.0 == 0 {
+            return None;
+        }
         self.state
             .set_gclk_divider_and_source(gclk, divider, src, improve_duty_cycle);
-        let freq: Hertz = match src {
+        let freq = Hertz(freq.0 / divider as u32);
+        self.gclks[idx] = freq;
+        Some(GClock { gclk, freq })
+    }
+
+    /// Switches gclk0, and therefore the CPU, to a new source and divider at
+    /// run-time.
+    /// The flash wait states are recomputed for the new frequency. They are
+    /// raised before switching to a faster clock, and lowered after
+    /// switching to a slower one, so that the flash is never read with too
+    /// few wait states.
+    /// Peripherals clocked from gclk0 are not reconfigured; they must be
+    /// notified of the new frequency through the `Reclock` trait.
+    /// Returns the new `GClock` for gclk0.
+    /// Returns `None` if the source has not been set up.
+    pub fn set_gclk0(
+        &mut self,
+        src: ClockSource,
+        divider: u16,
+        nvmctrl: &mut NVMCTRL,
+    ) -> Option<GClock> {
+        let freq = self.source_freq(0, src);
+        if freq.0 == 0 {
+            return None;
+        }
+        let freq = Hertz(freq.0 / divider as u32);
+        let faster = freq.0 > self.gclks[0].0;
+        if faster {
+            set_flash_wait_states(nvmctrl, freq);
+        }
+        self.state
+            .set_gclk_divider_and_source(GCLK0, divider, src, false);
+        if !faster {
+            set_flash_wait_states(nvmctrl, freq);
+        }
+        self.gclks[0] = freq;
+        Some(GClock { gclk: GCLK0, freq })
+    }
+
+    /// Returns the frequency of a clock source, as seen by the clock
+    /// generator at index `idx`, or zero if the source has not been set up
+    fn source_freq(&self, idx: usize, src: ClockSource) -> Hertz {
+        match src {
             XOSC32K | OSC32K | OSCULP32K => OSC32K_FREQ,
             GCLKGEN1 => self.gclks[1],
             OSC8M => OSC8M_FREQ,
             DFLL48M => OSC48M_FREQ,
-            DPLL96M => 96.mhz().into(),
-            GCLKIN | XOSC => unimplemented!(),
+            // A DPLL set up outside of this controller is assumed to run at
+            // its nominal frequency
+            DPLL96M if self.dpll_freq.0 == 0 => Hertz(96_000_000),
+            DPLL96M => self.dpll_freq,
+            XOSC => self.xosc_freq,
+            GCLKIN => self.gclkin_freqs[idx],
+        }
+    }
+
+    /// Configures and enables the external oscillator, so that it can be
+    /// used as the source of a clock generator or as the reference of the
+    /// FDPLL96M.
+    /// Unless the oscillator is configured to run on demand, this function
+    /// waits until it is ready.
+    /// Returns the frequency of the oscillator.
+    /// Returns `None` if the oscillator has already been configured.
+    pub fn configure_xosc(&mut self, sysctrl: &mut SYSCTRL, config: XoscConfig) -> Option<Hertz> {
+        if self.xosc_freq.0 != 0 {
+            return None;
+        }
+
+        sysctrl.xosc.write(|w| unsafe {
+            w.startup().bits(config.startup);
+            w.xtalen().bit(config.crystal);
+            w.gain().bits(config.gain());
+            w.ondemand().bit(config.on_demand);
+            w.runstdby().bit(config.run_standby);
+            w.enable().set_bit()
+        });
+
+        if !config.on_demand {
+            while sysctrl.pclksr.read().xoscrdy().bit_is_clear() {}
+        }
+        // The automatic gain control must only be enabled once the
+        // oscillator is running
+        if config.amplitude_control {
+            sysctrl.xosc.modify(|_, w| w.ampgc().set_bit());
+        }
+
+        self.xosc_freq = config.freq;
+        Some(config.freq)
+    }
+
+    /// Configures and enables the FDPLL96M, so that it can be used as the
+    /// source of a clock generator.
+    /// This function waits until the DPLL is locked, or until its clock is
+    /// ready if the lock is bypassed.
+    /// Returns the output frequency of the DPLL.
+    /// Returns `None` if the DPLL has already been configured, if the
+    /// reference clock has not been set up, or if the lock timed out. In the
+    /// latter case, the DPLL is disabled again.
+    pub fn configure_dpll96m(
+        &mut self,
+        sysctrl: &mut SYSCTRL,
+        config: DpllConfig,
+    ) -> Option<Hertz> {
+        if self.dpll_freq.0 != 0 {
+            return None;
+        }
+
+        let (refclk, div, ref_freq) = match config.reference {
+            DpllReference::Xosc32k => {
+                let running = sysctrl.pclksr.read().xosc32krdy().bit_is_set();
+                let freq = if running { OSC32K_FREQ } else { Hertz(0) };
+                (REFCLK_A::REF0, 0, freq)
+            }
+            DpllReference::Xosc { div } => {
+                if div >= 2_u16.pow(11) {
+                    panic!("invalid DPLL divider {}", div);
+                }
+                let freq = Hertz(self.xosc_freq.0 / (2 * (div as u32 + 1)));
+                (REFCLK_A::REF1, div, freq)
+            }
+            DpllReference::Gclk(gclk) => {
+                let bits: u64 = 1 << u8::from(ClockId::FDPLL) as u64;
+                if (self.used_clocks & bits) != 0 {
+                    return None;
+                }
+                (REFCLK_A::GCLK, 0, self.gclks[u8::from(gclk) as usize])
+            }
         };
-        self.gclks[idx] = Hertz(freq.0 / divider as u32);
-        Some(GClock { gclk, freq })
+        if ref_freq.0 == 0 {
+            return None;
+        }
+        if ref_freq.0 < 32_000 || ref_freq.0 > 2_000_000 {
+            panic!("invalid DPLL reference frequency {}", ref_freq.0);
+        }
+        let freq = dpll_output_freq(ref_freq, config.ldr, config.ldrfrac);
+        if freq.0 < 48_000_000 || freq.0 > 96_000_000 {
+            panic!("invalid DPLL output frequency {}", freq.0);
+        }
+
+        let timeout = config.lock_timeout.is_some();
+        if timeout {
+            // The lock timer runs from the 32kHz clock
+            let bits: u64 = 1 << u8::from(ClockId::FDPLL32K) as u64;
+            if self.gclks[1].0 == 0 {
+                return None;
+            }
+            if (self.used_clocks & bits) == 0 {
+                self.used_clocks |= bits;
+                self.state.enable_clock_generator(ClockId::FDPLL32K, GCLK1);
+            }
+        }
+        if let DpllReference::Gclk(gclk) = config.reference {
+            self.used_clocks |= 1 << u8::from(ClockId::FDPLL) as u64;
+            self.state.enable_clock_generator(ClockId::FDPLL, gclk);
+        }
+
+        sysctrl.dpllctrla.write(|w| w.enable().clear_bit());
+        while sysctrl.dpllstatus.read().enable().bit_is_set() {}
+        sysctrl.dpllratio.write(|w| unsafe {
+            w.ldr().bits(config.ldr);
+            w.ldrfrac().bits(config.ldrfrac)
+        });
+        sysctrl.dpllctrlb.write(|w| unsafe {
+            w.refclk().variant(refclk);
+            w.ltime().bits(config.lock_timeout.map_or(0, u8::from));
+            w.lbypass().bit(config.lock_bypass);
+            w.div().bits(div)
+        });
+        sysctrl.dpllctrla.write(|w| {
+            w.enable().set_bit();
+            w.ondemand().clear_bit()
+        });
+        while sysctrl.dpllstatus.read().enable().bit_is_clear() {}
+
+        loop {
+            let status = sysctrl.dpllstatus.read();
+            if status.clkrdy().bit_is_set() && (config.lock_bypass || status.lock().bit_is_set()) {
+                break;
+            }
+            if timeout && sysctrl.pclksr.read().dplllto().bit_is_set() {
+                sysctrl.dpllctrla.write(|w| w.enable().clear_bit());
+                while sysctrl.dpllstatus.read().enable().bit_is_set() {}
+                return None;
+            }
+        }
+
+        self.dpll_freq = freq;
+        Some(freq)
+    }
+
+    /// Records the frequency of the external clock signal applied to the
+    /// GCLK_IO pin of the specified clock generator, so that the generator
+    /// can then be configured with the `GCLKIN` source. The pin must be put
+    /// into its GCLK_IO alternate function by the caller.
+    pub fn configure_gclkin(&mut self, gclk: ClockGenId, freq: impl Into<Hertz>) {
+        self.gclkin_freqs[u8::from(gclk) as usize] = freq.into();
     }
 
     /// Enables or disables the given GClk from operation in standby.
@@ -336,9 +726,10 @@ impl GenericClockController {
 }
 
 macro_rules! clock_generator {
-    ($(($id:ident, $Type:ident, $clock:ident),)+) => {
+    ($($(#[$attr:meta])* ($id:ident, $Type:ident, $clock:ident),)+) => {
 
 $(
+$(#[$attr])*
 /// A typed token that indicates that the clock for the peripheral(s)
 /// with the matching name has been configured.
 /// The effective clock frequency is available via the `freq` method,
@@ -351,12 +742,14 @@ pub struct $Type {
     freq: Hertz,
 }
 
+$(#[$attr])*
 impl $Type {
     /// Returns the frequency of the configured clock
     pub fn freq(&self) -> Hertz {
         self.freq
     }
 }
+$(#[$attr])*
 impl Into<Hertz> for $Type {
     fn into(self) -> Hertz {
         self.freq
@@ -378,6 +771,7 @@ impl GenericClockController {
     /// appropriately.
     /// Returns `None` is the specified generic clock has already been
     /// configured.
+    $(#[$attr])*
     pub fn $id(&mut self, generator: &GClock) -> Option<$Type> {
         let bits: u64 = 1<<u8::from(ClockId::$clock) as u64;
         if (self.used_clocks & bits) != 0 {
@@ -402,6 +796,8 @@ clock_generator!(
     (sercom0_core, Sercom0CoreClock, SERCOM0_CORE),
     (sercom1_core, Sercom1CoreClock, SERCOM1_CORE),
     (sercom2_core, Sercom2CoreClock, SERCOM2_CORE),
+    #[cfg(feature = "samd11d")]
+    (usb, UsbClock, USB),
     (rtc, RtcClock, RTC),
     (adc, AdcClock, ADC),
     (wdt, WdtClock, WDT),
@@ -464,6 +860,16 @@ fn set_flash_to_half_auto_wait_state(nvmctrl: &mut NVMCTRL) {
     nvmctrl.ctrlb.modify(|_, w| w.rws().half());
 }
 
+/// Sets the number of flash wait states required for a CPU frequency,
+/// assuming a supply voltage of at least 2.7V
+fn set_flash_wait_states(nvmctrl: &mut NVMCTRL, freq: Hertz) {
+    if freq.0 <= 24_000_000 {
+        nvmctrl.ctrlb.modify(|_, w| w.rws().single());
+    } else {
+        nvmctrl.ctrlb.modify(|_, w| w.rws().half());
+    }
+}
+
 /// Prevent automatic writes to flash by pointers to flash area
 #[cfg(feature = "samd21")]
 fn set_flash_manual_write(nvmctrl: &mut NVMCTRL) {
@@ -517,8 +923,20 @@ fn wait_for_dfllrdy(sysctrl: &mut SYSCTRL) {
     while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
 }
 
+/// Reference of the dfll48m closed loop
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DfllReference {
+    /// Internal 32khz oscillator, through GCLK1
+    Osc32k,
+    /// External 32khz crystal, through GCLK1
+    Xosc32k,
+    /// USB start-of-frame packets
+    #[cfg_attr(feature = "samd11c", allow(dead_code))]
+    UsbSof,
+}
+
 /// Configure the dfll48m to operate at 48Mhz
-fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, use_external_crystal: bool) {
+fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, reference: DfllReference) {
     // Turn it off while we configure it.
     // Note that we need to turn off on-demand mode and
     // disable it here, rather than just reseting the ctrl
@@ -526,7 +944,7 @@ fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, use_external_crystal: boo
     sysctrl.dfllctrl.write(|w| w.ondemand().clear_bit());
     wait_for_dfllrdy(sysctrl);
 
-    if use_external_crystal {
+    if reference == DfllReference::Xosc32k {
         sysctrl.dfllmul.write(|w| unsafe {
             w.cstep().bits(31);
             w.fstep().bits(511);
@@ -547,6 +965,39 @@ fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, use_external_crystal: boo
             // Disable quick lock
             w.qldis().set_bit()
         });
+    } else if reference == DfllReference::UsbSof {
+        // Apply calibration, the loop then only has to correct the fine value
+        let coarse = super::calibration::dfll48m_coarse_cal();
+
+        sysctrl.dfllval.write(|w| unsafe {
+            w.coarse().bits(coarse);
+            w.fine().bits(0x200)
+        });
+
+        sysctrl.dfllmul.write(|w| unsafe {
+            w.cstep().bits(1);
+            w.fstep().bits(1);
+            // 48Mhz from the 1khz start-of-frame
+            w.mul().bits(48_000)
+        });
+
+        // Turn it on
+        sysctrl.dfllctrl.write(|w| {
+            // always on
+            w.ondemand().clear_bit();
+
+            // closed loop mode
+            w.mode().set_bit();
+
+            // usb clock recovery
+            w.usbcrm().set_bit();
+
+            // chill cycle disable
+            w.ccdis().set_bit();
+
+            // bypass coarse lock (have calibration data)
+            w.bplckc().set_bit()
+        });
     } else {
         // Apply calibration
         let coarse = super::calibration::dfll48m_coarse_cal();
@@ -576,11 +1027,7 @@ fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, use_external_crystal: boo
             w.ccdis().set_bit();
 
             // usb correction is not set due to instability issues around
-            // USB bus resets. TODO(twitchyliquid64): Maybe switch to OSC8M?
-            //
-            // TODO usb correction (still active for samd11??)
-            #[cfg(feature = "samd11")]
-            w.usbcrm().set_bit();
+            // USB bus resets, see `with_usb_clock_recovery` instead.
 
             // bypass coarse lock (have calibration data)
             w.bplckc().set_bit()
@@ -593,7 +1040,7 @@ fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, use_external_crystal: boo
     sysctrl.dfllctrl.modify(|_, w| w.enable().set_bit());
 
     #[cfg(feature = "samd21")]
-    if use_external_crystal {
+    if reference == DfllReference::Xosc32k {
         // wait for lock
         while sysctrl.pclksr.read().dflllckc().bit_is_clear()
             || sysctrl.pclksr.read().dflllckf().bit_is_clear()
@@ -602,3 +1049,15 @@ fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, use_external_crystal: boo
 
     wait_for_dfllrdy(sysctrl);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpll_ratio() {
+        assert_eq!(dpll_output_freq(OSC32K_FREQ, 1463, 0), Hertz(47_972_352));
+        assert_eq!(dpll_output_freq(Hertz(1_000_000), 47, 8), Hertz(48_500_000));
+        assert_eq!(dpll_output_freq(Hertz(2_000_000), 47, 0), Hertz(96_000_000));
+    }
+}
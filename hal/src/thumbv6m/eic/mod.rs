@@ -2,6 +2,7 @@ use crate::clock::EicClock;
 use crate::pac;
 
 pub mod pin;
+pub mod v2;
 
 pub struct EIC {
     eic: pac::EIC,
@@ -0,0 +1,644 @@
+//! # External interrupts from `gpio::v2` pins
+//!
+//! Version 2 of the EIC API configures external interrupts directly from
+//! [`gpio::v2`](crate::gpio::v2) pins in one of the
+//! [`Interrupt`] modes. The EXTINT line of a pin is derived from its
+//! [`PinId`], through the [`EicPinId`] trait.
+//!
+//! Every setting of an [`ExtInt`] can be changed at any time.
+//!
+//! ```
+//! let mut eic = Eic::new(&mut peripherals.PM, clocks.eic(&gclk).unwrap(), peripherals.EIC);
+//! let mut button = eic.ext_int(pins.pa16.into_pull_up_interrupt()).unwrap();
+//! button.sense(Sense::FALL);
+//! button.filter(true);
+//! button.enable_interrupt();
+//! ```
+//!
+//! ## Async
+//!
+//! [`ExtInt::wait_for_high`], [`ExtInt::wait_for_low`],
+//! [`ExtInt::wait_for_rising`] and [`ExtInt::wait_for_falling`] return
+//! futures that complete when the requested level or edge is seen. The
+//! futures are woken from the `EIC` interrupt, which must call
+//! [`handle_interrupt`]:
+//!
+//! ```
+//! #[interrupt]
+//! fn EIC() {
+//!     atsamd_hal::eic::v2::handle_interrupt();
+//! }
+//!
+//! button.wait_for_falling().await;
+//! ```
+//!
+//! ## NMI
+//!
+//! On SAMD21 devices, the NMI pin, PA08, is configured with [`Eic::nmi`]. Its
+//! interrupt is the `NonMaskableInt` exception.
+
+use core::cell::RefCell;
+use core::future::Future;
+use core::task::{Context, Poll, Waker};
+
+use cortex_m::interrupt::{self, Mutex};
+
+use crate::clock::EicClock;
+use crate::gpio::v2::*;
+use crate::pac;
+use crate::pac::eic::RegisterBlock;
+
+pub use super::pin::Sense;
+
+/// Number of EXTINT lines
+#[cfg(feature = "samd11")]
+const NUM_LINES: usize = 8;
+#[cfg(feature = "samd21")]
+const NUM_LINES: usize = 16;
+
+//==============================================================================
+//  EXTINT lines
+//==============================================================================
+
+/// A [`PinId`] connected to an EXTINT line
+pub trait EicPinId: PinId {
+    /// The EXTINT line of the pin
+    const EXTINT: u8;
+}
+
+/// A [`PinId`] connected to the NMI line
+pub trait NmiPinId: PinId {}
+
+#[cfg(feature = "samd21")]
+impl NmiPinId for PA08 {}
+
+macro_rules! eic_pins {
+    (
+        $(
+            $num:literal: [
+                $(
+                    $( #[$attr:meta] )*
+                    $Id:ident,
+                )+
+            ]
+        )+
+    ) => {
+        $(
+            $(
+                $( #[$attr] )*
+                impl EicPinId for $Id {
+                    const EXTINT: u8 = $num;
+                }
+            )+
+        )+
+    };
+}
+
+#[cfg(feature = "samd11")]
+eic_pins! {
+    1: [
+        PA15,
+    ]
+    2: [
+        PA02,
+    ]
+    3: [
+        PA31,
+    ]
+    4: [
+        PA04,
+        PA24,
+    ]
+    5: [
+        PA05,
+        PA25,
+    ]
+    6: [
+        PA08,
+    ]
+    7: [
+        PA09,
+    ]
+}
+
+#[cfg(feature = "samd21")]
+eic_pins! {
+    0: [
+        PA00,
+        PA16,
+        #[cfg(feature = "min-samd21j")]
+        PB00,
+        #[cfg(feature = "min-samd21j")]
+        PB16,
+    ]
+    1: [
+        PA01,
+        PA17,
+        #[cfg(feature = "min-samd21j")]
+        PB01,
+        #[cfg(feature = "min-samd21j")]
+        PB17,
+    ]
+    2: [
+        PA02,
+        PA18,
+        #[cfg(feature = "min-samd21g")]
+        PB02,
+    ]
+    3: [
+        PA03,
+        PA19,
+        #[cfg(feature = "min-samd21g")]
+        PB03,
+    ]
+    4: [
+        PA04,
+        #[cfg(feature = "min-samd21g")]
+        PA20,
+        #[cfg(feature = "min-samd21j")]
+        PB04,
+    ]
+    5: [
+        PA05,
+        #[cfg(feature = "min-samd21g")]
+        PA21,
+        #[cfg(feature = "min-samd21j")]
+        PB05,
+    ]
+    6: [
+        PA06,
+        PA22,
+        #[cfg(feature = "min-samd21j")]
+        PB06,
+        #[cfg(feature = "min-samd21g")]
+        PB22,
+    ]
+    7: [
+        PA07,
+        PA23,
+        #[cfg(feature = "min-samd21j")]
+        PB07,
+        #[cfg(feature = "min-samd21g")]
+        PB23,
+    ]
+    8: [
+        PA28,
+        #[cfg(feature = "min-samd21g")]
+        PB08,
+    ]
+    9: [
+        PA09,
+        #[cfg(feature = "min-samd21g")]
+        PB09,
+    ]
+    10: [
+        PA10,
+        PA30,
+        #[cfg(feature = "min-samd21g")]
+        PB10,
+    ]
+    11: [
+        PA11,
+        PA31,
+        #[cfg(feature = "min-samd21g")]
+        PB11,
+    ]
+    12: [
+        #[cfg(feature = "min-samd21g")]
+        PA12,
+        PA24,
+        #[cfg(feature = "min-samd21j")]
+        PB12,
+    ]
+    13: [
+        #[cfg(feature = "min-samd21g")]
+        PA13,
+        PA25,
+        #[cfg(feature = "min-samd21j")]
+        PB13,
+    ]
+    14: [
+        PA14,
+        #[cfg(feature = "min-samd21j")]
+        PB14,
+        #[cfg(feature = "min-samd21j")]
+        PB30,
+    ]
+    15: [
+        PA15,
+        PA27,
+        #[cfg(feature = "min-samd21j")]
+        PB15,
+        #[cfg(feature = "min-samd21j")]
+        PB31,
+    ]
+}
+
+//==============================================================================
+//  Register access
+//==============================================================================
+
+#[inline]
+fn regs() -> &'static RegisterBlock {
+    // SAFETY: Each EXTINT line is owned by a single `ExtInt`, and the registers
+    // shared between lines are only modified within a critical section
+    unsafe { &*pac::EIC::ptr() }
+}
+
+/// Set or clear the bits of `mask` in a register shared between lines
+macro_rules! write_shared_bits {
+    ($reg:ident, $mask:expr, $set:expr) => {
+        interrupt::free(|_| {
+            regs().$reg.modify(|r, w| unsafe {
+                if $set {
+                    w.bits(r.bits() | $mask)
+                } else {
+                    w.bits(r.bits() & !$mask)
+                }
+            })
+        })
+    };
+}
+
+//==============================================================================
+//  Eic
+//==============================================================================
+
+/// The External Interrupt Controller
+///
+/// The [`Eic`] hands out one [`ExtInt`] per EXTINT line, and keeps track of
+/// the lines in use, as several pins share each line.
+pub struct Eic {
+    eic: pac::EIC,
+    lines: u16,
+}
+
+impl Eic {
+    /// Reset and enable the EIC, clocked from `GCLK_EIC`
+    pub fn new(pm: &mut pac::PM, _clock: EicClock, eic: pac::EIC) -> Self {
+        pm.apbamask.modify(|_, w| w.eic_().set_bit());
+
+        eic.ctrl.modify(|_, w| w.swrst().set_bit());
+        while eic.status.read().syncbusy().bit_is_set() {}
+
+        eic.ctrl.modify(|_, w| w.enable().set_bit());
+        while eic.status.read().syncbusy().bit_is_set() {}
+
+        Self { eic, lines: 0 }
+    }
+
+    /// Use a pin as an external interrupt
+    ///
+    /// Returns the pin if its EXTINT line is already used by another pin.
+    pub fn ext_int<I, C>(
+        &mut self,
+        pin: Pin<I, Interrupt<C>>,
+    ) -> Result<ExtInt<I, C>, Pin<I, Interrupt<C>>>
+    where
+        I: EicPinId,
+        C: InterruptConfig,
+    {
+        let mask = 1 << I::EXTINT;
+        if self.lines & mask != 0 {
+            return Err(pin);
+        }
+        self.lines |= mask;
+        Ok(ExtInt { pin })
+    }
+
+    /// Use a pin as the non-maskable interrupt
+    pub fn nmi<I, C>(&mut self, pin: Pin<I, Interrupt<C>>) -> Nmi<I, C>
+    where
+        I: NmiPinId,
+        C: InterruptConfig,
+    {
+        Nmi { pin }
+    }
+
+    /// Disable the EIC and return the PAC struct
+    pub fn free(self) -> pac::EIC {
+        self.eic.ctrl.modify(|_, w| w.enable().clear_bit());
+        while self.eic.status.read().syncbusy().bit_is_set() {}
+        self.eic
+    }
+}
+
+//==============================================================================
+//  ExtInt
+//==============================================================================
+
+/// A pin used as an external interrupt
+pub struct ExtInt<I, C>
+where
+    I: EicPinId,
+    C: InterruptConfig,
+{
+    pin: Pin<I, Interrupt<C>>,
+}
+
+impl<I, C> ExtInt<I, C>
+where
+    I: EicPinId,
+    C: InterruptConfig,
+{
+    const MASK: u32 = 1 << I::EXTINT;
+
+    /// Return the EXTINT line of the pin
+    #[inline]
+    pub fn id(&self) -> u8 {
+        I::EXTINT
+    }
+
+    /// Release the EXTINT line, and return the pin
+    pub fn free(mut self, eic: &mut Eic) -> Pin<I, Interrupt<C>> {
+        self.disable_interrupt();
+        self.sense(Sense::NONE);
+        eic.lines &= !(1 << I::EXTINT);
+        self.pin
+    }
+
+    /// Write the 4-bit configuration of the line, made of the sense and
+    /// filter settings
+    fn modify_config(&mut self, f: impl FnOnce(u32) -> u32) {
+        let index = (I::EXTINT >> 3) as usize;
+        let shift = (I::EXTINT & 0b111) * 4;
+        interrupt::free(|_| {
+            regs().config[index].modify(|r, w| unsafe {
+                let config = (r.bits() >> shift) & 0xF;
+                w.bits(r.bits() & !(0xF << shift) | (f(config) & 0xF) << shift)
+            })
+        });
+    }
+
+    /// Read the sense configuration of the line
+    pub fn get_sense(&self) -> Sense {
+        let index = (I::EXTINT >> 3) as usize;
+        let shift = (I::EXTINT & 0b111) * 4;
+        match (regs().config[index].read().bits() >> shift) & 0b111 {
+            1 => Sense::RISE,
+            2 => Sense::FALL,
+            3 => Sense::BOTH,
+            4 => Sense::HIGH,
+            5 => Sense::LOW,
+            _ => Sense::NONE,
+        }
+    }
+
+    /// Set the level or edges that trigger the interrupt
+    pub fn sense(&mut self, sense: Sense) {
+        if self.get_sense() != sense {
+            self.modify_config(|config| config & !0b111 | sense as u32);
+        }
+    }
+
+    /// Enable or disable the majority filter, which requires two of three
+    /// samples to agree
+    pub fn filter(&mut self, filter: bool) {
+        self.modify_config(|config| config & !0b1000 | (filter as u32) << 3);
+    }
+
+    /// Enable or disable the wake-up of the device by the line
+    ///
+    /// Interrupts wake the device from the sleep modes in which the EIC
+    /// clock keeps running, even without this setting. With this setting,
+    /// edges and levels are also detected without a running EIC clock, and
+    /// wake the device from standby.
+    pub fn wakeup(&mut self, wakeup: bool) {
+        write_shared_bits!(wakeup, Self::MASK, wakeup);
+    }
+
+    /// Enable the event output of the line
+    pub fn enable_event(&mut self) {
+        write_shared_bits!(evctrl, Self::MASK, true);
+    }
+
+    /// Disable the event output of the line
+    pub fn disable_event(&mut self) {
+        write_shared_bits!(evctrl, Self::MASK, false);
+    }
+
+    /// Enable the interrupt of the line, on the `EIC` vector
+    #[inline]
+    pub fn enable_interrupt(&mut self) {
+        regs().intenset.write(|w| unsafe { w.bits(Self::MASK) });
+    }
+
+    /// Disable the interrupt of the line
+    #[inline]
+    pub fn disable_interrupt(&mut self) {
+        regs().intenclr.write(|w| unsafe { w.bits(Self::MASK) });
+    }
+
+    /// Returns `true` if the interrupt flag of the line is set
+    #[inline]
+    pub fn is_interrupt(&self) -> bool {
+        regs().intflag.read().bits() & Self::MASK != 0
+    }
+
+    /// Clear the interrupt flag of the line
+    #[inline]
+    pub fn clear_interrupt(&mut self) {
+        regs().intflag.write(|w| unsafe { w.bits(Self::MASK) });
+    }
+
+    /// Returns `true` if the pin is high
+    #[inline]
+    pub fn is_high(&self) -> bool {
+        self.pin._is_high()
+    }
+
+    /// Returns `true` if the pin is low
+    #[inline]
+    pub fn is_low(&self) -> bool {
+        self.pin._is_low()
+    }
+
+    /// Wait until the pin is high
+    pub fn wait_for_high(&mut self) -> Wait<'_, I, C> {
+        Wait::new(self, Sense::HIGH)
+    }
+
+    /// Wait until the pin is low
+    pub fn wait_for_low(&mut self) -> Wait<'_, I, C> {
+        Wait::new(self, Sense::LOW)
+    }
+
+    /// Wait for a rising edge on the pin
+    pub fn wait_for_rising(&mut self) -> Wait<'_, I, C> {
+        Wait::new(self, Sense::RISE)
+    }
+
+    /// Wait for a falling edge on the pin
+    pub fn wait_for_falling(&mut self) -> Wait<'_, I, C> {
+        Wait::new(self, Sense::FALL)
+    }
+}
+
+//==============================================================================
+//  Async
+//==============================================================================
+
+const NO_WAKER: Option<Waker> = None;
+
+static WAKERS: Mutex<RefCell<[Option<Waker>; NUM_LINES]>> =
+    Mutex::new(RefCell::new([NO_WAKER; NUM_LINES]));
+
+/// Wake the tasks waiting on the EXTINT lines that have fired
+///
+/// This function must be called from every EIC interrupt used by the
+/// [`ExtInt`] futures. It disables the interrupt of each line it wakes, and
+/// leaves the interrupt flags set, to be read by the futures.
+pub fn handle_interrupt() {
+    let eic = regs();
+    let pending = eic.intflag.read().bits() & eic.intenset.read().bits();
+    interrupt::free(|cs| {
+        let mut wakers = WAKERS.borrow(cs).borrow_mut();
+        for (line, waker) in wakers.iter_mut().enumerate() {
+            if pending & (1 << line) != 0 {
+                if let Some(waker) = waker.take() {
+                    eic.intenclr.write(|w| unsafe { w.bits(1 << line) });
+                    waker.wake();
+                }
+            }
+        }
+    });
+}
+
+/// Future returned by the `wait_for_*` methods of [`ExtInt`]
+///
+/// The future sets the sense of the line. On completion or when dropped, it
+/// disables the interrupt of the line.
+pub struct Wait<'a, I, C>
+where
+    I: EicPinId,
+    C: InterruptConfig,
+{
+    ext_int: &'a mut ExtInt<I, C>,
+    sense: Sense,
+    armed: bool,
+}
+
+impl<'a, I, C> Wait<'a, I, C>
+where
+    I: EicPinId,
+    C: InterruptConfig,
+{
+    fn new(ext_int: &'a mut ExtInt<I, C>, sense: Sense) -> Self {
+        Self {
+            ext_int,
+            sense,
+            armed: false,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        match self.sense {
+            Sense::HIGH => self.ext_int.is_high(),
+            Sense::LOW => self.ext_int.is_low(),
+            _ => self.armed && self.ext_int.is_interrupt(),
+        }
+    }
+}
+
+impl<I, C> Future for Wait<'_, I, C>
+where
+    I: EicPinId,
+    C: InterruptConfig,
+{
+    type Output = ();
+
+    fn poll(self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.is_done() {
+            this.ext_int.disable_interrupt();
+            this.ext_int.clear_interrupt();
+            return Poll::Ready(());
+        }
+        if !this.armed {
+            this.ext_int.sense(this.sense);
+            this.ext_int.clear_interrupt();
+            this.armed = true;
+        }
+        interrupt::free(|cs| {
+            WAKERS.borrow(cs).borrow_mut()[I::EXTINT as usize] = Some(cx.waker().clone());
+        });
+        this.ext_int.enable_interrupt();
+        Poll::Pending
+    }
+}
+
+impl<I, C> Drop for Wait<'_, I, C>
+where
+    I: EicPinId,
+    C: InterruptConfig,
+{
+    fn drop(&mut self) {
+        self.ext_int.disable_interrupt();
+        interrupt::free(|cs| {
+            WAKERS.borrow(cs).borrow_mut()[I::EXTINT as usize] = None;
+        });
+    }
+}
+
+//==============================================================================
+//  Nmi
+//==============================================================================
+
+/// A pin used as the non-maskable interrupt
+///
+/// The NMI is always enabled; it is triggered as soon as its sense is set.
+pub struct Nmi<I, C>
+where
+    I: NmiPinId,
+    C: InterruptConfig,
+{
+    pin: Pin<I, Interrupt<C>>,
+}
+
+impl<I, C> Nmi<I, C>
+where
+    I: NmiPinId,
+    C: InterruptConfig,
+{
+    /// Disable the NMI, and return the pin
+    pub fn free(mut self) -> Pin<I, Interrupt<C>> {
+        self.sense(Sense::NONE);
+        self.pin
+    }
+
+    /// Set the level or edges that trigger the NMI
+    pub fn sense(&mut self, sense: Sense) {
+        regs()
+            .nmictrl
+            .modify(|_, w| unsafe { w.nmisense().bits(sense as u8) });
+    }
+
+    /// Enable or disable the majority filter
+    pub fn filter(&mut self, filter: bool) {
+        regs().nmictrl.modify(|_, w| w.nmifilten().bit(filter));
+    }
+
+    /// Returns `true` if the NMI flag is set
+    #[inline]
+    pub fn is_interrupt(&self) -> bool {
+        regs().nmiflag.read().nmi().bit_is_set()
+    }
+
+    /// Clear the NMI flag
+    #[inline]
+    pub fn clear_interrupt(&mut self) {
+        regs().nmiflag.write(|w| w.nmi().set_bit());
+    }
+
+    /// Returns `true` if the pin is high
+    #[inline]
+    pub fn is_high(&self) -> bool {
+        self.pin._is_high()
+    }
+
+    /// Returns `true` if the pin is low
+    #[inline]
+    pub fn is_low(&self) -> bool {
+        self.pin._is_low()
+    }
+}
@@ -8,6 +8,7 @@ pub use serial_number::*;
 
 pub mod calibration;
 pub mod clock;
+pub mod nvm;
 pub mod timer;
 
 #[cfg(feature = "unproven")]
@@ -20,7 +21,6 @@ pub mod pwm;
 pub mod watchdog;
 
 #[cfg(feature = "usb")]
-#[cfg(feature = "samd21")]
 pub mod usb;
 
 pub(crate) mod sercom;
@@ -0,0 +1,362 @@
+//! # Non-volatile Memory Controller
+//!
+//! This module allows users to interact with non-volatile memory controller.
+//!
+//! The flash is organized in pages, which are written at once through a page
+//! buffer, and rows of 4 pages, which are erased at once. The flash is also
+//! split in 16 regions, which can be locked against erase and write.
+//!
+//! Page writes are issued explicitly in [`WriteMode::Manual`], which the clock
+//! setup of this HAL selects on SAMD21. In [`WriteMode::Automatic`], a page is
+//! written as soon as its last word is loaded in the page buffer, and partial
+//! pages are written explicitly.
+//!
+//! Some SAMD21 parts (the B, C, D and L variants) have an additional
+//! Read-While-Write EEPROM section (RWWEE). It can be erased and written while
+//! the CPU keeps running code from the main flash, which suits EEPROM
+//! emulation.
+//!
+//! Module features:
+//! - Erase rows & write pages of the main flash
+//! - Lock & unlock regions
+//! - Query the security bit
+//! - Erase, write & read the RWWEE section (SAMD21)
+#![warn(missing_docs)]
+
+use crate::pac::NVMCTRL;
+
+/// Start address of the RWWEE section
+#[cfg(feature = "samd21")]
+pub const RWWEE_ADDRESS: u32 = 0x0040_0000;
+
+/// Number of lock regions
+pub const REGIONS: u32 = 16;
+
+/// Number of pages in a row
+const ROW_PAGES: u32 = 4;
+
+// Commands, some of which are missing from the PAC
+const CMD_ER: u8 = 0x02;
+const CMD_WP: u8 = 0x04;
+const CMD_LR: u8 = 0x40;
+const CMD_UR: u8 = 0x41;
+const CMD_PBC: u8 = 0x44;
+#[cfg(feature = "samd21")]
+const CMD_RWWEEER: u8 = 0x1A;
+#[cfg(feature = "samd21")]
+const CMD_RWWEEWP: u8 = 0x1C;
+
+/// Non-volatile memory controller
+pub struct Nvm {
+    /// PAC peripheral
+    nvm: NVMCTRL,
+}
+
+/// Errors generated by the NVM peripheral
+#[derive(Debug)]
+pub enum PeripheralError {
+    /// Invalid command, or command on a protected address
+    NvmError,
+    /// Locked error
+    LockError,
+    /// Programming error
+    ProgrammingError,
+}
+
+/// Driver errors
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Error {
+    /// Address range outside of flash
+    NonFlash,
+    /// An alignment requirement was not fulfilled
+    Alignment,
+    /// The device has no RWWEE section
+    #[cfg(feature = "samd21")]
+    NoRwwee,
+    /// Errors generated by hardware
+    Peripheral(PeripheralError),
+}
+
+/// NVM result type
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// End of the range of `length` bytes at `address`, `None` on overflow
+#[inline]
+fn end_address(address: u32, length: usize) -> Option<u32> {
+    address.checked_add(u32::try_from(length).ok()?)
+}
+
+/// How pages are written from the page buffer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Pages are written by a write page command
+    Manual,
+    /// A page is written when its last word is loaded in the page buffer
+    Automatic,
+}
+
+impl Nvm {
+    /// Create a new NVM controller
+    #[inline]
+    pub fn new(nvm: NVMCTRL) -> Self {
+        Self { nvm }
+    }
+
+    /// Release the NVMCTRL peripheral, e.g. to change the flash wait states
+    #[inline]
+    pub fn free(self) -> NVMCTRL {
+        self.nvm
+    }
+
+    /// Size of a page in bytes
+    #[inline]
+    pub fn page_size(&self) -> u32 {
+        8 << self.nvm.param.read().psz().bits()
+    }
+
+    /// Size of a row, the erase unit, in bytes
+    #[inline]
+    pub fn row_size(&self) -> u32 {
+        self.page_size() * ROW_PAGES
+    }
+
+    /// Size of the main flash in bytes
+    #[inline]
+    pub fn flash_size(&self) -> u32 {
+        self.nvm.param.read().nvmp().bits() as u32 * self.page_size()
+    }
+
+    /// Size of a lock region in bytes
+    #[inline]
+    pub fn region_size(&self) -> u32 {
+        self.flash_size() / REGIONS
+    }
+
+    /// Determine if the controller is busy writing or erasing
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        self.nvm.intflag.read().ready().bit_is_set()
+    }
+
+    /// Check if the security bit is set
+    ///
+    /// A secured device cannot be accessed by a debugger, except to erase the
+    /// whole chip.
+    #[inline]
+    pub fn is_secured(&self) -> bool {
+        self.nvm.status.read().sb().bit_is_set()
+    }
+
+    /// Get the page write mode
+    #[inline]
+    pub fn write_mode(&self) -> WriteMode {
+        if self.nvm.ctrlb.read().manw().bit_is_set() {
+            WriteMode::Manual
+        } else {
+            WriteMode::Automatic
+        }
+    }
+
+    /// Set the page write mode
+    #[inline]
+    pub fn set_write_mode(&mut self, mode: WriteMode) {
+        self.nvm
+            .ctrlb
+            .modify(|_, w| w.manw().bit(mode == WriteMode::Manual));
+    }
+
+    /// Set address for a command, as the NVM expects it in 16-bit words
+    fn set_address(&mut self, address: u32) {
+        unsafe {
+            self.nvm
+                .addr
+                .write(|w| w.addr().bits((address >> 1) & 0x003f_ffff));
+        }
+    }
+
+    /// Execute a command, wait until it is done
+    fn command_sync(&mut self, command: u8) {
+        while !self.is_ready() {}
+        self.nvm
+            .ctrla
+            .write(|w| unsafe { w.cmdex().key().cmd().bits(command) });
+        while !self.is_ready() {}
+    }
+
+    /// Execute a command on an address and check the error flags
+    fn command_at(&mut self, command: u8, address: u32) -> Result<()> {
+        while !self.is_ready() {}
+        self.set_address(address);
+        self.command_sync(command);
+        self.manage_error_states()
+    }
+
+    /// Read the peripheral state to check error flags and clear them
+    /// afterwards
+    fn manage_error_states(&mut self) -> Result<()> {
+        let status = self.nvm.status.read();
+        // Check LOCKE first as it is more specific than PROGE
+        let state = if status.locke().bit_is_set() {
+            Err(Error::Peripheral(PeripheralError::LockError))
+        } else if status.proge().bit_is_set() {
+            Err(Error::Peripheral(PeripheralError::ProgrammingError))
+        } else if status.nvme().bit_is_set() {
+            Err(Error::Peripheral(PeripheralError::NvmError))
+        } else {
+            Ok(())
+        };
+
+        // Clear error flags
+        self.nvm.status.write(|w| {
+            w.locke().set_bit();
+            w.proge().set_bit();
+            w.nvme().set_bit()
+        });
+        self.nvm.intflag.write(|w| w.error().set_bit());
+        state
+    }
+
+    /// Load `data` in the page buffer at `address`, then write the page with
+    /// `command` unless an automatic write did
+    fn write_page_buffer(&mut self, address: u32, data: &[u32], command: u8) -> Result<()> {
+        let length = data.len() as u32 * 4;
+        if address % self.page_size() != 0 || length > self.page_size() {
+            return Err(Error::Alignment);
+        }
+
+        self.command_sync(CMD_PBC);
+        self.manage_error_states()?;
+
+        // The page buffer only accepts 16 and 32-bit writes
+        for (i, word) in data.iter().enumerate() {
+            unsafe { core::ptr::write_volatile((address as *mut u32).add(i), *word) };
+        }
+
+        if self.write_mode() == WriteMode::Manual || length < self.page_size() {
+            self.command_at(command, address)
+        } else {
+            while !self.is_ready() {}
+            self.manage_error_states()
+        }
+    }
+
+    fn check_flash(&self, address: u32, length: usize) -> Result<()> {
+        match end_address(address, length) {
+            Some(end) if end <= self.flash_size() => Ok(()),
+            _ => Err(Error::NonFlash),
+        }
+    }
+
+    /// Erase a row of flash memory
+    ///
+    /// `address` must be aligned to a row.
+    ///
+    /// # Safety
+    /// The row must not contain code or data in use.
+    #[inline]
+    pub unsafe fn erase_row(&mut self, address: u32) -> Result<()> {
+        if address % self.row_size() != 0 {
+            return Err(Error::Alignment);
+        }
+        self.check_flash(address, self.row_size() as usize)?;
+        self.command_at(CMD_ER, address)
+    }
+
+    /// Write a page of flash memory
+    ///
+    /// `address` must be aligned to a page, and `data` must fit in a page.
+    /// The page must have been erased.
+    ///
+    /// # Safety
+    /// The page must not contain code or data in use.
+    #[inline]
+    pub unsafe fn write_page(&mut self, address: u32, data: &[u32]) -> Result<()> {
+        self.check_flash(address, data.len().saturating_mul(4))?;
+        self.write_page_buffer(address, data, CMD_WP)
+    }
+
+    /// Lock the region containing `address` against erase and write
+    ///
+    /// The lock only lasts until reset. Locks set in the user row are restored
+    /// on reset.
+    #[inline]
+    pub fn lock_region(&mut self, address: u32) -> Result<()> {
+        self.check_flash(address, 1)?;
+        self.command_at(CMD_LR, address)
+    }
+
+    /// Unlock the region containing `address`
+    #[inline]
+    pub fn unlock_region(&mut self, address: u32) -> Result<()> {
+        self.check_flash(address, 1)?;
+        self.command_at(CMD_UR, address)
+    }
+
+    /// Check if the region containing `address` is locked
+    #[inline]
+    pub fn is_region_locked(&self, address: u32) -> Result<bool> {
+        self.check_flash(address, 1)?;
+        let region = address / self.region_size();
+        // A cleared bit means the region is locked
+        Ok(self.nvm.lock.read().lock().bits() & (1 << region) == 0)
+    }
+}
+
+#[cfg(feature = "samd21")]
+impl Nvm {
+    /// Size of the RWWEE section in bytes, 0 if the device has none
+    #[inline]
+    pub fn rwwee_size(&self) -> u32 {
+        // PARAM.RWWEEP is missing from the PAC
+        (self.nvm.param.read().bits() >> 20) * self.page_size()
+    }
+
+    fn check_rwwee(&self, offset: u32, length: usize) -> Result<()> {
+        let size = self.rwwee_size();
+        if size == 0 {
+            return Err(Error::NoRwwee);
+        }
+        match end_address(offset, length) {
+            Some(end) if end <= size => Ok(()),
+            _ => Err(Error::NonFlash),
+        }
+    }
+
+    /// Erase a row of the RWWEE section
+    ///
+    /// `offset` is relative to [`RWWEE_ADDRESS`] and must be aligned to a row.
+    #[inline]
+    pub fn erase_rwwee_row(&mut self, offset: u32) -> Result<()> {
+        if offset % self.row_size() != 0 {
+            return Err(Error::Alignment);
+        }
+        self.check_rwwee(offset, self.row_size() as usize)?;
+        self.command_at(CMD_RWWEEER, RWWEE_ADDRESS + offset)
+    }
+
+    /// Write a page of the RWWEE section
+    ///
+    /// `offset` is relative to [`RWWEE_ADDRESS`] and must be aligned to a
+    /// page, and `data` must fit in a page. The page must have been erased.
+    #[inline]
+    pub fn write_rwwee_page(&mut self, offset: u32, data: &[u32]) -> Result<()> {
+        self.check_rwwee(offset, data.len().saturating_mul(4))?;
+        self.write_page_buffer(RWWEE_ADDRESS + offset, data, CMD_RWWEEWP)
+    }
+
+    /// Read from the RWWEE section
+    ///
+    /// `offset` is relative to [`RWWEE_ADDRESS`].
+    #[inline]
+    pub fn read_rwwee(&self, offset: u32, buffer: &mut [u8]) -> Result<()> {
+        self.check_rwwee(offset, buffer.len())?;
+        // The section cannot be read while it is being written
+        while !self.is_ready() {}
+        let base = (RWWEE_ADDRESS + offset) as *const u8;
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile(base.add(i)) };
+        }
+        Ok(())
+    }
+}
@@ -13,6 +13,7 @@ use crate::pac::{PM, TC3, TC4, TC5};
 use crate::timer_params::TimerParams;
 
 use crate::clock;
+use crate::reclock::Reclock;
 use crate::time::{Hertz, Nanoseconds};
 use crate::timer_traits::InterruptDrivenTimer;
 use void::Void;
@@ -136,6 +137,14 @@ where
     }
 }
 
+impl<TC> Reclock for TimerCounter<TC> {
+    /// Update the frequency of the timer clock. The new frequency is used
+    /// to compute the prescaler and period on the next call to `start`.
+    fn clock_changed(&mut self, freq: Hertz) {
+        self.freq = freq;
+    }
+}
+
 macro_rules! tc {
     ($($TYPE:ident: ($TC:ident, $pm:ident, $clock:ident),)+) => {
         $(
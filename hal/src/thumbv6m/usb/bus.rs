@@ -13,7 +13,7 @@ use crate::pac;
 use crate::pac::usb::DEVICE;
 use crate::pac::{PM, USB};
 use crate::usb::devicedesc::DeviceDescBank;
-use core::cell::{Ref, RefCell, RefMut};
+use core::cell::{Cell, Ref, RefCell, RefMut};
 use core::marker::PhantomData;
 use core::mem;
 use cortex_m::interrupt::{free as disable_interrupts, Mutex};
@@ -31,7 +31,7 @@ pub enum EndpointTypeBits {
     Isochronous = 2,
     Bulk = 3,
     Interrupt = 4,
-    #[allow(unused)]
+    /// The bank is the second bank of the endpoint in the other direction.
     DualBank = 5,
 }
 
@@ -151,48 +151,59 @@ impl AllEndpoints {
     }
 }
 
-// FIXME: replace with more general heap?
+// Endpoint memory used by `UsbBus::new`, see `UsbBus::with_endpoint_memory`
+// to provide a pool of another size. The SAMD11 only has 4 KiB of RAM, which
+// leaves room for the control endpoint and a few bulk or interrupt endpoints
+// of 64 bytes.
+#[cfg(feature = "samd11")]
+const BUFFER_SIZE: usize = 512;
+#[cfg(feature = "samd21")]
 const BUFFER_SIZE: usize = 2048;
 fn buffer() -> &'static mut [u8; BUFFER_SIZE] {
     singleton!(: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE] ).unwrap()
 }
 
+/// Allocates the endpoint buffers from the endpoint memory pool
 struct BufferAllocator {
-    buffers: &'static mut [u8; BUFFER_SIZE],
-    next_buf: u16,
+    buffers: &'static mut [u8],
+    next_buf: usize,
 }
 
 impl BufferAllocator {
-    fn new() -> Self {
+    fn new(buffers: &'static mut [u8]) -> Self {
         Self {
             next_buf: 0,
-            buffers: buffer(),
+            buffers,
         }
     }
 
     fn allocate_buffer(&mut self, size: u16) -> UsbResult<*mut u8> {
         debug_assert!(size & 1 == 0);
 
-        let start_addr = &mut self.buffers[self.next_buf as usize] as *mut u8;
-        let buf_end = unsafe { start_addr.add(BUFFER_SIZE) };
-
         // The address must be 32-bit aligned, so allow for that here
         // by offsetting by an appropriate alignment.
-        let offset = start_addr.align_offset(mem::align_of::<u32>());
-        let start_addr = unsafe { start_addr.add(offset) };
-
-        if start_addr >= buf_end {
+        let base = self.buffers.as_mut_ptr();
+        let offset = unsafe { base.add(self.next_buf) }.align_offset(mem::align_of::<u32>());
+
+        let start = self
+            .next_buf
+            .checked_add(offset)
+            .ok_or(UsbError::EndpointMemoryOverflow)?;
+        let end = start
+            .checked_add(size as usize)
+            .ok_or(UsbError::EndpointMemoryOverflow)?;
+        if end > self.buffers.len() {
             return Err(UsbError::EndpointMemoryOverflow);
         }
 
-        let end_addr = unsafe { start_addr.offset(size as isize) };
-        if end_addr > buf_end {
-            return Err(UsbError::EndpointMemoryOverflow);
-        }
+        self.next_buf = end;
 
-        self.next_buf = unsafe { end_addr.sub(self.buffers.as_ptr() as usize) as u16 };
+        Ok(unsafe { base.add(start) })
+    }
 
-        Ok(start_addr)
+    /// Number of bytes left in the pool, before alignment
+    fn free(&self) -> usize {
+        self.buffers.len() - self.next_buf
     }
 }
 
@@ -202,6 +213,40 @@ struct Inner {
     _dp_pad: Pin<PA25, AlternateG>,
     endpoints: RefCell<AllEndpoints>,
     buffers: RefCell<BufferAllocator>,
+    power: Cell<PowerState>,
+}
+
+/// Link power state of the device, as seen by the bus
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LinkState {
+    /// The bus is active (L0)
+    On,
+    /// The host put the link to sleep with an LPM transaction (L1)
+    Sleep,
+    /// The bus has been idle for more than 3 ms (L2)
+    Suspend,
+}
+
+/// Suspend, resume and LPM configuration and state.
+#[derive(Clone, Copy)]
+struct PowerState {
+    suspend: bool,
+    lpm: bool,
+    link: LinkState,
+    on_suspend: Option<fn()>,
+    on_resume: Option<fn()>,
+}
+
+impl PowerState {
+    fn new() -> Self {
+        Self {
+            suspend: false,
+            lpm: false,
+            link: LinkState::On,
+            on_suspend: None,
+            on_resume: None,
+        }
+    }
 }
 
 pub struct UsbBus {
@@ -536,6 +581,30 @@ impl Inner {
         epstatus, EPSTATUS, epstatus0, epstatus1, epstatus2, epstatus3, epstatus4, epstatus5,
         epstatus6, epstatus7
     );
+    ep!(
+        epstatusset,
+        EPSTATUSSET,
+        epstatusset0,
+        epstatusset1,
+        epstatusset2,
+        epstatusset3,
+        epstatusset4,
+        epstatusset5,
+        epstatusset6,
+        epstatusset7
+    );
+    ep!(
+        epstatusclr,
+        EPSTATUSCLR,
+        epstatusclr0,
+        epstatusclr1,
+        epstatusclr2,
+        epstatusclr3,
+        epstatusclr4,
+        epstatusclr5,
+        epstatusclr6,
+        epstatusclr7
+    );
     ep!(
         epintflag, EPINTFLAG, epintflag0, epintflag1, epintflag2, epintflag3, epintflag4,
         epintflag5, epintflag6, epintflag7
@@ -559,6 +628,20 @@ impl Inner {
         })
     }
 
+    /// Returns the direction of the endpoint pair at `idx` if it is
+    /// double buffered, in which case both banks are used for that direction.
+    fn dual_bank(&self, idx: usize) -> Option<UsbDirection> {
+        let endpoints = self.endpoints.borrow();
+        let info = &endpoints.endpoints[idx];
+        if info.bank0.ep_type == EndpointTypeBits::DualBank {
+            Some(UsbDirection::In)
+        } else if info.bank1.ep_type == EndpointTypeBits::DualBank {
+            Some(UsbDirection::Out)
+        } else {
+            None
+        }
+    }
+
     fn bank1(&'_ self, ep: EndpointAddress) -> UsbResult<Bank<'_, InBank>> {
         if ep.is_out() {
             return Err(UsbError::InvalidEndpoint);
@@ -579,12 +662,38 @@ impl Inner {
 }
 
 impl UsbBus {
+    /// Creates the bus, with a static pool for the endpoint buffers of 512
+    /// bytes on SAMD11 and 2048 bytes on SAMD21
+    ///
+    /// Panics if called more than once, as the pool is a singleton.
     pub fn new(
+        clock: &clock::UsbClock,
+        pm: &mut PM,
+        dm_pad: impl AnyPin<Id = PA24>,
+        dp_pad: impl AnyPin<Id = PA25>,
+        usb: USB,
+    ) -> Self {
+        Self::with_endpoint_memory(clock, pm, dm_pad, dp_pad, usb, buffer())
+    }
+
+    /// Creates the bus, allocating the endpoint buffers from `buffers`
+    ///
+    /// The pool must hold the buffers of every endpoint the classes
+    /// allocate, plus up to 3 bytes of padding per buffer to keep them 32-bit
+    /// aligned. Endpoint allocation fails with
+    /// [`UsbError::EndpointMemoryOverflow`] once the pool is exhausted.
+    ///
+    /// ```no_run
+    /// let buffers = cortex_m::singleton!(: [u8; 1024] = [0; 1024]).unwrap();
+    /// let bus = UsbBus::with_endpoint_memory(&usb_clock, &mut peripherals.PM, dm, dp, usb, buffers);
+    /// ```
+    pub fn with_endpoint_memory(
         _clock: &clock::UsbClock,
         pm: &mut PM,
         dm_pad: impl AnyPin<Id = PA24>,
         dp_pad: impl AnyPin<Id = PA25>,
         _usb: USB,
+        buffers: &'static mut [u8],
     ) -> Self {
         dbgprint!("******** UsbBus::new\n");
         pm.apbbmask.modify(|_, w| w.usb_().set_bit());
@@ -595,8 +704,9 @@ impl UsbBus {
             _dm_pad: dm_pad.into().into_mode::<AlternateG>(),
             _dp_pad: dp_pad.into().into_mode::<AlternateG>(),
             desc,
-            buffers: RefCell::new(BufferAllocator::new()),
+            buffers: RefCell::new(BufferAllocator::new(buffers)),
             endpoints: RefCell::new(AllEndpoints::new()),
+            power: Cell::new(PowerState::new()),
         };
 
         Self {
@@ -607,7 +717,15 @@ impl UsbBus {
 
 impl Inner {
     fn usb(&self) -> &DEVICE {
-        unsafe { &(*USB::ptr()).device() }
+        // The SAMD11 USB peripheral has no host mode
+        #[cfg(feature = "samd11")]
+        unsafe {
+            &(*USB::ptr()).device
+        }
+        #[cfg(feature = "samd21")]
+        unsafe {
+            (*USB::ptr()).device()
+        }
     }
 
     fn set_stall<EP: Into<EndpointAddress>>(&self, ep: EP, stall: bool) {
@@ -691,7 +809,9 @@ impl Inner {
             w.transp().bits(usb_transp_cal());
             w.trim().bits(usb_trim_cal())
         });
-        usb.qosctrl.modify(|_, w| {
+        // The field writers are only unsafe in the SAMD11 PAC
+        #[allow(unused_unsafe)]
+        usb.qosctrl.modify(|_, w| unsafe {
             w.dqos().bits(0b11);
             w.cqos().bits(0b11)
         });
@@ -709,6 +829,7 @@ impl Inner {
         usb.intflag
             .write(|w| unsafe { w.bits(usb.intflag.read().bits()) });
         usb.intenset.write(|w| w.eorst().set_bit());
+        self.flush_power();
 
         // Configure the endpoints before we attach, as hosts may enumerate
         // before attempting a USB protocol reset.
@@ -726,6 +847,72 @@ impl Inner {
         }
     }
 
+    /// Writes the suspend and LPM configuration to the peripheral. It is lost
+    /// on the software reset performed by enable().
+    fn flush_power(&self) {
+        let power = self.power.get();
+        let usb = self.usb();
+        usb.ctrlb.modify(|_, w| {
+            if power.lpm {
+                w.lpmhdsk().ack()
+            } else {
+                w.lpmhdsk().no()
+            }
+        });
+        if power.suspend {
+            usb.intflag.write(|w| w.suspend().set_bit());
+            usb.intenset.write(|w| w.suspend().set_bit());
+        } else {
+            usb.intenclr.write(|w| w.suspend().set_bit());
+        }
+        if power.lpm {
+            usb.intflag.write(|w| w.lpmsusp().set_bit());
+            usb.intenset.write(|w| w.lpmsusp().set_bit());
+        } else {
+            usb.intenclr.write(|w| w.lpmsusp().set_bit());
+        }
+    }
+
+    fn set_power(&self, f: impl FnOnce(&mut PowerState)) {
+        let mut power = self.power.get();
+        f(&mut power);
+        self.power.set(power);
+        self.flush_power();
+    }
+
+    /// Records a new link state. Leaving a suspended state disables the
+    /// resume interrupts and runs the resume callback.
+    fn set_link_state(&self, link: LinkState) {
+        let mut power = self.power.get();
+        let resumed = power.link != LinkState::On && link == LinkState::On;
+        power.link = link;
+        self.power.set(power);
+        if resumed {
+            self.usb().intenclr.write(|w| {
+                w.wakeup().set_bit();
+                w.eorsm().set_bit();
+                w.uprsm().set_bit()
+            });
+            if let Some(callback) = power.on_resume {
+                callback();
+            }
+        }
+    }
+
+    /// Starts upstream resume signaling, if the link is suspended and the
+    /// host allowed it for L1 sleep.
+    fn remote_wakeup(&self) -> bool {
+        let allowed = match self.power.get().link {
+            LinkState::On => false,
+            LinkState::Suspend => true,
+            LinkState::Sleep => self.desc.borrow_mut().bank(0, 0).remote_wake(),
+        };
+        if allowed {
+            self.usb().ctrlb.modify(|_, w| w.uprsm().set_bit());
+        }
+        allowed
+    }
+
     /// Configures all endpoints based on prior calls to alloc_ep().
     fn flush_eps(&self, mode: FlushConfigMode) {
         for idx in 0..8 {
@@ -801,9 +988,27 @@ impl Inner {
 
     fn suspend(&self) {
         dbgprint!("UsbBus::suspend\n");
+        // Bus activity raises WAKEUP, the end of a host initiated resume raises
+        // EORSM, and the end of our own resume signaling raises UPRSM. WAKEUP
+        // is asynchronous, and wakes the CPU from standby.
+        let usb = self.usb();
+        usb.intflag.write(|w| {
+            w.wakeup().set_bit();
+            w.eorsm().set_bit();
+            w.uprsm().set_bit()
+        });
+        usb.intenset.write(|w| {
+            w.wakeup().set_bit();
+            w.eorsm().set_bit();
+            w.uprsm().set_bit()
+        });
+        if let Some(callback) = self.power.get().on_suspend {
+            callback();
+        }
     }
     fn resume(&self) {
         dbgprint!("UsbBus::resume\n");
+        self.set_link_state(LinkState::On);
     }
 
     fn alloc_ep(
@@ -862,6 +1067,165 @@ impl Inner {
         Ok(addr)
     }
 
+    /// Uses the bank of the other direction as a second buffer for `ep`.
+    ///
+    /// In this ping-pong mode, the hardware alternates between the banks,
+    /// and EPSTATUS.CURBK holds the bank used by the next transaction.
+    fn enable_double_buffering(&mut self, ep: EndpointAddress) -> UsbResult<()> {
+        let idx = ep.index();
+        let config = {
+            let endpoints = self.endpoints.borrow();
+            let info = &endpoints.endpoints[idx];
+            let (config, other) = if ep.is_in() {
+                (info.bank1, info.bank0)
+            } else {
+                (info.bank0, info.bank1)
+            };
+            match config.ep_type {
+                EndpointTypeBits::Isochronous | EndpointTypeBits::Bulk => (),
+                EndpointTypeBits::Disabled | EndpointTypeBits::DualBank => {
+                    return Err(UsbError::InvalidEndpoint)
+                }
+                _ => return Err(UsbError::Unsupported),
+            }
+            match other.ep_type {
+                EndpointTypeBits::DualBank => return Ok(()),
+                EndpointTypeBits::Disabled => (),
+                _ => return Err(UsbError::EndpointOverflow),
+            }
+            config
+        };
+
+        let buffer = self
+            .buffers
+            .borrow_mut()
+            .allocate_buffer(config.allocated_size)?;
+        let second = EPConfig {
+            ep_type: EndpointTypeBits::DualBank,
+            addr: buffer as usize,
+            ..config
+        };
+        {
+            let mut endpoints = self.endpoints.borrow_mut();
+            let info = &mut endpoints.endpoints[idx];
+            if ep.is_in() {
+                info.bank0 = second;
+            } else {
+                info.bank1 = second;
+            }
+        }
+
+        dbgprint!("UsbBus::enable_double_buffering ep={:?}\n", ep);
+
+        self.flush_ep(idx);
+        self.setup_ep_interrupts(EndpointAddress::from_parts(idx, UsbDirection::Out));
+        self.setup_ep_interrupts(EndpointAddress::from_parts(idx, UsbDirection::In));
+        Ok(())
+    }
+
+    /// Fills a free bank of a double buffered IN endpoint. If both are free,
+    /// the bank that the hardware sends next is used.
+    fn write_dual(&self, ep: EndpointAddress, buf: &[u8]) -> UsbResult<usize> {
+        let idx = ep.index();
+        let status = self.epstatus(idx).read();
+        let bank = match (status.bk0rdy().bit(), status.bk1rdy().bit()) {
+            (true, true) => return Err(UsbError::WouldBlock),
+            (true, false) => 1,
+            (false, true) => 0,
+            (false, false) => status.curbk().bit() as usize,
+        };
+
+        let allocated_size = self.endpoints.borrow().endpoints[idx].bank1.allocated_size;
+        let size = buf.len().min(allocated_size as usize);
+        {
+            let mut desc = self.desc.borrow_mut();
+            let desc = desc.bank(idx, bank);
+            unsafe {
+                buf.as_ptr()
+                    .copy_to_nonoverlapping(desc.get_address(), size);
+            }
+            desc.set_multi_packet_size(0);
+            desc.set_byte_count(size as u16);
+        }
+
+        if bank == 0 {
+            self.epintflag(idx)
+                .write(|w| w.trcpt0().set_bit().trfail0().set_bit());
+            self.epstatusset(idx).write(|w| w.bk0rdy().set_bit());
+        } else {
+            self.epintflag(idx)
+                .write(|w| w.trcpt1().set_bit().trfail1().set_bit());
+            self.epstatusset(idx).write(|w| w.bk1rdy().set_bit());
+        }
+
+        dbgprint!(
+            "UsbBus::write {} bytes to ep {:?} bank {}\n",
+            size,
+            ep,
+            bank
+        );
+
+        Ok(size)
+    }
+
+    /// Reads the oldest full bank of a double buffered OUT endpoint.
+    fn read_dual(&self, ep: EndpointAddress, buf: &mut [u8]) -> UsbResult<usize> {
+        let idx = ep.index();
+        let status = self.epstatus(idx).read();
+        // With both banks full, the hardware points to the one it filled
+        // first, as it is the next one to be freed.
+        let bank = match (status.bk0rdy().bit(), status.bk1rdy().bit()) {
+            (false, false) => return Err(UsbError::WouldBlock),
+            (true, false) => 0,
+            (false, true) => 1,
+            (true, true) => status.curbk().bit() as usize,
+        };
+
+        let size = {
+            let mut desc = self.desc.borrow_mut();
+            let desc = desc.bank(idx, bank);
+            let size = desc.get_byte_count() as usize;
+            if size <= buf.len() {
+                unsafe {
+                    desc.get_address()
+                        .copy_to_nonoverlapping(buf.as_mut_ptr(), size);
+                }
+            }
+            desc.set_byte_count(0);
+            desc.set_multi_packet_size(0);
+            size
+        };
+
+        // The bank is released even if the packet did not fit, like for
+        // single bank endpoints.
+        if bank == 0 {
+            self.epintflag(idx)
+                .write(|w| w.trcpt0().set_bit().trfail0().set_bit());
+            self.epstatusclr(idx).write(|w| w.bk0rdy().set_bit());
+        } else {
+            self.epintflag(idx)
+                .write(|w| w.trcpt1().set_bit().trfail1().set_bit());
+            self.epstatusclr(idx).write(|w| w.bk1rdy().set_bit());
+        }
+
+        if size > buf.len() {
+            return Err(UsbError::BufferOverflow);
+        }
+
+        dbgprint!(
+            "UsbBus::read {} bytes from ep {:?} bank {}\n",
+            size,
+            ep,
+            bank
+        );
+
+        Ok(size)
+    }
+
+    fn frame_number(&self) -> u16 {
+        self.usb().fnum.read().fnum().bits()
+    }
+
     fn set_device_address(&self, addr: u8) {
         dbgprint!("UsbBus::set_device_address addr={}\n", addr);
         self.usb()
@@ -882,12 +1246,43 @@ impl Inner {
         if intflags.eorst().bit() {
             // end of reset interrupt
             self.usb().intflag.write(|w| w.eorst().set_bit());
+            // A reset also ends a suspend.
+            self.set_link_state(LinkState::On);
             dbgprint!("PollResult::Reset\n");
             return PollResult::Reset;
         }
         // As the suspend & wakup interrupts/states cannot distinguish between
-        // unconnected & unsuspended, we do not handle them to avoid spurious
-        // transitions.
+        // unconnected & unsuspended, they are only handled once enabled with
+        // UsbBus::enable_suspend() or UsbBus::enable_lpm().
+        let power = self.power.get();
+        if power.link == LinkState::On {
+            let link = if power.suspend && intflags.suspend().bit() {
+                Some(LinkState::Suspend)
+            } else if power.lpm && intflags.lpmsusp().bit() {
+                Some(LinkState::Sleep)
+            } else {
+                None
+            };
+            if let Some(link) = link {
+                self.usb().intflag.write(|w| {
+                    w.suspend().set_bit();
+                    w.lpmsusp().set_bit()
+                });
+                let mut power = power;
+                power.link = link;
+                self.power.set(power);
+                dbgprint!("PollResult::Suspend\n");
+                return PollResult::Suspend;
+            }
+        } else if intflags.wakeup().bit() || intflags.eorsm().bit() || intflags.uprsm().bit() {
+            self.usb().intflag.write(|w| {
+                w.wakeup().set_bit();
+                w.eorsm().set_bit();
+                w.uprsm().set_bit()
+            });
+            dbgprint!("PollResult::Resume\n");
+            return PollResult::Resume;
+        }
 
         let intbits = self.usb().epintsmry.read().bits();
         if intbits == 0 {
@@ -906,6 +1301,31 @@ impl Inner {
 
             let idx = ep as usize;
 
+            // Both banks of a double buffered endpoint complete transfers in
+            // the same direction.
+            match self.dual_bank(idx) {
+                Some(UsbDirection::In) => {
+                    let intflag = self.epintflag(idx).read();
+                    if intflag.trcpt0().bit() || intflag.trcpt1().bit() {
+                        self.epintflag(idx).write(|w| {
+                            w.trcpt0().set_bit().trfail0().set_bit();
+                            w.trcpt1().set_bit().trfail1().set_bit()
+                        });
+                        ep_in_complete |= mask;
+                    }
+                    continue;
+                }
+                Some(UsbDirection::Out) => {
+                    // The flags are cleared as the banks are read.
+                    let intflag = self.epintflag(idx).read();
+                    if intflag.trcpt0().bit() || intflag.trcpt1().bit() {
+                        ep_out |= mask;
+                    }
+                    continue;
+                }
+                None => (),
+            }
+
             let bank1 = self
                 .bank1(EndpointAddress::from_parts(idx, UsbDirection::In))
                 .unwrap();
@@ -951,6 +1371,9 @@ impl Inner {
     }
 
     fn write(&self, ep: EndpointAddress, buf: &[u8]) -> UsbResult<usize> {
+        if ep.is_in() && self.dual_bank(ep.index()) == Some(UsbDirection::In) {
+            return self.write_dual(ep, buf);
+        }
         let mut bank = self.bank1(ep)?;
 
         if bank.is_ready() {
@@ -982,6 +1405,9 @@ impl Inner {
     }
 
     fn read(&self, ep: EndpointAddress, buf: &mut [u8]) -> UsbResult<usize> {
+        if ep.is_out() && self.dual_bank(ep.index()) == Some(UsbDirection::Out) {
+            return self.read_dual(ep, buf);
+        }
         let mut bank = self.bank0(ep)?;
         let rxstp = bank.received_setup_interrupt();
 
@@ -1049,6 +1475,141 @@ impl UsbBus {
     pub fn check_sof_interrupt(&self) -> bool {
         disable_interrupts(|cs| self.inner.borrow(cs).borrow_mut().check_sof_interrupt())
     }
+
+    /// Double buffers an isochronous or bulk endpoint
+    ///
+    /// The endpoint also uses the bank of the other direction at the same
+    /// index, which must not be allocated, and a second buffer. The host
+    /// can then transfer a packet while the previous one is processed, which
+    /// isochronous streams such as USB audio need to avoid dropping packets.
+    ///
+    /// Call this once the `UsbDevice` is built, through `UsbDevice::bus`,
+    /// before the host configures the device.
+    pub fn enable_double_buffering(&self, ep: EndpointAddress) -> UsbResult<()> {
+        disable_interrupts(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow_mut()
+                .enable_double_buffering(ep)
+        })
+    }
+
+    /// Returns the number of bytes left in the endpoint memory pool
+    ///
+    /// Once the classes are built, this is the memory that could be saved
+    /// with a smaller pool, see [`UsbBus::with_endpoint_memory`].
+    pub fn endpoint_memory_free(&self) -> usize {
+        disable_interrupts(|cs| self.inner.borrow(cs).borrow().buffers.borrow().free())
+    }
+
+    /// Returns the 11-bit number of the last start-of-frame (SOF) packet
+    ///
+    /// The frame number increments every millisecond, and can be used with
+    /// the SOF interrupt to measure rates against the host clock, see
+    /// [`Feedback`](super::Feedback).
+    pub fn frame_number(&self) -> u16 {
+        disable_interrupts(|cs| self.inner.borrow(cs).borrow().frame_number())
+    }
+
+    /// Enables suspend and resume detection
+    ///
+    /// `UsbDevice::poll` then moves the device to the `Suspend` state after 3
+    /// ms of bus inactivity, and back on resume. The peripheral cannot tell a
+    /// suspended bus from an unplugged cable, so an unplugged device also
+    /// reports a suspend.
+    ///
+    /// The `USB` interrupt is raised on suspend and resume. Since the resume
+    /// interrupt can wake the CPU from standby, a device may enter standby
+    /// while suspended, as long as the USB clock keeps running in standby.
+    pub fn enable_suspend(&self) {
+        disable_interrupts(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow()
+                .set_power(|power| power.suspend = true)
+        })
+    }
+
+    /// Disables suspend and resume detection
+    pub fn disable_suspend(&self) {
+        disable_interrupts(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow()
+                .set_power(|power| power.suspend = false)
+        })
+    }
+
+    /// Enables Link Power Management
+    ///
+    /// LPM transactions from the host are acknowledged, and the link enters
+    /// L1 sleep, which is reported as a suspend by `UsbDevice::poll`. The
+    /// host only sends them if the device advertises LPM support with a BOS
+    /// descriptor, and a `bcdUSB` of 0x0201.
+    pub fn enable_lpm(&self) {
+        disable_interrupts(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow()
+                .set_power(|power| power.lpm = true)
+        })
+    }
+
+    /// Disables Link Power Management
+    ///
+    /// LPM transactions are no longer answered.
+    pub fn disable_lpm(&self) {
+        disable_interrupts(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow()
+                .set_power(|power| power.lpm = false)
+        })
+    }
+
+    /// Sets a function to call when the bus is suspended
+    ///
+    /// The callback runs from `UsbDevice::poll` with interrupts disabled. It
+    /// can gate the clocks of peripherals that are not needed while
+    /// suspended, to meet the 2.5 mA suspend current.
+    pub fn on_suspend(&self, callback: fn()) {
+        disable_interrupts(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow()
+                .set_power(|power| power.on_suspend = Some(callback))
+        })
+    }
+
+    /// Sets a function to call when the bus resumes from a suspend
+    ///
+    /// The callback runs from `UsbDevice::poll` with interrupts disabled, and
+    /// should restore the clocks gated by the suspend callback.
+    pub fn on_resume(&self, callback: fn()) {
+        disable_interrupts(|cs| {
+            self.inner
+                .borrow(cs)
+                .borrow()
+                .set_power(|power| power.on_resume = Some(callback))
+        })
+    }
+
+    /// Returns the link power state
+    pub fn link_state(&self) -> LinkState {
+        disable_interrupts(|cs| self.inner.borrow(cs).borrow().power.get().link)
+    }
+
+    /// Signals a remote wakeup to the host
+    ///
+    /// Returns `false` if the link is not suspended, or if the host did not
+    /// allow remote wakeup when putting the link in L1 sleep. From L2
+    /// suspend, the host must have enabled the remote wakeup feature, which
+    /// can be checked with `UsbDevice::remote_wakeup_enabled`, and the bus
+    /// must have been suspended for at least 5 ms. The USB clock must be
+    /// running.
+    pub fn remote_wakeup(&self) -> bool {
+        disable_interrupts(|cs| self.inner.borrow(cs).borrow().remote_wakeup())
+    }
 }
 
 impl usb_device::bus::UsbBus for UsbBus {
@@ -0,0 +1,88 @@
+//! Feedback endpoint helper for asynchronous isochronous streams
+//!
+//! An asynchronous USB audio sink consumes samples at the rate of its own
+//! clock, and reports that rate to the host through an isochronous feedback
+//! endpoint, so that the host sends samples at the same rate. The rate is
+//! measured against the 1 ms start-of-frame (SOF) packets of the host, as a
+//! number of samples per frame in 10.14 fixed point.
+//!
+//! ```
+//! // 48 kHz, refreshed every 2^3 = 8 frames (bRefresh = 3)
+//! let mut feedback = Feedback::new(48, 3);
+//!
+//! // In the SOF interrupt handler
+//! if let Some(_) = feedback.update(usb_bus.frame_number(), samples_consumed) {
+//!     feedback_ep.write(&feedback.to_bytes()).ok();
+//! }
+//! ```
+
+/// Measures the rate of consumption of samples, in samples per frame
+pub struct Feedback {
+    refresh: u8,
+    start: Option<u16>,
+    samples: u32,
+    value: u32,
+}
+
+impl Feedback {
+    /// Create a feedback measure
+    ///
+    /// `nominal_rate` is the expected number of samples per frame, reported
+    /// until the first measurement completes. The rate is measured over
+    /// 2^`refresh` frames, the `bRefresh` of the endpoint descriptor.
+    ///
+    /// Panics if `refresh` is greater than 9.
+    pub fn new(nominal_rate: u32, refresh: u8) -> Self {
+        assert!(refresh <= 9);
+        Self {
+            refresh,
+            start: None,
+            samples: 0,
+            value: nominal_rate << 14,
+        }
+    }
+
+    /// Add the samples consumed since the last call, at the SOF of `frame`
+    ///
+    /// Returns the new feedback value once the measure period has elapsed.
+    /// The samples passed on the first call are not counted, as they were not
+    /// consumed during a known number of frames.
+    pub fn update(&mut self, frame: u16, samples: u32) -> Option<u32> {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.start = Some(frame);
+                self.samples = 0;
+                return None;
+            }
+        };
+        self.samples += samples;
+
+        // The frame number is 11 bits wide
+        let frames = frame.wrapping_sub(start) & 0x7FF;
+        if frames < 1 << self.refresh {
+            return None;
+        }
+        self.value = (((self.samples as u64) << 14) / frames as u64) as u32;
+        self.start = Some(frame);
+        self.samples = 0;
+        Some(self.value)
+    }
+
+    /// Last feedback value, in samples per frame in 10.14 fixed point
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    /// Last feedback value, as sent by a full speed feedback endpoint
+    pub fn to_bytes(&self) -> [u8; 3] {
+        let bytes = self.value.to_le_bytes();
+        [bytes[0], bytes[1], bytes[2]]
+    }
+
+    /// Restart the measure, e.g. when the stream is restarted
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.samples = 0;
+    }
+}
@@ -1,15 +1,26 @@
-//! USB Device support
+//! USB Device and Host support
+//!
+//! Host mode is only available on SAMD21, the SAMD11D USB peripheral is device
+//! only.
 
 use crate::gpio;
 
 pub use usb_device;
 
 mod bus;
-pub use self::bus::UsbBus;
+pub use self::bus::{LinkState, UsbBus};
+
+mod feedback;
+pub use self::feedback::Feedback;
 
 mod devicedesc;
 use self::devicedesc::Descriptors;
 
+#[cfg(feature = "samd21")]
+pub mod host;
+#[cfg(feature = "samd21")]
+pub use self::host::UsbHost;
+
 /// Emit SOF at 1Khz on this pin when configured as function G
 pub type SofPad = gpio::Pa23<gpio::PfG>;
 
@@ -7,9 +7,10 @@ use crate::gpio::v2::*;
 use crate::hal::adc::{Channel, OneShot};
 use crate::pac::gclk::genctrl::SRC_A::DFLL;
 use crate::pac::gclk::pchctrl::GEN_A;
-use crate::pac::{adc0, ADC0, ADC1, MCLK};
+use crate::pac::{adc0, ADC0, ADC1, MCLK, SUPC};
 
 use crate::calibration;
+use crate::nvm::TemperaturesCalibrationArea;
 
 /// Samples per reading
 pub use adc0::avgctrl::SAMPLENUM_A as SampleRate;
@@ -45,6 +46,20 @@ pub trait ConversionMode<ADC> {
 pub struct SingleConversion;
 pub struct FreeRunning;
 
+/// Selects how conversions are started while results are streamed into
+/// memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamTrigger {
+    /// A new conversion is started as soon as the previous one completes
+    FreeRunning,
+    /// Each conversion is started by an event on the ADC `START` event input,
+    /// e.g. a TC overflow routed through EVSYS
+    Event,
+}
+
+#[cfg(feature = "dma")]
+pub mod dma;
+
 macro_rules! adc_hal {
     ($($ADC:ident: ($init:ident, $mclk:ident, $apmask:ident, $compcal:ident, $refcal:ident, $r2rcal:ident),)+) => {
         $(
@@ -146,7 +161,7 @@ impl Adc<$ADC> {
     }
 
     fn disable_freerunning(&mut self) {
-        self.adc.ctrlb.modify(|_, w| w.freerun().set_bit());
+        self.adc.ctrlb.modify(|_, w| w.freerun().clear_bit());
         while self.adc.syncbusy.read().ctrlb().bit_is_set() {}
     }
 
@@ -185,6 +200,97 @@ impl Adc<$ADC> {
         while self.adc.syncbusy.read().inputctrl().bit_is_set() {}
         self.adc.inputctrl.modify(|_, w| w.muxpos().bits(chan));
     }
+
+    /// Sets the mux to a pair of channels and switches the ADC to
+    /// differential mode.
+    fn mux_differential<P, N>(&mut self, _pos: &mut P, _neg: &mut N)
+    where
+        P: Channel<$ADC, ID=u8>,
+        N: NegativeChannel<$ADC>,
+    {
+        let pos = P::channel();
+        let neg = N::channel();
+        while self.adc.syncbusy.read().inputctrl().bit_is_set() {}
+        self.adc.inputctrl.modify(|_, w| {
+            w.muxpos().bits(pos);
+            unsafe { w.muxneg().bits(neg) };
+            w.diffmode().set_bit()
+        });
+    }
+
+    /// Restores the single-ended configuration, with the negative input
+    /// connected to the internal ground.
+    fn mux_single_ended(&mut self) {
+        while self.adc.syncbusy.read().inputctrl().bit_is_set() {}
+        self.adc.inputctrl.modify(|_, w| {
+            w.muxneg().gnd();
+            w.diffmode().clear_bit()
+        });
+        while self.adc.syncbusy.read().inputctrl().bit_is_set() {}
+    }
+
+    /// Perform a differential conversion of `pos` relative to `neg`.
+    ///
+    /// The result is a signed, two's complement value that is negative
+    /// whenever the voltage on `neg` exceeds the voltage on `pos`.
+    pub fn read_differential<P, N>(&mut self, pos: &mut P, neg: &mut N) -> i16
+    where
+        P: Channel<$ADC, ID=u8>,
+        N: NegativeChannel<$ADC>,
+    {
+        self.mux_differential(pos, neg);
+        self.power_up();
+        let result = self.synchronous_convert();
+        self.power_down();
+        self.mux_single_ended();
+        result as i16
+    }
+
+    /// Read the CPU temperature in degrees Celsius.
+    ///
+    /// The temperature is measured through the [`Ptat`] and [`Ctat`] sensors
+    /// with the internal 1.0V reference, then corrected using the factory
+    /// calibration values stored in the NVM temperature calibration area (see
+    /// [`Nvm::temperatures_calibration_area`](crate::nvm::Nvm::temperatures_calibration_area)).
+    /// The reference, resolution and averaging settings of the ADC and the
+    /// `SUPC.VREF` register are restored afterwards.
+    pub fn read_cpu_temperature(&mut self, supc: &mut SUPC) -> f32 {
+        let vref = supc.vref.read().bits();
+        let refctrl = self.adc.refctrl.read().bits();
+        let ctrlb = self.adc.ctrlb.read().bits();
+        let avgctrl = self.adc.avgctrl.read().bits();
+
+        supc.vref.modify(|_, w| {
+            w.tsen().set_bit();
+            w.ondemand().set_bit();
+            w.sel()._1v0()
+        });
+        self.reference(Reference::INTREF);
+        self.resolution(Resolution::_12BIT);
+        self.samples(SampleRate::_1);
+
+        self.mux(&mut Ptat);
+        self.power_up();
+        // The first conversion after changing the reference must be discarded
+        self.synchronous_convert();
+        let tp = self.synchronous_convert() as f32;
+        self.power_down();
+
+        self.mux(&mut Ctat);
+        self.power_up();
+        let tc = self.synchronous_convert() as f32;
+        self.power_down();
+
+        self.adc.avgctrl.write(|w| unsafe { w.bits(avgctrl) });
+        while self.adc.syncbusy.read().avgctrl().bit_is_set() {}
+        self.adc.ctrlb.write(|w| unsafe { w.bits(ctrlb) });
+        while self.adc.syncbusy.read().ctrlb().bit_is_set() {}
+        self.adc.refctrl.write(|w| unsafe { w.bits(refctrl) });
+        while self.adc.syncbusy.read().refctrl().bit_is_set() {}
+        supc.vref.write(|w| unsafe { w.bits(vref) });
+
+        temperature_from_ptat_ctat(&crate::nvm::read_temperatures_calibration_area(), tp, tc)
+    }
 }
 
 impl ConversionMode<$ADC> for SingleConversion  {
@@ -267,11 +373,141 @@ where
     }
 }
 
+/// Convert a pair of PTAT and CTAT readings to a temperature in degrees
+/// Celsius, using the formula given in section 45.6.3.1 of the datasheet.
+fn temperature_from_ptat_ctat(cal: &TemperaturesCalibrationArea, tp: f32, tc: f32) -> f32 {
+    let tl = cal.tli() as f32 + decimal_to_fraction(cal.tld());
+    let th = cal.thi() as f32 + decimal_to_fraction(cal.thd());
+    let vpl = cal.vpl() as f32;
+    let vph = cal.vph() as f32;
+    let vcl = cal.vcl() as f32;
+    let vch = cal.vch() as f32;
+
+    (tl * vph * tc - vpl * th * tc - tl * vch * tp + th * vcl * tp)
+        / (vcl * tp - vch * tp - vpl * tc + vph * tc)
+}
+
+/// The calibration area stores the decimal part of a temperature as the
+/// digits following the decimal point.
+fn decimal_to_fraction(val: u32) -> f32 {
+    let val = val as f32;
+    if val < 10.0 {
+        val / 10.0
+    } else {
+        val / 100.0
+    }
+}
+
 adc_hal! {
     ADC0: (adc0, apbdmask, adc0_, adc0_biascomp_scale_cal, adc0_biasref_scale_cal, adc0_biasr2r_scale_cal),
     ADC1: (adc1, apbdmask, adc1_, adc1_biascomp_scale_cal, adc1_biasref_scale_cal, adc1_biasr2r_scale_cal),
 }
 
+/// `ADC0` and `ADC1` linked in a master/slave configuration.
+///
+/// `ADC1` is configured as a slave of `ADC0`: it is enabled together with its
+/// master and shares its clock and start trigger, so both inputs of a pair
+/// are sampled at exactly the same instant. This is useful to measure
+/// related quantities, such as the voltage and current of a load.
+///
+/// Resolution, averaging and reference are still configured individually on
+/// each [`Adc`] before linking them. The prescaler of `ADC0` applies to both.
+pub struct DualAdc {
+    master: Adc<ADC0>,
+    slave: Adc<ADC1>,
+}
+
+impl DualAdc {
+    /// Link `ADC0` and `ADC1`, making `ADC1` a slave of `ADC0`.
+    pub fn new(master: Adc<ADC0>, mut slave: Adc<ADC1>) -> Self {
+        // SLAVEEN is enable-protected
+        slave.power_down();
+        slave.adc.ctrla.modify(|_, w| w.slaveen().set_bit());
+
+        Self { master, slave }
+    }
+
+    /// Unlink the ADCs and return them, so they can be used independently.
+    pub fn free(mut self) -> (Adc<ADC0>, Adc<ADC1>) {
+        self.master.power_down();
+        self.slave.adc.ctrla.modify(|_, w| w.slaveen().clear_bit());
+        (self.master, self.slave)
+    }
+
+    /// Sample `pin0` on `ADC0` and `pin1` on `ADC1` simultaneously, blocking
+    /// until both results are available.
+    pub fn read<P0, P1>(&mut self, pin0: &mut P0, pin1: &mut P1) -> (u16, u16)
+    where
+        P0: Channel<ADC0, ID = u8>,
+        P1: Channel<ADC1, ID = u8>,
+    {
+        self.start_conversion(pin0, pin1);
+        while self.master.adc.intflag.read().resrdy().bit_is_clear()
+            || self.slave.adc.intflag.read().resrdy().bit_is_clear()
+        {}
+        let results = self.take_results();
+        self.master.power_down();
+
+        results
+    }
+
+    /// Start a simultaneous conversion of `pin0` on `ADC0` and `pin1` on
+    /// `ADC1`.
+    ///
+    /// The results can be retrieved with
+    /// [`service_interrupt_ready`](Self::service_interrupt_ready) once the
+    /// `ADC0_RESRDY` or `ADC1_RESRDY` interrupts fire. The ADCs stay powered
+    /// until [`stop_conversion`](Self::stop_conversion) is called.
+    pub fn start_conversion<P0, P1>(&mut self, pin0: &mut P0, pin1: &mut P1)
+    where
+        P0: Channel<ADC0, ID = u8>,
+        P1: Channel<ADC1, ID = u8>,
+    {
+        self.master.mux(pin0);
+        self.slave.mux(pin1);
+        // Enabling the master also enables the slave
+        self.master.power_up();
+        self.master.start_conversion();
+    }
+
+    /// Enable the result ready interrupt of both ADCs.
+    pub fn enable_interrupts(&mut self) {
+        self.master.enable_interrupts();
+        self.slave.enable_interrupts();
+    }
+
+    /// Disable the result ready interrupt of both ADCs.
+    pub fn disable_interrupts(&mut self) {
+        self.master.disable_interrupts();
+        self.slave.disable_interrupts();
+    }
+
+    /// Return the pair of results if both conversions have completed.
+    pub fn service_interrupt_ready(&mut self) -> Option<(u16, u16)> {
+        if self.master.adc.intflag.read().resrdy().bit_is_set()
+            && self.slave.adc.intflag.read().resrdy().bit_is_set()
+        {
+            Some(self.take_results())
+        } else {
+            None
+        }
+    }
+
+    /// Power down both ADCs.
+    pub fn stop_conversion(&mut self) {
+        self.master.power_down();
+    }
+
+    fn take_results(&mut self) -> (u16, u16) {
+        self.master.adc.intflag.write(|w| w.resrdy().set_bit());
+        self.slave.adc.intflag.write(|w| w.resrdy().set_bit());
+        (
+            self.master.adc.result.read().result().bits(),
+            self.slave.adc.result.read().result().bits(),
+        )
+    }
+}
+
 macro_rules! adc_pins {
     (
         $(
@@ -287,6 +523,72 @@ macro_rules! adc_pins {
     }
 }
 
+/// Marker trait for [`Channel`]s that can also be connected to the negative
+/// input of the ADC for differential conversions.
+///
+/// Only the analog inputs AIN0 to AIN7 of each ADC are available on the
+/// negative input multiplexer.
+pub trait NegativeChannel<ADC>: Channel<ADC, ID = u8> {}
+
+macro_rules! adc_negative_pins {
+    (
+        $(
+            $PinId:ident: $ADC:ident,
+        )+
+    ) => {
+        $(
+            impl NegativeChannel<$ADC> for Pin<$PinId, AlternateB> {}
+        )+
+    }
+}
+
+macro_rules! adc_internal_channels {
+    (
+        $(
+            $(#[$attr:meta])*
+            $Name:ident: $CHAN:literal,
+        )+
+    ) => {
+        $(
+            $(#[$attr])*
+            pub struct $Name;
+
+            impl Channel<ADC0> for $Name {
+                type ID = u8;
+                fn channel() -> u8 { $CHAN }
+            }
+
+            impl Channel<ADC1> for $Name {
+                type ID = u8;
+                fn channel() -> u8 { $CHAN }
+            }
+        )+
+    }
+}
+
+adc_internal_channels! {
+    /// Core supply voltage (VDDCORE), scaled by 1/4
+    ScaledCoreVcc: 0x18,
+    /// Battery backup supply voltage (VBAT), scaled by 1/4
+    ScaledVbat: 0x19,
+    /// I/O supply voltage (VDDIO), scaled by 1/4
+    ScaledIoVcc: 0x1A,
+    /// Internal bandgap voltage
+    Bandgap: 0x1B,
+    /// Temperature sensor, proportional to absolute temperature
+    ///
+    /// `SUPC.VREF.TSEN` must be set for the sensor to be powered. See
+    /// `read_cpu_temperature` for a calibrated reading.
+    Ptat: 0x1C,
+    /// Temperature sensor, complementary to absolute temperature
+    ///
+    /// `SUPC.VREF.TSEN` must be set for the sensor to be powered. See
+    /// `read_cpu_temperature` for a calibrated reading.
+    Ctat: 0x1D,
+    /// DAC output
+    DacOutput: 0x1E,
+}
+
 /// Implement [`Channel`] for [`v1::Pin`]s based on the implementations for
 /// `v2` [`Pin`]s
 #[allow(deprecated)]
@@ -301,6 +603,16 @@ where
     }
 }
 
+/// Implement [`NegativeChannel`] for [`v1::Pin`]s based on the
+/// implementations for `v2` [`Pin`]s
+#[allow(deprecated)]
+impl<I, A> NegativeChannel<A> for v1::Pin<I, v1::PfB>
+where
+    I: PinId,
+    Pin<I, AlternateB>: NegativeChannel<A>,
+{
+}
+
 adc_pins! {
     PA02: (ADC0, 0),
     PA03: (ADC0, 1),
@@ -348,3 +660,31 @@ adc_pins! {
     PD00: (ADC1, 14),
     PD01: (ADC1, 15),
 }
+
+adc_negative_pins! {
+    PA02: ADC0,
+    PA03: ADC0,
+    PB08: ADC0,
+    PB09: ADC0,
+    PA04: ADC0,
+    PA05: ADC0,
+    PA06: ADC0,
+    PA07: ADC0,
+
+    PB08: ADC1,
+    PB09: ADC1,
+    PA08: ADC1,
+    PA09: ADC1,
+}
+
+#[cfg(feature = "min-samd51j")]
+adc_negative_pins! {
+    PB04: ADC1,
+    PB05: ADC1,
+}
+
+#[cfg(feature = "min-samd51n")]
+adc_negative_pins! {
+    PC02: ADC1,
+    PC03: ADC1,
+}
@@ -0,0 +1,255 @@
+//! Use the DMA Controller to stream ADC conversion results into memory
+//!
+//! [`Adc`] implements [`Buffer`], so its `RESULT` register can be used as the
+//! source of a DMA [`Transfer`]. Two ways of streaming are offered:
+//!
+//! * [`Adc::stream_with_dma`] repeatedly converts a single input, either in
+//!   free-running mode or started by an event on the ADC `START` event input
+//!   (e.g. a TC overflow routed through EVSYS), and writes every result into a
+//!   buffer.
+//!
+//! * [`Adc::sequence_with_dma`] uses the DMA sequencing feature of the ADC to
+//!   rewrite `INPUTCTRL` between conversions, so that several inputs are
+//!   scanned in turn. This requires two DMA channels: one writing the input
+//!   sequence into the `DSEQDATA` register, and one reading the results.
+//!
+//! ```
+//! let sequence: &'static mut [u32; 3] = cortex_m::singleton!(: [u32; 3] = [
+//!     Adc::<ADC0>::sequence_entry(&a0),
+//!     Adc::<ADC0>::sequence_entry(&a1),
+//!     Adc::<ADC0>::sequence_entry(&a2),
+//! ]).unwrap();
+//! let results: &'static mut [u16; 3] = cortex_m::singleton!(: [u16; 3] = [0; 3]).unwrap();
+//!
+//! let (seq_xfer, res_xfer) =
+//!     adc.sequence_with_dma(sequence, results, chan0, chan1, false, |_| {});
+//! let (chan1, mut adc, results) = res_xfer.wait();
+//! let (chan0, sequence, _) = seq_xfer.stop();
+//! adc.stop_sequence();
+//! ```
+
+use core::marker::PhantomData;
+
+use super::{Adc, StreamTrigger};
+use crate::{
+    dmac::{
+        self,
+        channel::{AnyChannel, Busy, CallbackStatus, Channel, InterruptFlags, Ready},
+        transfer::BufferPair,
+        Buffer, Transfer, TriggerAction, TriggerSource,
+    },
+    hal::adc::Channel as AdcChannel,
+    pac::{ADC0, ADC1},
+};
+
+/// Destination of a DMA sequencing transfer, representing the `DSEQDATA`
+/// register of an ADC
+///
+/// Instances can only be obtained through [`Adc::sequence_with_dma`].
+pub struct SequenceData<ADC> {
+    _adc: PhantomData<ADC>,
+}
+
+macro_rules! adc_dma {
+    ($($ADC:ident: ($resrdy:ident, $seq:ident),)+) => {
+        $(
+unsafe impl Buffer for Adc<$ADC> {
+    type Beat = u16;
+
+    #[inline]
+    fn dma_ptr(&mut self) -> *mut Self::Beat {
+        self.adc.result.as_ptr() as *mut _
+    }
+
+    #[inline]
+    fn incrementing(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        1
+    }
+}
+
+unsafe impl Buffer for SequenceData<$ADC> {
+    type Beat = u32;
+
+    #[inline]
+    fn dma_ptr(&mut self) -> *mut Self::Beat {
+        // SAFETY: Only the address of the register is taken
+        unsafe { (*$ADC::ptr()).dseqdata.as_ptr() as *mut _ }
+    }
+
+    #[inline]
+    fn incrementing(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        1
+    }
+}
+
+impl Adc<$ADC> {
+    /// DMA trigger source raised when a conversion result is ready
+    pub const DMA_RESULT_TRIGGER: TriggerSource = TriggerSource::$resrdy;
+
+    /// DMA trigger source raised when the ADC is ready to receive the next
+    /// DMA sequencing word
+    pub const DMA_SEQUENCE_TRIGGER: TriggerSource = TriggerSource::$seq;
+
+    /// Compute the `INPUTCTRL` word selecting `pin` as a single-ended input,
+    /// for use in the sequence passed to
+    /// [`sequence_with_dma`](Self::sequence_with_dma).
+    #[inline]
+    pub fn sequence_entry<P: AdcChannel<$ADC, ID = u8>>(_pin: &P) -> u32 {
+        // MUXNEG = GND
+        P::channel() as u32 | (0x18 << 8)
+    }
+
+    /// Start converting `pin` continuously, and transform the [`Adc`] into a
+    /// DMA [`Transfer`] writing each result into `buf`.
+    ///
+    /// If `circular` is `true`, the transfer wraps around to the start of
+    /// `buf` once it is full, and keeps running until it is stopped.
+    /// Otherwise, it completes once `buf` has been filled. The `Adc` is
+    /// recovered with [`Transfer::wait`] or [`Transfer::stop`], after which
+    /// [`stop_stream`](Self::stop_stream) must be called.
+    #[inline]
+    pub fn stream_with_dma<P, Ch, B, W>(
+        mut self,
+        pin: &mut P,
+        trigger: StreamTrigger,
+        buf: B,
+        mut channel: Ch,
+        circular: bool,
+        waker: W,
+    ) -> Transfer<Channel<Ch::Id, Busy>, BufferPair<Self, B>, W>
+    where
+        P: AdcChannel<$ADC, ID = u8>,
+        Ch: AnyChannel<Status = Ready>,
+        B: Buffer<Beat = u16> + 'static,
+        W: FnOnce(CallbackStatus) + 'static,
+    {
+        channel
+            .as_mut()
+            .enable_interrupts(InterruptFlags::new().with_tcmpl(true));
+
+        self.mux(pin);
+        self.start_stream(trigger);
+
+        // SAFETY: We use new_unchecked to avoid having to pass a 'static self as the
+        // source buffer. This is safe as long as we guarantee the destination
+        // buffer is static.
+        unsafe { dmac::Transfer::new_unchecked(channel, self, buf, circular) }
+            .with_waker(waker)
+            .begin(Self::DMA_RESULT_TRIGGER, TriggerAction::BURST)
+    }
+
+    /// Stop the conversions started by
+    /// [`stream_with_dma`](Self::stream_with_dma) and power down the ADC.
+    #[inline]
+    pub fn stop_stream(&mut self) {
+        self.power_down();
+        self.disable_freerunning();
+        self.adc.evctrl.modify(|_, w| w.startei().clear_bit());
+    }
+
+    /// Scan a sequence of inputs using DMA sequencing.
+    ///
+    /// Each word of `sequence` is written to `INPUTCTRL` by the DMA before a
+    /// conversion, which then starts automatically. Words can be computed
+    /// with [`sequence_entry`](Self::sequence_entry). Results are written to
+    /// `results` in the same order, so both buffers should have the same
+    /// length.
+    ///
+    /// Two transfers are returned: the sequencing transfer (running on
+    /// `seq_channel`) and the result transfer (running on `result_channel`).
+    /// The `waker` is attached to the result transfer. If `circular` is
+    /// `true`, both transfers run until they are stopped, scanning the
+    /// sequence over and over. Once both transfers are stopped, call
+    /// [`stop_sequence`](Self::stop_sequence) on the recovered `Adc`.
+    #[allow(clippy::type_complexity)]
+    #[inline]
+    pub fn sequence_with_dma<S, B, SCh, RCh, W>(
+        mut self,
+        sequence: S,
+        results: B,
+        seq_channel: SCh,
+        mut result_channel: RCh,
+        circular: bool,
+        waker: W,
+    ) -> (
+        Transfer<Channel<SCh::Id, Busy>, BufferPair<S, SequenceData<$ADC>>>,
+        Transfer<Channel<RCh::Id, Busy>, BufferPair<Self, B>, W>,
+    )
+    where
+        S: Buffer<Beat = u32> + 'static,
+        B: Buffer<Beat = u16> + 'static,
+        SCh: AnyChannel<Status = Ready>,
+        RCh: AnyChannel<Status = Ready>,
+        W: FnOnce(CallbackStatus) + 'static,
+    {
+        result_channel
+            .as_mut()
+            .enable_interrupts(InterruptFlags::new().with_tcmpl(true));
+
+        // Only INPUTCTRL is updated by the sequence, and a conversion starts
+        // as soon as it has been written
+        self.adc.dseqctrl.write(|w| {
+            w.inputctrl().set_bit();
+            w.autostart().set_bit()
+        });
+        while self.adc.syncbusy.read().bits() != 0 {}
+        self.power_up();
+
+        // SAFETY: We use new_unchecked to avoid having to pass a 'static self as the
+        // source buffer. This is safe as long as we guarantee the destination
+        // buffer is static.
+        let results =
+            unsafe { dmac::Transfer::new_unchecked(result_channel, self, results, circular) }
+                .with_waker(waker)
+                .begin(Self::DMA_RESULT_TRIGGER, TriggerAction::BURST);
+
+        let data = SequenceData { _adc: PhantomData };
+        // SAFETY: The sequence buffer is static, and SequenceData points to a
+        // peripheral register.
+        let sequence =
+            unsafe { dmac::Transfer::new_unchecked(seq_channel, sequence, data, circular) }
+                .begin(Self::DMA_SEQUENCE_TRIGGER, TriggerAction::BURST);
+
+        (sequence, results)
+    }
+
+    /// Disable DMA sequencing after a
+    /// [`sequence_with_dma`](Self::sequence_with_dma) scan, restore the
+    /// single-ended input configuration and power down the ADC.
+    #[inline]
+    pub fn stop_sequence(&mut self) {
+        self.power_down();
+        self.adc.dseqctrl.write(|w| unsafe { w.bits(0) });
+        while self.adc.syncbusy.read().bits() != 0 {}
+        self.mux_single_ended();
+    }
+
+    fn start_stream(&mut self, trigger: StreamTrigger) {
+        match trigger {
+            StreamTrigger::FreeRunning => self.enable_freerunning(),
+            StreamTrigger::Event => self.adc.evctrl.modify(|_, w| w.startei().set_bit()),
+        }
+        self.power_up();
+        if trigger == StreamTrigger::FreeRunning {
+            self.start_conversion();
+        }
+    }
+}
+        )+
+    }
+}
+
+adc_dma! {
+    ADC0: (ADC0_RESRDY, ADC0_SEQ),
+    ADC1: (ADC1_RESRDY, ADC1_SEQ),
+}
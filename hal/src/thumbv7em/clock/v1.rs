@@ -0,0 +1,811 @@
+//! Configuring the system clock sources.
+//! You will typically need to create an instance of `GenericClockController`
+//! before you can set up most of the peripherals on the atsamd51 device.
+//! The other types in this module are used to enforce at compile time
+//! that the peripherals have been correctly configured.
+use crate::pac::gclk::genctrl::SRC_A::*;
+use crate::pac::gclk::pchctrl::GEN_A::*;
+use crate::pac::oscctrl::dpll::dpllctrlb::REFCLK_A;
+use crate::pac::{self, GCLK, MCLK, NVMCTRL, OSC32KCTRL, OSCCTRL};
+use crate::time::{Hertz, MegaHertz};
+
+pub use super::v2::xosc::{XoscConfig, XoscStartup};
+
+pub type ClockGenId = pac::gclk::pchctrl::GEN_A;
+pub type ClockSource = pac::gclk::genctrl::SRC_A;
+
+#[allow(non_camel_case_types)]
+pub enum ClockId {
+    DFLL48 = 0,
+    FDPLL0,
+    FDPLL1,
+    SLOW_32K,
+    EIC,
+    FREQM_MSR,
+    FREQM_REF,
+    SERCOM0_CORE,
+    SERCOM1_CORE,
+    TC0_TC1,
+    USB,
+    EVSYS0,
+    EVSYS1,
+    EVSYS2,
+    EVSYS3,
+    EVSYS4,
+    EVSYS5,
+    EVSYS6,
+    EVSYS7,
+    EVSYS8,
+    EVSYS9,
+    EVSYS10,
+    EVSYS11,
+    SERCOM2_CORE,
+    SERCOM3_CORE,
+    TCC0_TCC1,
+    TC2_TC3,
+    CAN0,
+    CAN1,
+    TCC2_TCC3,
+    TC4_TC5,
+    PDEC,
+    AC,
+    CCL,
+    SERCOM4_CORE,
+    SERCOM5_CORE,
+    SERCOM6_CORE,
+    SERCOM7_CORE,
+    TCC4,
+    TC6_TC7,
+    ADC0,
+    ADC1,
+    DAC,
+    I2S0,
+    I2S1,
+    SDHC0,
+    SDHC1,
+    CM4_TRACE,
+}
+
+impl From<ClockId> for u8 {
+    fn from(clock: ClockId) -> u8 {
+        clock as u8
+    }
+}
+
+/// Represents a configured clock generator.
+/// Can be converted into the effective clock frequency.
+/// Its primary purpose is to be passed in to methods
+/// such as `GenericClockController::tcc2_tc3` to configure
+/// the clock for a peripheral.
+//#[derive(Clone, Copy)]
+pub struct GClock {
+    gclk: ClockGenId,
+    freq: Hertz,
+}
+
+impl Into<Hertz> for GClock {
+    fn into(self) -> Hertz {
+        self.freq
+    }
+}
+
+/// Selects one of the two external multipurpose oscillators
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Xosc {
+    /// XOSC0, using the XIN0/XOUT0 pins
+    Xosc0 = 0,
+    /// XOSC1, using the XIN1/XOUT1 pins
+    Xosc1 = 1,
+}
+
+/// Reference clock of the DPLL1 fractional digital phase-locked loop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DpllReference {
+    /// The external 32kHz crystal oscillator. Only available if the clock
+    /// controller was created with `with_external_32kosc`.
+    Xosc32k,
+    /// An external oscillator configured with
+    /// `GenericClockController::configure_xosc`, divided by
+    /// `2 * (div + 1)`
+    Xosc {
+        /// The oscillator to use
+        xosc: Xosc,
+        /// The 11-bit divider applied to the oscillator frequency
+        div: u16,
+    },
+    /// A configured clock generator, connected to the DPLL through the
+    /// FDPLL1 peripheral channel
+    Gclk(ClockGenId),
+}
+
+/// Compute the output frequency of a DPLL, given its reference frequency and
+/// its integer and fractional loop divider ratios
+fn dpll_output_freq(reference: Hertz, ldr: u16, ldrfrac: u8) -> Hertz {
+    // fCK = fCKR * (LDR + 1 + LDRFRAC / 32)
+    let ratio = 32 * (ldr as u64 + 1) + ldrfrac as u64;
+    Hertz((reference.0 as u64 * ratio / 32) as u32)
+}
+
+struct State {
+    gclk: GCLK,
+}
+
+impl State {
+    fn reset_gclk(&mut self) {
+        self.gclk.ctrla.write(|w| w.swrst().set_bit());
+        while self.gclk.ctrla.read().swrst().bit_is_set() || self.gclk.syncbusy.read().bits() != 0 {
+        }
+    }
+
+    fn wait_for_sync(&mut self) {
+        while self.gclk.syncbusy.read().bits() != 0 {}
+    }
+
+    fn set_gclk_divider_and_source(
+        &mut self,
+        gclk: ClockGenId,
+        divider: u16,
+        src: ClockSource,
+        improve_duty_cycle: bool,
+    ) {
+        // validate the divisor factor based on gclk ID (see 14.8.3)
+        let mut divisor_invalid = false;
+        if gclk == GCLK1 {
+            if divider as u32 >= 2_u32.pow(16) {
+                divisor_invalid = true;
+            }
+        } else {
+            if divider >= 2_u16.pow(8) {
+                divisor_invalid = true;
+            }
+        }
+        if divisor_invalid {
+            panic!("invalid divisor {} for GCLK {}", divider, gclk as u8);
+        }
+
+        self.gclk.genctrl[u8::from(gclk) as usize].write(|w| unsafe {
+            w.src().variant(src);
+            w.div().bits(divider);
+            // divide directly by divider, rather than 2^(n+1)
+            w.divsel().clear_bit();
+            w.idc().bit(improve_duty_cycle);
+            w.genen().set_bit();
+            // The GCLK_IO pin is an input when used as the source
+            w.oe().bit(src != GCLKIN)
+        });
+
+        self.wait_for_sync();
+    }
+
+    fn enable_clock_generator(&mut self, clock: ClockId, generator: ClockGenId) {
+        self.gclk.pchctrl[u8::from(clock) as usize].write(|w| unsafe {
+            w.gen().bits(generator.into());
+            w.chen().set_bit()
+        });
+        self.wait_for_sync();
+    }
+
+    fn configure_standby(&mut self, gclk: ClockGenId, enable: bool) {
+        self.gclk.genctrl[u8::from(gclk) as usize].modify(|_, w| w.runstdby().bit(enable));
+        self.wait_for_sync();
+    }
+}
+
+/// `GenericClockController` encapsulates the GCLK hardware.
+/// It provides a type safe way to configure the system clocks.
+/// Initializing the `GenericClockController` instance configures
+/// the system to run at 120MHz by taking the DFLL48
+/// and feeding it into the DPLL0 hardware which multiplies the
+/// signal by 2.5x.
+pub struct GenericClockController {
+    state: State,
+    gclks: [Hertz; 12],
+    used_clocks: u64,
+    xosc32k_freq: Hertz,
+    xosc_freqs: [Hertz; 2],
+    dpll1_freq: Hertz,
+    gclkin_freqs: [Hertz; 12],
+}
+
+impl GenericClockController {
+    /// Reset the clock controller, configure the system to run
+    /// at 120Mhz and reset various clock dividers.
+    pub fn with_internal_32kosc(
+        gclk: GCLK,
+        mclk: &mut MCLK,
+        osc32kctrl: &mut OSC32KCTRL,
+        oscctrl: &mut OSCCTRL,
+        nvmctrl: &mut NVMCTRL,
+    ) -> Self {
+        Self::new(gclk, mclk, osc32kctrl, oscctrl, nvmctrl, false)
+    }
+
+    /// Reset the clock controller, configure the system to run
+    /// at 120Mhz and reset various clock dividers.
+    pub fn with_external_32kosc(
+        gclk: GCLK,
+        mclk: &mut MCLK,
+        osc32kctrl: &mut OSC32KCTRL,
+        oscctrl: &mut OSCCTRL,
+        nvmctrl: &mut NVMCTRL,
+    ) -> Self {
+        Self::new(gclk, mclk, osc32kctrl, oscctrl, nvmctrl, true)
+    }
+
+    fn new(
+        gclk: GCLK,
+        mclk: &mut MCLK,
+        osc32kctrl: &mut OSC32KCTRL,
+        oscctrl: &mut OSCCTRL,
+        nvmctrl: &mut NVMCTRL,
+        use_external_crystal: bool,
+    ) -> Self {
+        let mut state = State { gclk };
+
+        set_flash_to_half_auto_wait_state(nvmctrl);
+        enable_gclk_apb(mclk);
+
+        if use_external_crystal {
+            enable_external_32kosc(osc32kctrl);
+            state.reset_gclk();
+            state.set_gclk_divider_and_source(GCLK1, 1, XOSC32K, false);
+        } else {
+            enable_internal_32kosc(osc32kctrl);
+            state.reset_gclk();
+            state.set_gclk_divider_and_source(GCLK1, 1, OSCULP32K, false);
+        }
+
+        while state.gclk.syncbusy.read().genctrl().is_gclk0() {}
+
+        #[cfg(feature = "usb")]
+        configure_usb_correction(oscctrl);
+
+        // GCLK5 set to 2MHz
+        unsafe {
+            state.gclk.genctrl[5].write(|w| {
+                w.src().dfll();
+                w.genen().set_bit();
+                w.div().bits(24)
+            });
+        }
+
+        while state.gclk.syncbusy.read().genctrl().is_gclk5() {}
+
+        configure_and_enable_dpll0(oscctrl, &mut state.gclk);
+        wait_for_dpllrdy(oscctrl, 0);
+
+        unsafe {
+            // GCLK0 set to DPLL0 (120MHz)
+            state.gclk.genctrl[0].write(|w| {
+                w.src().dpll0();
+                w.div().bits(1);
+                w.oe().set_bit();
+                w.genen().set_bit()
+            });
+        }
+
+        while state.gclk.syncbusy.read().genctrl().is_gclk0() {}
+
+        mclk.cpudiv.write(|w| w.div().div1());
+
+        Self {
+            state,
+            gclks: [
+                OSC120M_FREQ,
+                OSC32K_FREQ,
+                Hertz(0),
+                Hertz(0),
+                Hertz(0),
+                MegaHertz(2).into(),
+                Hertz(0),
+                Hertz(0),
+                Hertz(0),
+                Hertz(0),
+                Hertz(0),
+                Hertz(0),
+            ],
+            used_clocks: 1u64 << u8::from(ClockId::FDPLL0),
+            xosc32k_freq: if use_external_crystal {
+                OSC32K_FREQ
+            } else {
+                Hertz(0)
+            },
+            xosc_freqs: [Hertz(0); 2],
+            dpll1_freq: Hertz(0),
+            gclkin_freqs: [Hertz(0); 12],
+        }
+    }
+
+    /// Returns a `GClock` for gclk0, the 120MHz oscillator.
+    pub fn gclk0(&mut self) -> GClock {
+        GClock {
+            gclk: GCLK0,
+            freq: self.gclks[0],
+        }
+    }
+
+    /// Returns a `GClock` for gclk1, the 32KHz oscillator.
+    pub fn gclk1(&mut self) -> GClock {
+        GClock {
+            gclk: GCLK1,
+            freq: self.gclks[1],
+        }
+    }
+
+    /// Returns the `GClock` for the specified clock generator.
+    /// If that clock generator has not yet been configured,
+    /// returns None.
+    pub fn get_gclk(&mut self, gclk: ClockGenId) -> Option<GClock> {
+        let idx = u8::from(gclk) as usize;
+        if self.gclks[idx].0 == 0 {
+            None
+        } else {
+            Some(GClock {
+                gclk,
+                freq: self.gclks[idx],
+            })
+        }
+    }
+
+    /// Configures a clock generator with the specified divider and
+    /// source.
+    /// `divider` is a linear divider to be applied to the clock
+    /// source.  While the hardware also supports an exponential divider,
+    /// this function doesn't expose that functionality at this time.
+    /// `improve_duty_cycle` is a boolean that, when set to true, enables
+    /// a 50/50 duty cycle for odd divider values.
+    /// The `XOSC0`, `XOSC1`, `DPLL1` and `GCLKIN` sources must first be set
+    /// up with `configure_xosc`, `configure_dpll1` or `configure_gclkin`.
+    /// Returns a `GClock` for the configured clock generator.
+    /// Returns `None` if the clock generator has already been configured,
+    /// or if the source has not been set up.
+    pub fn configure_gclk_divider_and_source(
+        &mut self,
+        gclk: ClockGenId,
+        divider: u16,
+        src: ClockSource,
+        improve_duty_cycle: bool,
+    ) -> Option<GClock> {
+        let idx = u8::from(gclk) as usize;
+        if self.gclks[idx].0 != 0 {
+            return None;
+        }
+        let freq = self.source_freq(idx, src);
+        if freq.0 == 0 {
+            return None;
+        }
+        self.state
+            .set_gclk_divider_and_source(gclk, divider, src, improve_duty_cycle);
+        let freq = Hertz(freq.0 / divider as u32);
+        self.gclks[idx] = freq;
+        Some(GClock { gclk, freq })
+    }
+
+    /// Switches gclk0, and therefore the CPU, to a new source and divider at
+    /// run-time.
+    /// The automatic wait state generation of the NVM controller stays
+    /// enabled, as with `clock::v2`. The manual flash wait states are also
+    /// recomputed for the new frequency, in case it is disabled later. They
+    /// are raised before switching to a faster clock, and lowered after
+    /// switching to a slower one.
+    /// Unlike the SAML2x parts, the SAMD5x/E5x have no power manager
+    /// performance levels, so the core voltage doesn't need to be changed.
+    /// Peripherals clocked from gclk0 are not reconfigured; they must be
+    /// notified of the new frequency through the `Reclock` trait.
+    /// Returns the new `GClock` for gclk0.
+    /// Returns `None` if the source has not been set up.
+    pub fn set_gclk0(
+        &mut self,
+        src: ClockSource,
+        divider: u16,
+        nvmctrl: &mut NVMCTRL,
+    ) -> Option<GClock> {
+        let freq = self.source_freq(0, src);
+        if freq.0 == 0 {
+            return None;
+        }
+        let freq = Hertz(freq.0 / divider as u32);
+        let faster = freq.0 > self.gclks[0].0;
+        if faster {
+            set_flash_wait_states(nvmctrl, freq);
+        }
+        self.state
+            .set_gclk_divider_and_source(GCLK0, divider, src, false);
+        if !faster {
+            set_flash_wait_states(nvmctrl, freq);
+        }
+        self.gclks[0] = freq;
+        Some(GClock { gclk: GCLK0, freq })
+    }
+
+    /// Returns the frequency of a clock source, as seen by the clock
+    /// generator at index `idx`, or zero if the source has not been set up
+    fn source_freq(&self, idx: usize, src: ClockSource) -> Hertz {
+        match src {
+            XOSC32K | OSCULP32K => OSC32K_FREQ,
+            GCLKGEN1 => self.gclks[1],
+            DFLL => OSC48M_FREQ,
+            DPLL0 => OSC120M_FREQ,
+            XOSC0 => self.xosc_freqs[0],
+            XOSC1 => self.xosc_freqs[1],
+            GCLKIN => self.gclkin_freqs[idx],
+            DPLL1 => self.dpll1_freq,
+        }
+    }
+
+    /// Configures and enables one of the external oscillators, so that it
+    /// can be used as the source of a clock generator or as the reference
+    /// of DPLL1.
+    /// Unless the oscillator is configured to run on demand, this function
+    /// waits until it is ready.
+    /// Returns the frequency of the oscillator.
+    /// Returns `None` if the oscillator has already been configured.
+    pub fn configure_xosc(
+        &mut self,
+        oscctrl: &mut OSCCTRL,
+        xosc: Xosc,
+        config: XoscConfig,
+    ) -> Option<Hertz> {
+        let idx = xosc as usize;
+        if self.xosc_freqs[idx].0 != 0 {
+            return None;
+        }
+
+        config.write(&oscctrl.xoscctrl[idx]);
+
+        if !config.is_on_demand() {
+            let ready = |oscctrl: &mut OSCCTRL| {
+                let status = oscctrl.status.read();
+                match xosc {
+                    Xosc::Xosc0 => status.xoscrdy0().bit_is_set(),
+                    Xosc::Xosc1 => status.xoscrdy1().bit_is_set(),
+                }
+            };
+            while !ready(oscctrl) {}
+        }
+
+        self.xosc_freqs[idx] = config.freq();
+        Some(config.freq())
+    }
+
+    /// Configures and enables DPLL1, so that it can be used as the source
+    /// of a clock generator.
+    /// The output frequency is `fref * (ldr + 1 + ldrfrac / 32)`, where
+    /// `fref` is the frequency of the `reference` clock (after division, for
+    /// the XOSC references). `fref` must be between 32kHz and 3.2MHz, and
+    /// the output frequency between 96MHz and 200MHz.
+    /// This function waits until the DPLL is locked.
+    /// Returns the output frequency of the DPLL.
+    /// Returns `None` if DPLL1 has already been configured, or if the
+    /// reference clock has not been set up.
+    pub fn configure_dpll1(
+        &mut self,
+        oscctrl: &mut OSCCTRL,
+        reference: DpllReference,
+        ldr: u16,
+        ldrfrac: u8,
+    ) -> Option<Hertz> {
+        if self.dpll1_freq.0 != 0 {
+            return None;
+        }
+        if ldr >= 2_u16.pow(13) || ldrfrac >= 32 {
+            panic!("invalid DPLL ratio {}.{}", ldr, ldrfrac);
+        }
+
+        let (refclk, div, ref_freq) = match reference {
+            DpllReference::Xosc32k => (REFCLK_A::XOSC32, 0, self.xosc32k_freq),
+            DpllReference::Xosc { xosc, div } => {
+                if div >= 2_u16.pow(11) {
+                    panic!("invalid DPLL divider {}", div);
+                }
+                let refclk = match xosc {
+                    Xosc::Xosc0 => REFCLK_A::XOSC0,
+                    Xosc::Xosc1 => REFCLK_A::XOSC1,
+                };
+                let freq = self.xosc_freqs[xosc as usize];
+                (refclk, div, Hertz(freq.0 / (2 * (div as u32 + 1))))
+            }
+            DpllReference::Gclk(gclk) => {
+                let bits: u64 = 1 << u8::from(ClockId::FDPLL1) as u64;
+                if (self.used_clocks & bits) != 0 {
+                    return None;
+                }
+                (REFCLK_A::GCLK, 0, self.gclks[u8::from(gclk) as usize])
+            }
+        };
+        if ref_freq.0 == 0 {
+            return None;
+        }
+        if ref_freq.0 < 32_000 || ref_freq.0 > 3_200_000 {
+            panic!("invalid DPLL reference frequency {}", ref_freq.0);
+        }
+        let freq = dpll_output_freq(ref_freq, ldr, ldrfrac);
+        if freq.0 < 96_000_000 || freq.0 > 200_000_000 {
+            panic!("invalid DPLL output frequency {}", freq.0);
+        }
+
+        if let DpllReference::Gclk(gclk) = reference {
+            self.used_clocks |= 1 << u8::from(ClockId::FDPLL1) as u64;
+            self.state.enable_clock_generator(ClockId::FDPLL1, gclk);
+        }
+
+        let dpll = &oscctrl.dpll[1];
+        dpll.dpllctrla.write(|w| w.enable().clear_bit());
+        while dpll.dpllsyncbusy.read().enable().bit_is_set() {}
+        dpll.dpllratio.write(|w| unsafe {
+            w.ldr().bits(ldr);
+            w.ldrfrac().bits(ldrfrac)
+        });
+        while dpll.dpllsyncbusy.read().dpllratio().bit_is_set() {}
+        dpll.dpllctrlb.write(|w| unsafe {
+            w.refclk().variant(refclk);
+            w.div().bits(div)
+        });
+        dpll.dpllctrla.write(|w| {
+            w.enable().set_bit();
+            w.ondemand().clear_bit()
+        });
+        while dpll.dpllsyncbusy.read().enable().bit_is_set() {}
+        wait_for_dpllrdy(oscctrl, 1);
+
+        self.dpll1_freq = freq;
+        Some(freq)
+    }
+
+    /// Records the frequency of the external clock signal applied to the
+    /// GCLK_IO pin of the specified clock generator, so that the generator
+    /// can then be configured with the `GCLKIN` source. The pin must be put
+    /// into its GCLK_IO alternate function by the caller.
+    pub fn configure_gclkin(&mut self, gclk: ClockGenId, freq: impl Into<Hertz>) {
+        self.gclkin_freqs[u8::from(gclk) as usize] = freq.into();
+    }
+
+    /// Enables or disables the given GClk from operation in standby.
+    pub fn configure_standby(&mut self, gclk: ClockGenId, enable: bool) {
+        self.state.configure_standby(gclk, enable)
+    }
+}
+
+macro_rules! clock_generator {
+    (
+        $(
+            $(#[$attr:meta])*
+            ($id:ident, $Type:ident, $clock:ident),
+        )+
+    ) => {
+
+$(
+
+/// A typed token that indicates that the clock for the peripheral(s)
+/// with the matching name has been configured.
+/// The effective clock frequency is available via the `freq` method,
+/// or by converting the object into a `Hertz` instance.
+/// The peripheral initialization code will typically require passing
+/// in this object to prove at compile time that the clock has been
+/// correctly initialized.
+$(#[$attr])*
+#[derive(Debug)]
+pub struct $Type {
+    pub(super) freq: Hertz,
+}
+
+$(#[$attr])*
+impl $Type {
+    /// Returns the frequency of the configured clock
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+}
+$(#[$attr])*
+impl Into<Hertz> for $Type {
+    fn into(self) -> Hertz {
+        self.freq
+    }
+}
+)+
+
+impl GenericClockController {
+    $(
+    /// Configure the clock for peripheral(s) that match the name
+    /// of this function to use the specific clock generator.
+    /// The `GClock` parameter may be one of default clocks
+    /// return from `gclk0()`, `gclk1()` or a clock configured
+    /// by the host application using the `configure_gclk_divider_and_source`
+    /// method.
+    /// Returns a typed token that proves that the clock has been configured;
+    /// the peripheral initialization code will typically require that this
+    /// clock token be passed in to ensure that the clock has been initialized
+    /// appropriately.
+    /// Returns `None` is the specified generic clock has already been
+    /// configured.
+    $(#[$attr])*
+    pub fn $id(&mut self, generator: &GClock) -> Option<$Type> {
+        let bits: u64 = 1 << u8::from(ClockId::$clock) as u64;
+        if (self.used_clocks & bits) != 0 {
+            return None;
+        }
+        self.used_clocks |= bits;
+
+        self.state.enable_clock_generator(ClockId::$clock, generator.gclk);
+        let freq = self.gclks[u8::from(generator.gclk) as usize];
+        Some($Type{freq})
+    }
+    )+
+}
+    }
+}
+
+clock_generator!(
+    (tc0_tc1, Tc0Tc1Clock, TC0_TC1),
+    (tcc0_tcc1, Tcc0Tcc1Clock, TCC0_TCC1),
+    (tc2_tc3, Tc2Tc3Clock, TC2_TC3),
+    (tcc2_tcc3, Tcc2Tcc3Clock, TCC2_TCC3),
+    (tc4_tc5, Tc4Tc5Clock, TC4_TC5),
+    (tcc4, Tcc4Clock, TCC4),
+    (tc6_tc7, Tc6Tc7Clock, TC6_TC7),
+    (sercom0_core, Sercom0CoreClock, SERCOM0_CORE),
+    (sercom1_core, Sercom1CoreClock, SERCOM1_CORE),
+    (sercom2_core, Sercom2CoreClock, SERCOM2_CORE),
+    (sercom3_core, Sercom3CoreClock, SERCOM3_CORE),
+    (sercom4_core, Sercom4CoreClock, SERCOM4_CORE),
+    (sercom5_core, Sercom5CoreClock, SERCOM5_CORE),
+    #[cfg(feature = "min-samd51n")]
+    (sercom6_core, Sercom6CoreClock, SERCOM6_CORE),
+    #[cfg(feature = "min-samd51n")]
+    (sercom7_core, Sercom7CoreClock, SERCOM7_CORE),
+    (usb, UsbClock, USB),
+    (adc0, Adc0Clock, ADC0),
+    (adc1, Adc1Clock, ADC1),
+    (eic, EicClock, EIC),
+    (freq_m_msr, FreqmMsrClock, FREQM_MSR),
+    (freq_m_ref, FreqmRefClock, FREQM_REF),
+    (evsys0, Evsys0Clock, EVSYS0),
+    (evsys1, Evsys1Clock, EVSYS1),
+    (evsys2, Evsys2Clock, EVSYS2),
+    (evsys3, Evsys3Clock, EVSYS3),
+    (evsys4, Evsys4Clock, EVSYS4),
+    (evsys5, Evsys5Clock, EVSYS5),
+    (evsys6, Evsys6Clock, EVSYS6),
+    (evsys7, Evsys7Clock, EVSYS7),
+    (evsys8, Evsys8Clock, EVSYS8),
+    (evsys9, Evsys9Clock, EVSYS9),
+    (evsys10, Evsys10Clock, EVSYS10),
+    (evsys11, Evsys11Clock, EVSYS11),
+    (can0, Can0Clock, CAN0),
+    (can1, Can1Clock, CAN1),
+    (pdec, PdecClock, PDEC),
+    (ac, AcClock, AC),
+    (ccl, CclClock, CCL),
+    (dac, DacClock, DAC),
+    (i2s0, I2S0Clock, I2S0),
+    (i2s1, I2S1Clock, I2S1),
+    (sdhc0, Sdhc0Clock, SDHC0),
+    (sdhc1, Sdhc1Clock, SDHC1),
+    (cm4_trace, Cm4TraceClock, CM4_TRACE),
+);
+
+/// The frequency of the 48Mhz source.
+pub const OSC48M_FREQ: Hertz = Hertz(48_000_000);
+/// The frequency of the 32Khz source.
+pub const OSC32K_FREQ: Hertz = Hertz(32_768);
+/// The frequency of the 120Mhz source.
+pub const OSC120M_FREQ: Hertz = Hertz(120_000_000);
+
+fn set_flash_to_half_auto_wait_state(nvmctrl: &mut NVMCTRL) {
+    // Zero indicates zero wait states, one indicates one wait state, etc.,
+    // up to 15 wait states.
+    nvmctrl.ctrla.modify(|_, w| unsafe { w.rws().bits(0b0111) });
+}
+
+/// Sets the number of flash wait states required for a CPU frequency,
+/// as given in the NVM characteristics of the datasheet, and keeps the
+/// automatic wait states enabled
+fn set_flash_wait_states(nvmctrl: &mut NVMCTRL, freq: Hertz) {
+    let rws = match freq.0 {
+        0..=24_000_000 => 0,
+        24_000_001..=51_000_000 => 1,
+        51_000_001..=77_000_000 => 2,
+        77_000_001..=101_000_000 => 3,
+        101_000_001..=119_000_000 => 4,
+        _ => 5,
+    };
+    nvmctrl.ctrla.modify(|_, w| unsafe {
+        w.autows().set_bit();
+        w.rws().bits(rws)
+    });
+}
+
+fn enable_gclk_apb(mclk: &mut MCLK) {
+    mclk.apbamask.modify(|_, w| w.gclk_().set_bit());
+}
+
+/// Turn on the internal 32hkz oscillator
+fn enable_internal_32kosc(osc32kctrl: &mut OSC32KCTRL) {
+    osc32kctrl.osculp32k.modify(|_, w| {
+        w.en32k().set_bit();
+        w.en1k().set_bit()
+    });
+    osc32kctrl.rtcctrl.write(|w| w.rtcsel().ulp1k());
+}
+
+/// Turn on the external 32hkz oscillator
+fn enable_external_32kosc(osc32kctrl: &mut OSC32KCTRL) {
+    osc32kctrl.xosc32k.modify(|_, w| {
+        w.ondemand().clear_bit();
+        // Enable 32khz output
+        w.en32k().set_bit();
+        w.en1k().set_bit();
+        // Crystal connected to xin32/xout32
+        w.xtalen().set_bit();
+        w.enable().set_bit();
+        w.cgm().xt();
+        w.runstdby().set_bit()
+    });
+
+    osc32kctrl.rtcctrl.write(|w| w.rtcsel().xosc1k());
+
+    // Wait for the oscillator to stabilize
+    while osc32kctrl.status.read().xosc32krdy().bit_is_clear() {}
+}
+
+fn wait_for_dpllrdy(oscctrl: &mut OSCCTRL, dpll: usize) {
+    while oscctrl.dpll[dpll].dpllstatus.read().lock().bit_is_clear()
+        || oscctrl.dpll[dpll].dpllstatus.read().clkrdy().bit_is_clear()
+    {}
+}
+
+/// Configure the dpll0 to run at 120MHz
+fn configure_and_enable_dpll0(oscctrl: &mut OSCCTRL, gclk: &mut GCLK) {
+    gclk.pchctrl[ClockId::FDPLL0 as usize].write(|w| {
+        w.chen().set_bit();
+        w.gen().gclk5()
+    });
+    unsafe {
+        oscctrl.dpll[0].dpllratio.write(|w| {
+            w.ldr().bits(59);
+            w.ldrfrac().bits(0)
+        });
+    }
+    oscctrl.dpll[0].dpllctrlb.write(|w| w.refclk().gclk());
+    oscctrl.dpll[0].dpllctrla.write(|w| {
+        w.enable().set_bit();
+        w.ondemand().clear_bit()
+    });
+}
+
+#[cfg(feature = "usb")]
+/// Configure the dfll48m to calibrate against the 1Khz USB SOF reference.
+fn configure_usb_correction(oscctrl: &mut OSCCTRL) {
+    oscctrl.dfllmul.write(|w| unsafe {
+        w.cstep().bits(0x1)
+        .fstep().bits(0x1)
+        // scaling factor for 1Khz SOF signal.
+        .mul().bits((48_000_000u32 / 1000) as u16)
+    });
+    while oscctrl.dfllsync.read().dfllmul().bit_is_set() {}
+
+    oscctrl.dfllctrlb.write(|w| {
+        // closed loop mode
+        w.mode().set_bit()
+        // chill cycle disable
+        .ccdis().set_bit()
+        // usb correction
+        .usbcrm().set_bit()
+    });
+    while oscctrl.dfllsync.read().dfllctrlb().bit_is_set() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpll_ratio() {
+        assert_eq!(dpll_output_freq(Hertz(2_000_000), 59, 0), OSC120M_FREQ);
+        assert_eq!(dpll_output_freq(OSC32K_FREQ, 3661, 0), Hertz(119_996_416));
+        assert_eq!(
+            dpll_output_freq(Hertz(1_000_000), 99, 16),
+            Hertz(100_500_000)
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! # Version 2 of the clocking API
+//!
+//! This module represents the SAMD5x/E5x clock tree as a set of owned,
+//! type-level tokens. Each clock in the tree goes through the same life
+//! cycle:
+//!
+//! 1. A token, such as an [`XoscToken`](xosc::XoscToken) or a
+//!    [`GclkToken`](gclk::GclkToken), grants exclusive access to the
+//!    registers of one clock. All tokens are returned by
+//!    [`clock_system_at_reset`] in the [`Tokens`] struct.
+//! 2. The token is combined with its source clock (and any required pins) to
+//!    build a clock, e.g. [`Xosc`](xosc::Xosc) or [`Gclk`](gclk::Gclk), which
+//!    can then be configured with builder methods.
+//! 3. The clock is enabled, which writes the configuration to the hardware
+//!    and returns an [`Enabled`] clock. Enabled clocks can be used as the
+//!    [`Source`] of other clocks.
+//!
+//! [`Enabled`] clocks count their consumers in the type parameter `N`, a
+//! [`typenum`] [`Unsigned`] integer. Using a clock as a source increments its
+//! count, and releasing a consumer decrements it. Because a clock can only be
+//! disabled when its count is [`U0`], the compiler guarantees that no source
+//! is ever disabled while something still depends on it. Going through these
+//! steps in reverse tears the clock tree down again, and the tokens can be
+//! used to rebuild it differently at run-time.
+//!
+//! Frequencies are tracked at run-time, and travel from each source to its
+//! consumers. A [`Pclk`](pclk::Pclk) can be traded for the corresponding
+//! [`v1`](super::v1) clock token, so that it can be used with existing
+//! drivers.
+//!
+//! At reset, the 48MHz DFLL drives generic clock generator 0, which clocks
+//! the CPU. The following example reconfigures the clock tree to run the CPU
+//! at 100MHz from DPLL0, itself referenced to a 12MHz crystal on XOSC0, and
+//! clocks SERCOM0 from the DFLL through GCLK2.
+//!
+//! ```
+//! let (mut clocks, tokens) = clock_system_at_reset(
+//!     peripherals.OSCCTRL,
+//!     peripherals.OSC32KCTRL,
+//!     peripherals.GCLK,
+//! );
+//!
+//! let xosc0 = Xosc::from_crystal(tokens.xosc0, pins.pa14, pins.pa15, 12.mhz()).enable();
+//! let (dpll0, xosc0) = Dpll::from_xosc(tokens.dpll0, xosc0, 2);
+//! // 12MHz / 6 * 50 = 100MHz
+//! let dpll0 = dpll0.loop_div(49, 0).enable();
+//! let (gclk0, dfll, dpll0) = clocks.gclk0.swap_sources(clocks.dfll, dpll0);
+//!
+//! let (gclk2, dfll) = Gclk::new(tokens.gclks.gclk2, dfll);
+//! let gclk2 = gclk2.enable();
+//! let (sercom0, gclk2) = Pclk::enable(tokens.pclks.sercom0_core, gclk2);
+//! let (sercom0_clock, sercom0) = sercom0.into_v1();
+//! ```
+//!
+//! The flash wait states are managed automatically by the NVM controller
+//! (`NVMCTRL.CTRLA.AUTOWS`, enabled at reset and left enabled by the
+//! [`v1`](super::v1) API), so the CPU frequency can be changed freely. The `GCLKIN` source is only supported by the
+//! [`v1`](super::v1) API.
+//!
+//! The external oscillators can be monitored by clock failure detectors,
+//! which switch them to a safe clock if they stop. See the [`cfd`] module.
+
+use core::marker::PhantomData;
+use core::ops::{Add, Sub};
+
+use typenum::{Add1, Sub1, Unsigned, B1, U0, U1};
+
+use crate::pac::{GCLK, OSC32KCTRL, OSCCTRL};
+use crate::time::Hertz;
+use crate::typelevel::{PrivateDecrement, PrivateIncrement, Sealed};
+
+pub mod cfd;
+pub mod dfll;
+pub mod dpll;
+pub mod gclk;
+pub mod osculp32k;
+pub mod pclk;
+pub mod xosc;
+pub mod xosc32k;
+
+use cfd::ClockFailureDetector;
+use dfll::{Dfll, DfllId};
+use dpll::{Dpll0Id, Dpll1Id, DpllToken};
+use gclk::{Gclk0, GclkTokens};
+use osculp32k::OscUlp32k;
+use pclk::PclkTokens;
+use xosc::{Xosc0Id, Xosc1Id, XoscToken};
+use xosc32k::Xosc32kToken;
+
+//==============================================================================
+// Enabled
+//==============================================================================
+
+/// An enabled clock, with its number of consumers `N`
+///
+/// `N` is a type-level [`Unsigned`] integer. It is incremented each time the
+/// clock is used as the source of another clock, and decremented when that
+/// consumer is released. Clocks can only be disabled when `N` is [`U0`].
+pub struct Enabled<T, N = U0> {
+    clock: T,
+    count: PhantomData<N>,
+}
+
+impl<T, N> Enabled<T, N> {
+    #[inline]
+    fn new(clock: T) -> Self {
+        Enabled {
+            clock,
+            count: PhantomData,
+        }
+    }
+}
+
+impl<T, N> Sealed for Enabled<T, N> {}
+
+impl<T, N> PrivateIncrement for Enabled<T, N>
+where
+    N: Unsigned + Add<B1>,
+    Add1<N>: Unsigned,
+{
+    type Inc = Enabled<T, Add1<N>>;
+
+    #[inline]
+    fn inc(self) -> Self::Inc {
+        Enabled::new(self.clock)
+    }
+}
+
+impl<T, N> PrivateDecrement for Enabled<T, N>
+where
+    N: Unsigned + Sub<B1>,
+    Sub1<N>: Unsigned,
+{
+    type Dec = Enabled<T, Sub1<N>>;
+
+    #[inline]
+    fn dec(self) -> Self::Dec {
+        Enabled::new(self.clock)
+    }
+}
+
+//==============================================================================
+// Source
+//==============================================================================
+
+/// An enabled clock that can drive other clocks
+///
+/// `Id` is a type-level identifier of the clock, which consumers use to
+/// select it in hardware. It also ensures that a consumer is released with
+/// the same source it was built from.
+pub trait Source: Sealed {
+    /// Type-level identifier of the clock
+    type Id;
+
+    /// Returns the frequency of the clock
+    fn freq(&self) -> Hertz;
+}
+
+//==============================================================================
+// Clock system at reset
+//==============================================================================
+
+/// PAC structs of the clocking peripherals
+///
+/// The registers of these peripherals are accessed through the individual
+/// clock tokens. Holding on to the PAC structs ensures that they cannot be
+/// used elsewhere at the same time.
+pub struct Pac {
+    oscctrl: OSCCTRL,
+    osc32kctrl: OSC32KCTRL,
+    gclk: GCLK,
+}
+
+impl Pac {
+    /// Release the PAC structs
+    ///
+    /// # Safety
+    ///
+    /// The clocks and tokens of the clock tree keep accessing the registers
+    /// of these peripherals. None of them may be used while the PAC structs
+    /// are in use elsewhere.
+    #[inline]
+    pub unsafe fn free(self) -> (OSCCTRL, OSC32KCTRL, GCLK) {
+        (self.oscctrl, self.osc32kctrl, self.gclk)
+    }
+
+    /// Access the status, interrupts and events of the clock failure
+    /// detectors
+    #[inline]
+    pub fn clock_failure_detector(&mut self) -> ClockFailureDetector<'_> {
+        ClockFailureDetector::new(&mut self.oscctrl, &mut self.osc32kctrl)
+    }
+}
+
+/// Clocks that are enabled at power-on reset
+pub struct Clocks {
+    /// PAC structs of the clocking peripherals
+    pub pac: Pac,
+    /// The DFLL, running at 48MHz in open-loop mode and driving GCLK0
+    pub dfll: Enabled<Dfll, U1>,
+    /// Generic clock generator 0, which clocks the CPU. Its count starts at
+    /// one to represent the CPU, so it can never be disabled.
+    pub gclk0: Enabled<Gclk0<DfllId>, U1>,
+    /// The always-on ultra low power 32kHz oscillator
+    pub osculp32k: Enabled<OscUlp32k>,
+}
+
+/// Tokens for the clocks that are disabled at power-on reset
+pub struct Tokens {
+    /// Token for XOSC0
+    pub xosc0: XoscToken<Xosc0Id>,
+    /// Token for XOSC1
+    pub xosc1: XoscToken<Xosc1Id>,
+    /// Token for XOSC32K
+    pub xosc32k: Xosc32kToken,
+    /// Token for DPLL0
+    pub dpll0: DpllToken<Dpll0Id>,
+    /// Token for DPLL1
+    pub dpll1: DpllToken<Dpll1Id>,
+    /// Tokens for generic clock generators 1 to 11
+    pub gclks: GclkTokens,
+    /// Tokens for the peripheral channels
+    pub pclks: PclkTokens,
+}
+
+/// Take ownership of the clocking peripherals, and return the clock tree in
+/// its power-on reset state
+///
+/// This function assumes that the clocking peripherals have not been
+/// reconfigured since reset, e.g. by a bootloader or by the
+/// [`GenericClockController`](super::v1::GenericClockController).
+#[inline]
+pub fn clock_system_at_reset(
+    oscctrl: OSCCTRL,
+    osc32kctrl: OSC32KCTRL,
+    gclk: GCLK,
+) -> (Clocks, Tokens) {
+    // SAFETY: We take ownership of the clocking peripherals, so each token
+    // is a singleton
+    unsafe {
+        let clocks = Clocks {
+            pac: Pac {
+                oscctrl,
+                osc32kctrl,
+                gclk,
+            },
+            dfll: Enabled::new(Dfll::new()),
+            gclk0: Enabled::new(Gclk0::at_reset()),
+            osculp32k: Enabled::new(OscUlp32k::new()),
+        };
+        let tokens = Tokens {
+            xosc0: XoscToken::new(),
+            xosc1: XoscToken::new(),
+            xosc32k: Xosc32kToken::new(),
+            dpll0: DpllToken::new(),
+            dpll1: DpllToken::new(),
+            gclks: GclkTokens::new(),
+            pclks: PclkTokens::new(),
+        };
+        (clocks, tokens)
+    }
+}
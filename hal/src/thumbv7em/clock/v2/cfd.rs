@@ -0,0 +1,151 @@
+//! # Clock failure detection
+//!
+//! XOSC0, XOSC1 and XOSC32K each have a clock failure detector (CFD), which
+//! monitors the oscillator against a safe clock. The detectors are enabled
+//! when configuring the oscillators, with
+//! [`Xosc::clock_failure_detection`](super::xosc::Xosc::clock_failure_detection)
+//! and
+//! [`Xosc32k::clock_failure_detection`](super::xosc32k::Xosc32k::clock_failure_detection),
+//! or with
+//! [`XoscConfig::clock_failure_detection`](super::xosc::XoscConfig::clock_failure_detection)
+//! for the [`v1`](crate::clock::v1) API.
+//!
+//! When an oscillator fails, its output is switched to the safe clock in
+//! hardware, so that the clocks derived from it keep running, and a failure
+//! is flagged. [`ClockFailureDetector`] reports which oscillators have
+//! failed, and controls the failure interrupts and event outputs.
+//!
+//! ```
+//! let mut cfd = ClockFailureDetector::new(&mut oscctrl, &mut osc32kctrl);
+//! cfd.enable_interrupts(ClockFailures::XOSC0 | ClockFailures::XOSC32K);
+//!
+//! // In the OSCCTRL and OSC32KCTRL interrupt handlers
+//! let failures = cfd.failures();
+//! cfd.clear(failures);
+//! ```
+
+use bitflags::bitflags;
+
+use crate::pac::{OSC32KCTRL, OSCCTRL};
+
+bitflags! {
+    /// Oscillators monitored by a clock failure detector
+    pub struct ClockFailures: u8 {
+        const XOSC0 = 1;
+        const XOSC1 = 2;
+        const XOSC32K = 4;
+    }
+}
+
+/// Status, interrupts and events of the clock failure detectors
+///
+/// The [`v2`](super) API owns the PAC structs, and provides this type with
+/// [`Pac::clock_failure_detector`](super::Pac::clock_failure_detector).
+pub struct ClockFailureDetector<'a> {
+    oscctrl: &'a mut OSCCTRL,
+    osc32kctrl: &'a mut OSC32KCTRL,
+}
+
+impl<'a> ClockFailureDetector<'a> {
+    /// Borrow the clocking peripherals to access the clock failure
+    /// detectors
+    #[inline]
+    pub fn new(oscctrl: &'a mut OSCCTRL, osc32kctrl: &'a mut OSC32KCTRL) -> Self {
+        Self {
+            oscctrl,
+            osc32kctrl,
+        }
+    }
+
+    /// Returns the oscillators that have failed since the flags were last
+    /// cleared
+    #[inline]
+    pub fn failures(&self) -> ClockFailures {
+        let intflag = self.oscctrl.intflag.read();
+        let mut failures = ClockFailures::empty();
+        failures.set(ClockFailures::XOSC0, intflag.xoscfail0().bit_is_set());
+        failures.set(ClockFailures::XOSC1, intflag.xoscfail1().bit_is_set());
+        failures.set(
+            ClockFailures::XOSC32K,
+            self.osc32kctrl.intflag.read().xosc32kfail().bit_is_set(),
+        );
+        failures
+    }
+
+    /// Returns the oscillators whose output is currently switched to the
+    /// safe clock
+    #[inline]
+    pub fn switched(&self) -> ClockFailures {
+        let status = self.oscctrl.status.read();
+        let mut switched = ClockFailures::empty();
+        switched.set(ClockFailures::XOSC0, status.xosccksw0().bit_is_set());
+        switched.set(ClockFailures::XOSC1, status.xosccksw1().bit_is_set());
+        switched.set(
+            ClockFailures::XOSC32K,
+            self.osc32kctrl.status.read().xosc32ksw().bit_is_set(),
+        );
+        switched
+    }
+
+    /// Clear the failure flags of the specified oscillators
+    #[inline]
+    pub fn clear(&mut self, failures: ClockFailures) {
+        self.oscctrl.intflag.write(|w| {
+            w.xoscfail0().bit(failures.contains(ClockFailures::XOSC0));
+            w.xoscfail1().bit(failures.contains(ClockFailures::XOSC1))
+        });
+        self.osc32kctrl.intflag.write(|w| {
+            w.xosc32kfail()
+                .bit(failures.contains(ClockFailures::XOSC32K))
+        });
+    }
+
+    /// Enable the failure interrupts of the specified oscillators
+    ///
+    /// Failures of XOSC0 and XOSC1 are signalled on the `OSCCTRL_XOSC0` and
+    /// `OSCCTRL_XOSC1` interrupts, and failures of XOSC32K on the
+    /// `OSC32KCTRL` interrupt.
+    #[inline]
+    pub fn enable_interrupts(&mut self, failures: ClockFailures) {
+        self.oscctrl.intenset.write(|w| {
+            w.xoscfail0().bit(failures.contains(ClockFailures::XOSC0));
+            w.xoscfail1().bit(failures.contains(ClockFailures::XOSC1))
+        });
+        self.osc32kctrl.intenset.write(|w| {
+            w.xosc32kfail()
+                .bit(failures.contains(ClockFailures::XOSC32K))
+        });
+    }
+
+    /// Disable the failure interrupts of the specified oscillators
+    #[inline]
+    pub fn disable_interrupts(&mut self, failures: ClockFailures) {
+        self.oscctrl.intenclr.write(|w| {
+            w.xoscfail0().bit(failures.contains(ClockFailures::XOSC0));
+            w.xoscfail1().bit(failures.contains(ClockFailures::XOSC1))
+        });
+        self.osc32kctrl.intenclr.write(|w| {
+            w.xosc32kfail()
+                .bit(failures.contains(ClockFailures::XOSC32K))
+        });
+    }
+
+    /// Enable or disable the failure event outputs of the specified
+    /// oscillators, so that a failure can trigger other peripherals through
+    /// the event system
+    #[inline]
+    pub fn set_events(&mut self, failures: ClockFailures, enable: bool) {
+        self.oscctrl.evctrl.modify(|_, w| {
+            if failures.contains(ClockFailures::XOSC0) {
+                w.cfdeo0().bit(enable);
+            }
+            if failures.contains(ClockFailures::XOSC1) {
+                w.cfdeo1().bit(enable);
+            }
+            w
+        });
+        if failures.contains(ClockFailures::XOSC32K) {
+            self.osc32kctrl.evctrl.modify(|_, w| w.cfdeo().bit(enable));
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! # Digital frequency locked loop
+//!
+//! At reset, the DFLL runs at 48MHz in open-loop mode and drives GCLK0, so
+//! it is returned as an [`Enabled`] clock by
+//! [`clock_system_at_reset`](super::clock_system_at_reset). It can be
+//! disabled once nothing uses it anymore, e.g. after GCLK0 has been switched
+//! to another source with
+//! `swap_sources`, and re-enabled later.
+
+use typenum::U0;
+
+use crate::pac::{self, OSCCTRL};
+use crate::time::Hertz;
+use crate::typelevel::Sealed;
+
+use super::{Enabled, Source};
+
+/// Type-level identifier of the DFLL
+pub enum DfllId {}
+
+impl Sealed for DfllId {}
+
+/// The DFLL, in 48MHz open-loop mode
+pub struct Dfll {
+    _private: (),
+}
+
+impl Dfll {
+    /// Create the DFLL
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one instance of the DFLL.
+    #[inline]
+    pub(super) unsafe fn new() -> Self {
+        Dfll { _private: () }
+    }
+
+    #[inline]
+    fn oscctrl(&self) -> &pac::oscctrl::RegisterBlock {
+        // SAFETY: The Dfll grants exclusive access to the DFLL registers
+        unsafe { &*OSCCTRL::ptr() }
+    }
+
+    /// Enable the DFLL, and wait until it is ready
+    #[inline]
+    pub fn enable(self) -> Enabled<Self> {
+        let oscctrl = self.oscctrl();
+        oscctrl.dfllctrla.modify(|_, w| w.enable().set_bit());
+        while oscctrl.dfllsync.read().enable().bit_is_set() {}
+        while oscctrl.status.read().dfllrdy().bit_is_clear() {}
+        Enabled::new(self)
+    }
+}
+
+impl Enabled<Dfll, U0> {
+    /// Disable the DFLL
+    #[inline]
+    pub fn disable(self) -> Dfll {
+        let oscctrl = self.clock.oscctrl();
+        oscctrl.dfllctrla.modify(|_, w| w.enable().clear_bit());
+        while oscctrl.dfllsync.read().enable().bit_is_set() {}
+        self.clock
+    }
+}
+
+impl<N> Source for Enabled<Dfll, N> {
+    type Id = DfllId;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        Hertz(48_000_000)
+    }
+}
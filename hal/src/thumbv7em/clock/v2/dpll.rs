@@ -0,0 +1,360 @@
+//! # Fractional digital phase-locked loops
+//!
+//! DPLL0 and DPLL1 multiply a reference clock of 32kHz to 3.2MHz by a
+//! fractional ratio, to produce a clock of 96MHz to 200MHz. The reference is
+//! either the XOSC32K, one of the XOSCs (divided by `2 * (div + 1)`), or a
+//! generic clock generator connected through the DPLL peripheral channel.
+//!
+//! The reference is tracked by the `R` type parameter of [`Dpll`], and is
+//! released along with the DPLL token when the DPLL is freed.
+//!
+//! ```
+//! let (dpll1, xosc32k) = Dpll::from_xosc32k(tokens.dpll1, xosc32k);
+//! // 32.768kHz * 3662 = 119.996MHz
+//! let dpll1 = dpll1.loop_div(3661, 0).enable();
+//! ```
+
+use core::marker::PhantomData;
+
+use typenum::U0;
+
+use crate::pac::oscctrl::dpll::dpllctrlb::REFCLK_A;
+use crate::pac::{self, OSCCTRL};
+use crate::time::Hertz;
+use crate::typelevel::{Decrement, Increment, Sealed};
+
+use super::gclk::GclkId;
+use super::pclk::{FDpll0, FDpll1, Pclk, PclkId};
+use super::xosc::XoscId;
+use super::xosc32k::Xosc32kId;
+use super::{Enabled, Source};
+
+//==============================================================================
+// Ids
+//==============================================================================
+
+/// Type-level identifier of a DPLL
+pub trait DpllId: Sealed {
+    /// Index of the DPLL
+    const NUM: usize;
+    /// Peripheral channel used when the DPLL is referenced to a generator
+    type Pclk: PclkId;
+}
+
+/// Type-level identifier of DPLL0
+pub enum Dpll0Id {}
+
+impl Sealed for Dpll0Id {}
+
+impl DpllId for Dpll0Id {
+    const NUM: usize = 0;
+    type Pclk = FDpll0;
+}
+
+/// Type-level identifier of DPLL1
+pub enum Dpll1Id {}
+
+impl Sealed for Dpll1Id {}
+
+impl DpllId for Dpll1Id {
+    const NUM: usize = 1;
+    type Pclk = FDpll1;
+}
+
+//==============================================================================
+// DpllToken
+//==============================================================================
+
+/// Singleton token granting access to the registers of a DPLL
+pub struct DpllToken<D: DpllId> {
+    dpll: PhantomData<D>,
+}
+
+impl<D: DpllId> DpllToken<D> {
+    /// Create a new token
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one token for each DPLL.
+    #[inline]
+    pub(super) unsafe fn new() -> Self {
+        DpllToken { dpll: PhantomData }
+    }
+
+    #[inline]
+    fn dpll(&self) -> &pac::oscctrl::DPLL {
+        // SAFETY: The token grants exclusive access to the registers of this
+        // DPLL
+        unsafe { &(*OSCCTRL::ptr()).dpll[D::NUM] }
+    }
+
+    #[inline]
+    fn disable(&mut self) {
+        let dpll = self.dpll();
+        dpll.dpllctrla.modify(|_, w| w.enable().clear_bit());
+        while dpll.dpllsyncbusy.read().enable().bit_is_set() {}
+    }
+}
+
+//==============================================================================
+// References
+//==============================================================================
+
+/// Reference clock of a DPLL
+pub trait DpllReference: Sealed {
+    /// Value of the `DPLLCTRLB.REFCLK` field selecting this reference
+    const DYN: REFCLK_A;
+
+    /// Returns the frequency of the reference, after division
+    fn freq(&self) -> Hertz;
+
+    /// Returns the value of the `DPLLCTRLB.DIV` field
+    fn div(&self) -> u16 {
+        0
+    }
+}
+
+/// An external oscillator used as a DPLL reference
+pub struct XoscReference<X: XoscId> {
+    xosc: PhantomData<X>,
+    freq: Hertz,
+    div: u16,
+}
+
+impl<X: XoscId> Sealed for XoscReference<X> {}
+
+impl<X: XoscId> DpllReference for XoscReference<X> {
+    const DYN: REFCLK_A = match X::NUM {
+        0 => REFCLK_A::XOSC0,
+        _ => REFCLK_A::XOSC1,
+    };
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        Hertz(self.freq.0 / (2 * (self.div as u32 + 1)))
+    }
+
+    #[inline]
+    fn div(&self) -> u16 {
+        self.div
+    }
+}
+
+/// The XOSC32K used as a DPLL reference
+pub struct Xosc32kReference {
+    _private: (),
+}
+
+impl Sealed for Xosc32kReference {}
+
+impl DpllReference for Xosc32kReference {
+    const DYN: REFCLK_A = REFCLK_A::XOSC32;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        Hertz(32_768)
+    }
+}
+
+/// A peripheral channel used as a DPLL reference, driven by the generator
+/// `G`
+pub struct PclkReference<P: PclkId, G: GclkId> {
+    pclk: Pclk<P, G>,
+}
+
+impl<P: PclkId, G: GclkId> Sealed for PclkReference<P, G> {}
+
+impl<P: PclkId, G: GclkId> DpllReference for PclkReference<P, G> {
+    const DYN: REFCLK_A = REFCLK_A::GCLK;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        self.pclk.freq()
+    }
+}
+
+//==============================================================================
+// Dpll
+//==============================================================================
+
+/// A DPLL `D`, with the reference `R`
+pub struct Dpll<D: DpllId, R: DpllReference> {
+    token: DpllToken<D>,
+    reference: R,
+    ldr: u16,
+    ldrfrac: u8,
+    on_demand: bool,
+    run_standby: bool,
+}
+
+impl<D: DpllId, R: DpllReference> Dpll<D, R> {
+    #[inline]
+    fn new(token: DpllToken<D>, reference: R) -> Self {
+        Dpll {
+            token,
+            reference,
+            ldr: 0,
+            ldrfrac: 0,
+            on_demand: false,
+            run_standby: false,
+        }
+    }
+
+    /// Set the integer (`ldr`, 13 bits) and fractional (`ldrfrac`, 5 bits)
+    /// parts of the loop divider ratio. The output frequency is
+    /// `fref * (ldr + 1 + ldrfrac / 32)`.
+    #[inline]
+    pub fn loop_div(mut self, ldr: u16, ldrfrac: u8) -> Self {
+        if ldr >= 2_u16.pow(13) || ldrfrac >= 32 {
+            panic!("invalid DPLL ratio {}.{}", ldr, ldrfrac);
+        }
+        self.ldr = ldr;
+        self.ldrfrac = ldrfrac;
+        self
+    }
+
+    /// Only run the DPLL when it is requested by a consumer
+    #[inline]
+    pub fn on_demand(mut self, enable: bool) -> Self {
+        self.on_demand = enable;
+        self
+    }
+
+    /// Keep the DPLL running in standby sleep mode
+    #[inline]
+    pub fn run_standby(mut self, enable: bool) -> Self {
+        self.run_standby = enable;
+        self
+    }
+
+    /// Returns the output frequency of the DPLL
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        let ratio = 32 * (self.ldr as u64 + 1) + self.ldrfrac as u64;
+        Hertz((self.reference.freq().0 as u64 * ratio / 32) as u32)
+    }
+
+    /// Enable the DPLL. Unless it runs on demand, this waits until it is
+    /// locked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reference or output frequencies are out of range.
+    #[inline]
+    pub fn enable(self) -> Enabled<Self> {
+        let ref_freq = self.reference.freq().0;
+        if !(32_000..=3_200_000).contains(&ref_freq) {
+            panic!("invalid DPLL reference frequency {}", ref_freq);
+        }
+        let freq = self.freq().0;
+        if !(96_000_000..=200_000_000).contains(&freq) {
+            panic!("invalid DPLL output frequency {}", freq);
+        }
+
+        let dpll = self.token.dpll();
+        dpll.dpllratio.write(|w| unsafe {
+            w.ldr().bits(self.ldr);
+            w.ldrfrac().bits(self.ldrfrac)
+        });
+        while dpll.dpllsyncbusy.read().dpllratio().bit_is_set() {}
+        dpll.dpllctrlb.write(|w| unsafe {
+            w.refclk().variant(R::DYN);
+            w.div().bits(self.reference.div())
+        });
+        dpll.dpllctrla.write(|w| {
+            w.ondemand().bit(self.on_demand);
+            w.runstdby().bit(self.run_standby);
+            w.enable().set_bit()
+        });
+        while dpll.dpllsyncbusy.read().enable().bit_is_set() {}
+        if !self.on_demand {
+            while dpll.dpllstatus.read().lock().bit_is_clear()
+                || dpll.dpllstatus.read().clkrdy().bit_is_clear()
+            {}
+        }
+        Enabled::new(self)
+    }
+}
+
+impl<D: DpllId, X: XoscId> Dpll<D, XoscReference<X>> {
+    /// Create a DPLL referenced to an external oscillator, divided by
+    /// `2 * (div + 1)`, and increment the count of the oscillator
+    #[inline]
+    pub fn from_xosc<S>(token: DpllToken<D>, xosc: S, div: u16) -> (Self, S::Inc)
+    where
+        S: Source<Id = X> + Increment,
+    {
+        if div >= 2_u16.pow(11) {
+            panic!("invalid DPLL divider {}", div);
+        }
+        let reference = XoscReference {
+            xosc: PhantomData,
+            freq: xosc.freq(),
+            div,
+        };
+        (Self::new(token, reference), xosc.inc())
+    }
+
+    /// Release the token, and decrement the count of the oscillator
+    #[inline]
+    pub fn free_xosc<S>(self, xosc: S) -> (DpllToken<D>, S::Dec)
+    where
+        S: Source<Id = X> + Decrement,
+    {
+        (self.token, xosc.dec())
+    }
+}
+
+impl<D: DpllId> Dpll<D, Xosc32kReference> {
+    /// Create a DPLL referenced to the XOSC32K, and increment its count
+    #[inline]
+    pub fn from_xosc32k<S>(token: DpllToken<D>, xosc32k: S) -> (Self, S::Inc)
+    where
+        S: Source<Id = Xosc32kId> + Increment,
+    {
+        let reference = Xosc32kReference { _private: () };
+        (Self::new(token, reference), xosc32k.inc())
+    }
+
+    /// Release the token, and decrement the count of the XOSC32K
+    #[inline]
+    pub fn free_xosc32k<S>(self, xosc32k: S) -> (DpllToken<D>, S::Dec)
+    where
+        S: Source<Id = Xosc32kId> + Decrement,
+    {
+        (self.token, xosc32k.dec())
+    }
+}
+
+impl<D: DpllId, G: GclkId> Dpll<D, PclkReference<D::Pclk, G>> {
+    /// Create a DPLL referenced to a generic clock generator, through its
+    /// peripheral channel
+    #[inline]
+    pub fn from_pclk(token: DpllToken<D>, pclk: Pclk<D::Pclk, G>) -> Self {
+        Self::new(token, PclkReference { pclk })
+    }
+
+    /// Release the token and the peripheral channel
+    #[inline]
+    pub fn free_pclk(self) -> (DpllToken<D>, Pclk<D::Pclk, G>) {
+        (self.token, self.reference.pclk)
+    }
+}
+
+impl<D: DpllId, R: DpllReference> Enabled<Dpll<D, R>, U0> {
+    /// Disable the DPLL
+    #[inline]
+    pub fn disable(mut self) -> Dpll<D, R> {
+        self.clock.token.disable();
+        self.clock
+    }
+}
+
+impl<D: DpllId, R: DpllReference, N> Source for Enabled<Dpll<D, R>, N> {
+    type Id = D;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        self.clock.freq()
+    }
+}
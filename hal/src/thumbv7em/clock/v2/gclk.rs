@@ -0,0 +1,389 @@
+//! # Generic clock generators
+//!
+//! Each of the twelve generic clock generators divides the frequency of one
+//! source clock, and drives any number of peripheral channels. GCLK0 also
+//! clocks the CPU. It is always enabled, and is returned as an [`Enabled`]
+//! clock by [`clock_system_at_reset`](super::clock_system_at_reset). The
+//! other generators are built from [`GclkToken`]s.
+//!
+//! ```
+//! let (gclk1, xosc32k) = Gclk::new(tokens.gclks.gclk1, xosc32k);
+//! let gclk1 = gclk1.divider(32).enable();
+//! ```
+
+use core::marker::PhantomData;
+
+use typenum::{U0, U1};
+
+use crate::pac::gclk::genctrl::SRC_A;
+use crate::pac::{self, GCLK};
+use crate::time::Hertz;
+use crate::typelevel::{Decrement, Increment, Sealed};
+
+use super::dfll::DfllId;
+use super::dpll::{Dpll0Id, Dpll1Id};
+use super::osculp32k::OscUlp32kId;
+use super::xosc::{Xosc0Id, Xosc1Id};
+use super::xosc32k::Xosc32kId;
+use super::{Enabled, Source};
+
+//==============================================================================
+// Ids
+//==============================================================================
+
+/// Type-level identifier of a generic clock generator
+pub trait GclkId: Sealed {
+    /// Index of the generator
+    const NUM: usize;
+    /// Largest valid division factor
+    const DIV_MAX: u32;
+}
+
+/// Type-level identifier of a clock that can drive a generic clock generator
+pub trait GclkSourceId: Sealed {
+    /// Value of the `GENCTRL.SRC` field selecting this clock
+    const DYN: SRC_A;
+}
+
+macro_rules! gclk_ids {
+    ($($Id:ident: ($num:literal, $div_max:expr),)+) => {
+        $(
+            #[doc = concat!("Type-level identifier of GCLK", stringify!($num))]
+            pub enum $Id {}
+
+            impl Sealed for $Id {}
+
+            impl GclkId for $Id {
+                const NUM: usize = $num;
+                const DIV_MAX: u32 = $div_max;
+            }
+        )+
+    };
+}
+
+gclk_ids!(
+    Gclk0Id: (0, 255),
+    Gclk1Id: (1, 65535),
+    Gclk2Id: (2, 255),
+    Gclk3Id: (3, 255),
+    Gclk4Id: (4, 255),
+    Gclk5Id: (5, 255),
+    Gclk6Id: (6, 255),
+    Gclk7Id: (7, 255),
+    Gclk8Id: (8, 255),
+    Gclk9Id: (9, 255),
+    Gclk10Id: (10, 255),
+    Gclk11Id: (11, 255),
+);
+
+macro_rules! gclk_source_ids {
+    ($($Id:ident: $src:ident,)+) => {
+        $(
+            impl GclkSourceId for $Id {
+                const DYN: SRC_A = SRC_A::$src;
+            }
+        )+
+    };
+}
+
+gclk_source_ids!(
+    Xosc0Id: XOSC0,
+    Xosc1Id: XOSC1,
+    OscUlp32kId: OSCULP32K,
+    Xosc32kId: XOSC32K,
+    DfllId: DFLL,
+    Dpll0Id: DPLL0,
+    Dpll1Id: DPLL1,
+    Gclk1Id: GCLKGEN1,
+);
+
+//==============================================================================
+// GclkToken
+//==============================================================================
+
+/// Singleton token granting access to the registers of a generic clock
+/// generator
+pub struct GclkToken<G: GclkId> {
+    gen: PhantomData<G>,
+}
+
+impl<G: GclkId> GclkToken<G> {
+    /// Create a new token
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one token for each generator.
+    #[inline]
+    unsafe fn new() -> Self {
+        GclkToken { gen: PhantomData }
+    }
+
+    #[inline]
+    fn gclk(&self) -> &pac::gclk::RegisterBlock {
+        // SAFETY: The token grants exclusive access to the GENCTRL register
+        // of this generator, and the SYNCBUSY register is only read
+        unsafe { &*GCLK::ptr() }
+    }
+
+    #[inline]
+    fn wait_for_sync(&self) {
+        let mask = 1 << (G::NUM + 2);
+        while self.gclk().syncbusy.read().bits() & mask != 0 {}
+    }
+
+    #[inline]
+    fn write(&mut self, settings: &Settings, src: SRC_A) {
+        self.gclk().genctrl[G::NUM].write(|w| unsafe {
+            w.src().variant(src);
+            w.div().bits(settings.div);
+            // divide directly by div, rather than 2^(div+1)
+            w.divsel().clear_bit();
+            w.idc().bit(settings.improve_duty_cycle);
+            w.oe().bit(settings.output);
+            w.runstdby().bit(settings.run_standby);
+            w.genen().set_bit()
+        });
+        self.wait_for_sync();
+    }
+
+    #[inline]
+    fn disable(&mut self) {
+        self.gclk().genctrl[G::NUM].modify(|_, w| w.genen().clear_bit());
+        self.wait_for_sync();
+    }
+}
+
+/// Tokens for generic clock generators 1 to 11
+pub struct GclkTokens {
+    /// Token for GCLK1
+    pub gclk1: GclkToken<Gclk1Id>,
+    /// Token for GCLK2
+    pub gclk2: GclkToken<Gclk2Id>,
+    /// Token for GCLK3
+    pub gclk3: GclkToken<Gclk3Id>,
+    /// Token for GCLK4
+    pub gclk4: GclkToken<Gclk4Id>,
+    /// Token for GCLK5
+    pub gclk5: GclkToken<Gclk5Id>,
+    /// Token for GCLK6
+    pub gclk6: GclkToken<Gclk6Id>,
+    /// Token for GCLK7
+    pub gclk7: GclkToken<Gclk7Id>,
+    /// Token for GCLK8
+    pub gclk8: GclkToken<Gclk8Id>,
+    /// Token for GCLK9
+    pub gclk9: GclkToken<Gclk9Id>,
+    /// Token for GCLK10
+    pub gclk10: GclkToken<Gclk10Id>,
+    /// Token for GCLK11
+    pub gclk11: GclkToken<Gclk11Id>,
+}
+
+impl GclkTokens {
+    /// Create the tokens
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one instance of each token.
+    #[inline]
+    pub(super) unsafe fn new() -> Self {
+        GclkTokens {
+            gclk1: GclkToken::new(),
+            gclk2: GclkToken::new(),
+            gclk3: GclkToken::new(),
+            gclk4: GclkToken::new(),
+            gclk5: GclkToken::new(),
+            gclk6: GclkToken::new(),
+            gclk7: GclkToken::new(),
+            gclk8: GclkToken::new(),
+            gclk9: GclkToken::new(),
+            gclk10: GclkToken::new(),
+            gclk11: GclkToken::new(),
+        }
+    }
+}
+
+//==============================================================================
+// Gclk
+//==============================================================================
+
+#[derive(Clone, Copy)]
+struct Settings {
+    div: u16,
+    improve_duty_cycle: bool,
+    output: bool,
+    run_standby: bool,
+}
+
+/// A generic clock generator `G`, driven by the source `I`
+pub struct Gclk<G: GclkId, I: GclkSourceId> {
+    token: GclkToken<G>,
+    src: PhantomData<I>,
+    src_freq: Hertz,
+    settings: Settings,
+}
+
+/// Generic clock generator 0, which clocks the CPU
+pub type Gclk0<I> = Gclk<Gclk0Id, I>;
+
+impl<G: GclkId, I: GclkSourceId> Gclk<G, I> {
+    /// Create a generator driven by `source`, and increment the count of
+    /// `source`. The generator initially divides by one.
+    #[inline]
+    pub fn new<S>(token: GclkToken<G>, source: S) -> (Self, S::Inc)
+    where
+        S: Source<Id = I> + Increment,
+    {
+        let gclk = Gclk {
+            token,
+            src: PhantomData,
+            src_freq: source.freq(),
+            settings: Settings {
+                div: 1,
+                improve_duty_cycle: false,
+                output: false,
+                run_standby: false,
+            },
+        };
+        (gclk, source.inc())
+    }
+
+    /// Release the token, and decrement the count of `source`
+    #[inline]
+    pub fn free<S>(self, source: S) -> (GclkToken<G>, S::Dec)
+    where
+        S: Source<Id = I> + Decrement,
+    {
+        (self.token, source.dec())
+    }
+
+    /// Set the linear division factor
+    #[inline]
+    pub fn divider(mut self, div: u16) -> Self {
+        if div == 0 || div as u32 > G::DIV_MAX {
+            panic!("invalid divisor {} for GCLK {}", div, G::NUM);
+        }
+        self.settings.div = div;
+        self
+    }
+
+    /// Enable a 50/50 duty cycle for odd division factors
+    #[inline]
+    pub fn improve_duty_cycle(mut self, enable: bool) -> Self {
+        self.settings.improve_duty_cycle = enable;
+        self
+    }
+
+    /// Output the clock on the GCLK_IO pin of the generator. The pin must be
+    /// put into its GCLK_IO alternate function separately.
+    #[inline]
+    pub fn output(mut self, enable: bool) -> Self {
+        self.settings.output = enable;
+        self
+    }
+
+    /// Keep the generator running in standby sleep mode
+    #[inline]
+    pub fn run_standby(mut self, enable: bool) -> Self {
+        self.settings.run_standby = enable;
+        self
+    }
+
+    /// Returns the frequency of the generator
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        Hertz(self.src_freq.0 / self.settings.div as u32)
+    }
+
+    /// Enable the generator
+    #[inline]
+    pub fn enable(mut self) -> Enabled<Self> {
+        self.token.write(&self.settings, I::DYN);
+        Enabled::new(self)
+    }
+}
+
+impl<G: GclkId, I: GclkSourceId> Enabled<Gclk<G, I>, U0> {
+    /// Disable the generator
+    #[inline]
+    pub fn disable(mut self) -> Gclk<G, I> {
+        self.clock.token.disable();
+        self.clock
+    }
+}
+
+impl Gclk0<DfllId> {
+    /// Create GCLK0 in its reset state, driven by the DFLL
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one instance of GCLK0.
+    #[inline]
+    pub(super) unsafe fn at_reset() -> Self {
+        Gclk {
+            token: GclkToken::new(),
+            src: PhantomData,
+            src_freq: Hertz(48_000_000),
+            settings: Settings {
+                div: 1,
+                improve_duty_cycle: false,
+                output: false,
+                run_standby: false,
+            },
+        }
+    }
+}
+
+impl<I: GclkSourceId> Enabled<Gclk0<I>, U1> {
+    /// Switch GCLK0, and thus the CPU, to another source
+    ///
+    /// This is only possible while the CPU is the only consumer of GCLK0, so
+    /// that no peripheral channel is affected by the change of frequency.
+    /// The count of the `old` source is decremented, and the count of the
+    /// `new` source is incremented.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn swap_sources<Old, New>(
+        self,
+        old: Old,
+        new: New,
+    ) -> (Enabled<Gclk0<New::Id>, U1>, Old::Dec, New::Inc)
+    where
+        Old: Source<Id = I> + Decrement,
+        New: Source + Increment,
+        New::Id: GclkSourceId,
+    {
+        let mut gclk = Gclk {
+            token: self.clock.token,
+            src: PhantomData,
+            src_freq: new.freq(),
+            settings: self.clock.settings,
+        };
+        gclk.token.write(&gclk.settings, New::Id::DYN);
+        (Enabled::new(gclk), old.dec(), new.inc())
+    }
+
+    /// Change the division factor of GCLK0, and thus the CPU frequency
+    ///
+    /// This is only possible while the CPU is the only consumer of GCLK0.
+    #[inline]
+    pub fn set_div(&mut self, div: u16) {
+        let Gclk {
+            token, settings, ..
+        } = &mut self.clock;
+        if div == 0 || div as u32 > Gclk0Id::DIV_MAX {
+            panic!("invalid divisor {} for GCLK 0", div);
+        }
+        settings.div = div;
+        token.write(settings, I::DYN);
+    }
+}
+
+impl<G: GclkId, I: GclkSourceId, N> Source for Enabled<Gclk<G, I>, N> {
+    type Id = G;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        self.clock.freq()
+    }
+}
@@ -0,0 +1,41 @@
+//! # Ultra low power 32kHz oscillator
+//!
+//! The OSCULP32K is always running, and cannot be disabled. It is returned
+//! as an [`Enabled`] clock by
+//! [`clock_system_at_reset`](super::clock_system_at_reset).
+
+use crate::time::Hertz;
+use crate::typelevel::Sealed;
+
+use super::{Enabled, Source};
+
+/// Type-level identifier of the OSCULP32K
+pub enum OscUlp32kId {}
+
+impl Sealed for OscUlp32kId {}
+
+/// The ultra low power 32kHz oscillator
+pub struct OscUlp32k {
+    _private: (),
+}
+
+impl OscUlp32k {
+    /// Create the oscillator
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one instance of the oscillator.
+    #[inline]
+    pub(super) unsafe fn new() -> Self {
+        OscUlp32k { _private: () }
+    }
+}
+
+impl<N> Source for Enabled<OscUlp32k, N> {
+    type Id = OscUlp32kId;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        Hertz(32_768)
+    }
+}
@@ -0,0 +1,264 @@
+//! # Peripheral channels
+//!
+//! Each peripheral channel connects one generic clock generator to one or
+//! more peripherals. A [`Pclk`] is built from a [`PclkToken`] and an enabled
+//! generator, and its frequency is that of the generator.
+//!
+//! Existing drivers expect the clock tokens of the [`v1`](super::super::v1)
+//! API. `Pclk::into_v1` trades a [`Pclk`] for the corresponding token and a
+//! [`LentPclk`], which gives the [`Pclk`] back once the token is returned, so
+//! that the channel cannot be disabled while a driver still uses it:
+//!
+//! ```
+//! let (sercom0, gclk2) = Pclk::enable(tokens.pclks.sercom0_core, gclk2);
+//! let (sercom0_clock, sercom0) = sercom0.into_v1();
+//! // ...
+//! let sercom0 = sercom0.restore(sercom0_clock);
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::pac::{self, GCLK};
+use crate::time::Hertz;
+use crate::typelevel::{Decrement, Increment, Sealed};
+
+use super::super::v1::{self, ClockId};
+use super::gclk::GclkId;
+use super::Source;
+
+/// Type-level identifier of a peripheral channel
+pub trait PclkId: Sealed {
+    /// Value-level identifier of the channel
+    const DYN: ClockId;
+}
+
+/// Singleton token granting access to the register of a peripheral channel
+pub struct PclkToken<P: PclkId> {
+    pclk: PhantomData<P>,
+}
+
+impl<P: PclkId> PclkToken<P> {
+    /// Create a new token
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one token for each channel.
+    #[inline]
+    unsafe fn new() -> Self {
+        PclkToken { pclk: PhantomData }
+    }
+
+    #[inline]
+    fn gclk(&self) -> &pac::gclk::RegisterBlock {
+        // SAFETY: The token grants exclusive access to the PCHCTRL register
+        // of this channel
+        unsafe { &*GCLK::ptr() }
+    }
+
+    #[inline]
+    fn enable(&mut self, gen: usize) {
+        self.gclk().pchctrl[P::DYN as usize].write(|w| unsafe {
+            w.gen().bits(gen as u8);
+            w.chen().set_bit()
+        });
+        while self.gclk().pchctrl[P::DYN as usize]
+            .read()
+            .chen()
+            .bit_is_clear()
+        {}
+    }
+
+    #[inline]
+    fn disable(&mut self) {
+        self.gclk().pchctrl[P::DYN as usize].modify(|_, w| w.chen().clear_bit());
+        while self.gclk().pchctrl[P::DYN as usize]
+            .read()
+            .chen()
+            .bit_is_set()
+        {}
+    }
+}
+
+/// A peripheral channel `P`, driven by the generic clock generator `G`
+pub struct Pclk<P: PclkId, G: GclkId> {
+    token: PclkToken<P>,
+    gen: PhantomData<G>,
+    freq: Hertz,
+}
+
+impl<P: PclkId, G: GclkId> Pclk<P, G> {
+    /// Enable the channel, driven by `gclk`, and increment the count of
+    /// `gclk`
+    #[inline]
+    pub fn enable<S>(mut token: PclkToken<P>, gclk: S) -> (Self, S::Inc)
+    where
+        S: Source<Id = G> + Increment,
+    {
+        token.enable(G::NUM);
+        let pclk = Pclk {
+            token,
+            gen: PhantomData,
+            freq: gclk.freq(),
+        };
+        (pclk, gclk.inc())
+    }
+
+    /// Disable the channel, and decrement the count of `gclk`
+    #[inline]
+    pub fn disable<S>(mut self, gclk: S) -> (PclkToken<P>, S::Dec)
+    where
+        S: Source<Id = G> + Decrement,
+    {
+        self.token.disable();
+        (self.token, gclk.dec())
+    }
+
+    /// Returns the frequency of the channel
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+}
+
+/// A [`Pclk`] whose [`v1`](super::super::v1) clock token is in use
+pub struct LentPclk<P: PclkId, G: GclkId> {
+    pclk: Pclk<P, G>,
+}
+
+impl<P: PclkId, G: GclkId> LentPclk<P, G> {
+    /// Returns the frequency of the channel
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.pclk.freq
+    }
+}
+
+macro_rules! pclk_v1_from {
+    ($(#[$attr:meta])* $Id:ident) => {};
+    ($(#[$attr:meta])* $Id:ident, $V1:ident) => {
+        $(#[$attr])*
+        impl<G: GclkId> Pclk<$Id, G> {
+            #[doc = concat!("Trade the channel for a [`v1::", stringify!($V1), "`]")]
+            ///
+            /// The returned [`LentPclk`] gives the channel back in exchange
+            /// for the token.
+            #[inline]
+            pub fn into_v1(self) -> (v1::$V1, LentPclk<$Id, G>) {
+                (v1::$V1 { freq: self.freq }, LentPclk { pclk: self })
+            }
+        }
+
+        $(#[$attr])*
+        impl<G: GclkId> LentPclk<$Id, G> {
+            #[doc = concat!("Give back the [`v1::", stringify!($V1), "`], and get the [`Pclk`] back")]
+            #[inline]
+            pub fn restore(self, _clock: v1::$V1) -> Pclk<$Id, G> {
+                self.pclk
+            }
+        }
+    };
+}
+
+macro_rules! pclk_ids {
+    (
+        $(
+            $(#[$attr:meta])*
+            ($Id:ident, $field:ident, $clock:ident $(, $V1:ident)?),
+        )+
+    ) => {
+        $(
+            $(#[$attr])*
+            #[doc = concat!("Type-level identifier of the ", stringify!($clock), " channel")]
+            pub enum $Id {}
+
+            $(#[$attr])*
+            impl Sealed for $Id {}
+
+            $(#[$attr])*
+            impl PclkId for $Id {
+                const DYN: ClockId = ClockId::$clock;
+            }
+
+            pclk_v1_from!($(#[$attr])* $Id $(, $V1)?);
+        )+
+
+        /// Tokens for the peripheral channels
+        pub struct PclkTokens {
+            $(
+                $(#[$attr])*
+                #[doc = concat!("Token for the ", stringify!($clock), " channel")]
+                pub $field: PclkToken<$Id>,
+            )+
+        }
+
+        impl PclkTokens {
+            /// Create the tokens
+            ///
+            /// # Safety
+            ///
+            /// There must be at most one instance of each token.
+            #[inline]
+            pub(super) unsafe fn new() -> Self {
+                PclkTokens {
+                    $(
+                        $(#[$attr])*
+                        $field: PclkToken::new(),
+                    )+
+                }
+            }
+        }
+    };
+}
+
+pclk_ids!(
+    (Dfll48, dfll48, DFLL48),
+    (FDpll0, fdpll0, FDPLL0),
+    (FDpll1, fdpll1, FDPLL1),
+    (Slow32k, slow_32k, SLOW_32K),
+    (Eic, eic, EIC, EicClock),
+    (FreqmMsr, freq_m_msr, FREQM_MSR, FreqmMsrClock),
+    (FreqmRef, freq_m_ref, FREQM_REF, FreqmRefClock),
+    (Sercom0Core, sercom0_core, SERCOM0_CORE, Sercom0CoreClock),
+    (Sercom1Core, sercom1_core, SERCOM1_CORE, Sercom1CoreClock),
+    (Tc0Tc1, tc0_tc1, TC0_TC1, Tc0Tc1Clock),
+    (Usb, usb, USB, UsbClock),
+    (Evsys0, evsys0, EVSYS0, Evsys0Clock),
+    (Evsys1, evsys1, EVSYS1, Evsys1Clock),
+    (Evsys2, evsys2, EVSYS2, Evsys2Clock),
+    (Evsys3, evsys3, EVSYS3, Evsys3Clock),
+    (Evsys4, evsys4, EVSYS4, Evsys4Clock),
+    (Evsys5, evsys5, EVSYS5, Evsys5Clock),
+    (Evsys6, evsys6, EVSYS6, Evsys6Clock),
+    (Evsys7, evsys7, EVSYS7, Evsys7Clock),
+    (Evsys8, evsys8, EVSYS8, Evsys8Clock),
+    (Evsys9, evsys9, EVSYS9, Evsys9Clock),
+    (Evsys10, evsys10, EVSYS10, Evsys10Clock),
+    (Evsys11, evsys11, EVSYS11, Evsys11Clock),
+    (Sercom2Core, sercom2_core, SERCOM2_CORE, Sercom2CoreClock),
+    (Sercom3Core, sercom3_core, SERCOM3_CORE, Sercom3CoreClock),
+    (Tcc0Tcc1, tcc0_tcc1, TCC0_TCC1, Tcc0Tcc1Clock),
+    (Tc2Tc3, tc2_tc3, TC2_TC3, Tc2Tc3Clock),
+    (Can0, can0, CAN0, Can0Clock),
+    (Can1, can1, CAN1, Can1Clock),
+    (Tcc2Tcc3, tcc2_tcc3, TCC2_TCC3, Tcc2Tcc3Clock),
+    (Tc4Tc5, tc4_tc5, TC4_TC5, Tc4Tc5Clock),
+    (Pdec, pdec, PDEC, PdecClock),
+    (Ac, ac, AC, AcClock),
+    (Ccl, ccl, CCL, CclClock),
+    (Sercom4Core, sercom4_core, SERCOM4_CORE, Sercom4CoreClock),
+    (Sercom5Core, sercom5_core, SERCOM5_CORE, Sercom5CoreClock),
+    #[cfg(feature = "min-samd51n")]
+    (Sercom6Core, sercom6_core, SERCOM6_CORE, Sercom6CoreClock),
+    #[cfg(feature = "min-samd51n")]
+    (Sercom7Core, sercom7_core, SERCOM7_CORE, Sercom7CoreClock),
+    (Tcc4, tcc4, TCC4, Tcc4Clock),
+    (Tc6Tc7, tc6_tc7, TC6_TC7, Tc6Tc7Clock),
+    (Adc0, adc0, ADC0, Adc0Clock),
+    (Adc1, adc1, ADC1, Adc1Clock),
+    (Dac, dac, DAC, DacClock),
+    (I2S0, i2s0, I2S0, I2S0Clock),
+    (I2S1, i2s1, I2S1, I2S1Clock),
+    (Sdhc0, sdhc0, SDHC0, Sdhc0Clock),
+    (Sdhc1, sdhc1, SDHC1, Sdhc1Clock),
+    (Cm4Trace, cm4_trace, CM4_TRACE, Cm4TraceClock),
+);
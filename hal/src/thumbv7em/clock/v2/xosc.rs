@@ -0,0 +1,471 @@
+//! # External multipurpose oscillators
+//!
+//! The XOSC0 and XOSC1 oscillators are driven by a crystal between their XIN
+//! and XOUT pins, or by an external clock signal on their XIN pin. Their
+//! frequency can range up to 48MHz.
+//!
+//! An [`Xosc`] is built from an [`XoscToken`] and the corresponding pins,
+//! which are owned by the oscillator until it is freed.
+//!
+//! ```
+//! let xosc0 = Xosc::from_crystal(tokens.xosc0, pins.pa14, pins.pa15, 12.mhz())
+//!     .amplitude_control(true)
+//!     .enable();
+//! ```
+
+use core::marker::PhantomData;
+
+use typenum::U0;
+
+use crate::gpio::v2::{AnyPin, FloatingDisabled, Pin, PinId, PA14, PA15, PB22, PB23};
+use crate::pac::oscctrl::XOSCCTRL;
+use crate::pac::{self, OSCCTRL};
+use crate::time::Hertz;
+use crate::typelevel::Sealed;
+
+use super::{Enabled, Source};
+
+pub type XoscStartup = pac::oscctrl::xoscctrl::STARTUP_A;
+
+pub type XoscCfdPrescaler = pac::oscctrl::xoscctrl::CFDPRESC_A;
+
+//==============================================================================
+// Ids
+//==============================================================================
+
+/// Type-level identifier of an external oscillator
+pub trait XoscId: Sealed {
+    /// Index of the oscillator
+    const NUM: usize;
+    /// [`PinId`] of the XIN pin
+    type XIn: PinId;
+    /// [`PinId`] of the XOUT pin
+    type XOut: PinId;
+}
+
+/// Type-level identifier of XOSC0
+pub enum Xosc0Id {}
+
+impl Sealed for Xosc0Id {}
+
+impl XoscId for Xosc0Id {
+    const NUM: usize = 0;
+    type XIn = PA14;
+    type XOut = PA15;
+}
+
+/// Type-level identifier of XOSC1
+pub enum Xosc1Id {}
+
+impl Sealed for Xosc1Id {}
+
+impl XoscId for Xosc1Id {
+    const NUM: usize = 1;
+    type XIn = PB22;
+    type XOut = PB23;
+}
+
+//==============================================================================
+// XoscToken
+//==============================================================================
+
+/// Singleton token granting access to the registers of an external
+/// oscillator
+pub struct XoscToken<X: XoscId> {
+    xosc: PhantomData<X>,
+}
+
+impl<X: XoscId> XoscToken<X> {
+    /// Create a new token
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one token for each oscillator.
+    #[inline]
+    pub(super) unsafe fn new() -> Self {
+        XoscToken { xosc: PhantomData }
+    }
+
+    #[inline]
+    fn oscctrl(&self) -> &pac::oscctrl::RegisterBlock {
+        // SAFETY: The token grants exclusive access to the XOSCCTRL register
+        // of this oscillator, and the STATUS register is only read
+        unsafe { &*OSCCTRL::ptr() }
+    }
+
+    #[inline]
+    fn is_ready(&self) -> bool {
+        let status = self.oscctrl().status.read();
+        match X::NUM {
+            0 => status.xoscrdy0().bit_is_set(),
+            _ => status.xoscrdy1().bit_is_set(),
+        }
+    }
+
+    #[inline]
+    fn has_failed(&self) -> bool {
+        let status = self.oscctrl().status.read();
+        match X::NUM {
+            0 => status.xoscfail0().bit_is_set(),
+            _ => status.xoscfail1().bit_is_set(),
+        }
+    }
+
+    #[inline]
+    fn is_switched(&self) -> bool {
+        let status = self.oscctrl().status.read();
+        match X::NUM {
+            0 => status.xosccksw0().bit_is_set(),
+            _ => status.xosccksw1().bit_is_set(),
+        }
+    }
+
+    #[inline]
+    fn enable(&mut self, config: &XoscConfig) {
+        config.write(&self.oscctrl().xoscctrl[X::NUM]);
+        if !config.is_on_demand() {
+            while !self.is_ready() {}
+        }
+    }
+
+    #[inline]
+    fn disable(&mut self) {
+        self.oscctrl().xoscctrl[X::NUM].modify(|_, w| {
+            w.cfden().clear_bit();
+            w.enable().clear_bit()
+        });
+    }
+}
+
+//==============================================================================
+// Xosc
+//==============================================================================
+
+/// An external oscillator, owning its token and pins
+pub struct Xosc<X: XoscId> {
+    token: XoscToken<X>,
+    xin: Pin<X::XIn, FloatingDisabled>,
+    xout: Option<Pin<X::XOut, FloatingDisabled>>,
+    config: XoscConfig,
+}
+
+impl<X: XoscId> Xosc<X> {
+    /// Create an oscillator driving a crystal of frequency `freq` (8 to
+    /// 48MHz) connected between `xin` and `xout`
+    #[inline]
+    pub fn from_crystal<I, O>(token: XoscToken<X>, xin: I, xout: O, freq: impl Into<Hertz>) -> Self
+    where
+        I: AnyPin<Id = X::XIn>,
+        O: AnyPin<Id = X::XOut>,
+    {
+        Xosc {
+            token,
+            xin: xin.into().into_floating_disabled(),
+            xout: Some(xout.into().into_floating_disabled()),
+            config: XoscConfig::crystal(freq),
+        }
+    }
+
+    /// Create an oscillator taking an external clock signal of frequency
+    /// `freq` (up to 48MHz) on `xin`
+    #[inline]
+    pub fn from_clock<I>(token: XoscToken<X>, xin: I, freq: impl Into<Hertz>) -> Self
+    where
+        I: AnyPin<Id = X::XIn>,
+    {
+        Xosc {
+            token,
+            xin: xin.into().into_floating_disabled(),
+            xout: None,
+            config: XoscConfig::external_clock(freq),
+        }
+    }
+
+    /// Release the token and the pins. The XOUT pin is only returned if the
+    /// oscillator was created with [`Xosc::from_crystal`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn free(
+        self,
+    ) -> (
+        XoscToken<X>,
+        Pin<X::XIn, FloatingDisabled>,
+        Option<Pin<X::XOut, FloatingDisabled>>,
+    ) {
+        (self.token, self.xin, self.xout)
+    }
+
+    /// Set the start-up time, in OSCULP32K cycles
+    #[inline]
+    pub fn startup(mut self, startup: XoscStartup) -> Self {
+        self.config = self.config.startup(startup);
+        self
+    }
+
+    /// Enable the automatic amplitude loop control
+    #[inline]
+    pub fn amplitude_control(mut self, enable: bool) -> Self {
+        self.config = self.config.amplitude_control(enable);
+        self
+    }
+
+    /// Select the low buffer gain
+    #[inline]
+    pub fn low_buffer_gain(mut self, enable: bool) -> Self {
+        self.config = self.config.low_buffer_gain(enable);
+        self
+    }
+
+    /// Override the oscillator current (`IMULT` and `IPTAT`)
+    #[inline]
+    pub fn current(mut self, imult: u8, iptat: u8) -> Self {
+        self.config = self.config.current(imult, iptat);
+        self
+    }
+
+    /// Only run the oscillator when it is requested by a consumer
+    #[inline]
+    pub fn on_demand(mut self, enable: bool) -> Self {
+        self.config = self.config.on_demand(enable);
+        self
+    }
+
+    /// Keep the oscillator running in standby sleep mode
+    #[inline]
+    pub fn run_standby(mut self, enable: bool) -> Self {
+        self.config = self.config.run_standby(enable);
+        self
+    }
+
+    /// Enable the clock failure detector, with a safe clock of the DFLL
+    /// divided by `prescaler`
+    #[inline]
+    pub fn clock_failure_detection(mut self, prescaler: XoscCfdPrescaler) -> Self {
+        self.config = self.config.clock_failure_detection(prescaler);
+        self
+    }
+
+    /// Switch back from the safe clock to the oscillator once it recovers
+    #[inline]
+    pub fn switch_back(mut self, enable: bool) -> Self {
+        self.config = self.config.switch_back(enable);
+        self
+    }
+
+    /// Returns the frequency of the oscillator
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.config.freq()
+    }
+
+    /// Enable the oscillator. Unless it runs on demand, this waits until it
+    /// is ready.
+    #[inline]
+    pub fn enable(mut self) -> Enabled<Self> {
+        self.token.enable(&self.config);
+        Enabled::new(self)
+    }
+}
+
+impl<X: XoscId> Enabled<Xosc<X>, U0> {
+    /// Disable the oscillator
+    #[inline]
+    pub fn disable(mut self) -> Xosc<X> {
+        self.clock.token.disable();
+        self.clock
+    }
+}
+
+impl<X: XoscId, N> Enabled<Xosc<X>, N> {
+    /// Returns `true` if the clock failure detector currently reports a
+    /// failure of the oscillator
+    #[inline]
+    pub fn has_failed(&self) -> bool {
+        self.clock.token.has_failed()
+    }
+
+    /// Returns `true` if the output of the oscillator has been switched to
+    /// the safe clock after a failure
+    ///
+    /// While switched, consumers run from the safe clock, so
+    /// [`Source::freq`] no longer reflects their actual frequency.
+    #[inline]
+    pub fn is_switched(&self) -> bool {
+        self.clock.token.is_switched()
+    }
+}
+
+impl<X: XoscId, N> Source for Enabled<Xosc<X>, N> {
+    type Id = X;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        self.clock.freq()
+    }
+}
+
+//==============================================================================
+// XoscConfig
+//==============================================================================
+
+/// Configuration of an external oscillator (XOSC0 or XOSC1)
+///
+/// The oscillator either drives a crystal connected between the XIN and XOUT
+/// pins, or takes an external clock signal on the XIN pin. The configuration
+/// is built by [`Xosc`], or passed to
+/// [`GenericClockController::configure_xosc`](crate::clock::GenericClockController::configure_xosc).
+#[derive(Clone, Copy, Debug)]
+pub struct XoscConfig {
+    freq: Hertz,
+    crystal: bool,
+    startup: XoscStartup,
+    amplitude_control: bool,
+    low_buffer_gain: bool,
+    current: Option<(u8, u8)>,
+    on_demand: bool,
+    run_standby: bool,
+    cfd: Option<XoscCfdPrescaler>,
+    switch_back: bool,
+}
+
+impl XoscConfig {
+    /// Configuration for a crystal of frequency `freq` (8 to 48MHz)
+    /// connected between XIN and XOUT.
+    pub fn crystal(freq: impl Into<Hertz>) -> Self {
+        let freq = freq.into();
+        if freq.0 < 8_000_000 || freq.0 > 48_000_000 {
+            panic!("invalid crystal frequency {}", freq.0);
+        }
+        Self::new(freq, true)
+    }
+
+    /// Configuration for an external clock signal of frequency `freq` (up
+    /// to 48MHz) applied to XIN. XOUT is free to be used as a GPIO.
+    pub fn external_clock(freq: impl Into<Hertz>) -> Self {
+        let freq = freq.into();
+        if freq.0 == 0 || freq.0 > 48_000_000 {
+            panic!("invalid external clock frequency {}", freq.0);
+        }
+        Self::new(freq, false)
+    }
+
+    fn new(freq: Hertz, crystal: bool) -> Self {
+        Self {
+            freq,
+            crystal,
+            startup: XoscStartup::CYCLE1024,
+            amplitude_control: false,
+            low_buffer_gain: false,
+            current: None,
+            on_demand: false,
+            run_standby: false,
+            cfd: None,
+            switch_back: false,
+        }
+    }
+
+    /// Set the start-up time, in OSCULP32K cycles. The oscillator is not
+    /// reported ready until it has elapsed.
+    pub fn startup(mut self, startup: XoscStartup) -> Self {
+        self.startup = startup;
+        self
+    }
+
+    /// Enable the automatic loop control, which reduces the amplitude of
+    /// the crystal oscillation (and thus the power consumption) once it has
+    /// started up.
+    pub fn amplitude_control(mut self, enable: bool) -> Self {
+        self.amplitude_control = enable;
+        self
+    }
+
+    /// Select the low buffer gain, for crystals where the oscillation
+    /// amplitude is high enough without it.
+    pub fn low_buffer_gain(mut self, enable: bool) -> Self {
+        self.low_buffer_gain = enable;
+        self
+    }
+
+    /// Override the oscillator current, as the `IMULT` (current multiplier)
+    /// and `IPTAT` (current reference) fields. By default, the values
+    /// recommended by the datasheet for the crystal frequency are used.
+    pub fn current(mut self, imult: u8, iptat: u8) -> Self {
+        if imult > 0xF || iptat > 0x3 {
+            panic!("invalid oscillator current {} {}", imult, iptat);
+        }
+        self.current = Some((imult, iptat));
+        self
+    }
+
+    /// Only run the oscillator when a peripheral or clock generator
+    /// requests it.
+    pub fn on_demand(mut self, enable: bool) -> Self {
+        self.on_demand = enable;
+        self
+    }
+
+    /// Keep the oscillator running in standby sleep mode.
+    pub fn run_standby(mut self, enable: bool) -> Self {
+        self.run_standby = enable;
+        self
+    }
+
+    /// Enable the clock failure detector. If the oscillator stops, its
+    /// output is switched to a safe clock, the DFLL divided by `prescaler`,
+    /// which should be chosen to approach the oscillator frequency. The DFLL
+    /// must be running for the safe clock to be available.
+    pub fn clock_failure_detection(mut self, prescaler: XoscCfdPrescaler) -> Self {
+        self.cfd = Some(prescaler);
+        self
+    }
+
+    /// Switch back from the safe clock to the oscillator once it recovers.
+    /// Otherwise, the safe clock is used until the oscillator is
+    /// reconfigured.
+    pub fn switch_back(mut self, enable: bool) -> Self {
+        self.switch_back = enable;
+        self
+    }
+
+    /// Returns the frequency of the oscillator
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Returns `true` if the oscillator only runs on demand
+    pub fn is_on_demand(&self) -> bool {
+        self.on_demand
+    }
+
+    /// Write the configuration to an `XOSCCTRL` register, and enable the
+    /// oscillator
+    pub(in crate::thumbv7em::clock) fn write(&self, xoscctrl: &XOSCCTRL) {
+        let (imult, iptat) = self.current_settings();
+        xoscctrl.write(|w| unsafe {
+            w.startup().variant(self.startup);
+            w.xtalen().bit(self.crystal);
+            w.enalc().bit(self.amplitude_control);
+            w.lowbufgain().bit(self.low_buffer_gain);
+            w.imult().bits(imult);
+            w.iptat().bits(iptat);
+            w.ondemand().bit(self.on_demand);
+            w.runstdby().bit(self.run_standby);
+            if let Some(prescaler) = self.cfd {
+                w.cfdpresc().variant(prescaler);
+            }
+            w.cfden().bit(self.cfd.is_some());
+            w.swben().bit(self.switch_back);
+            w.enable().set_bit()
+        });
+    }
+
+    /// Returns the `IMULT` and `IPTAT` values to use
+    fn current_settings(&self) -> (u8, u8) {
+        self.current.unwrap_or(match self.freq.0 {
+            0..=8_000_000 => (3, 2),
+            8_000_001..=16_000_000 => (4, 3),
+            16_000_001..=24_000_000 => (5, 3),
+            _ => (6, 3),
+        })
+    }
+}
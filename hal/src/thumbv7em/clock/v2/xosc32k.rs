@@ -0,0 +1,239 @@
+//! # External 32kHz crystal oscillator
+//!
+//! The XOSC32K is driven by a 32.768kHz crystal between the XIN32 and XOUT32
+//! pins, or by an external clock signal on XIN32. It can drive a generic
+//! clock generator, or serve as the reference of a DPLL.
+//!
+//! ```
+//! let xosc32k = Xosc32k::from_crystal(tokens.xosc32k, pins.pa00, pins.pa01)
+//!     .run_standby(true)
+//!     .enable();
+//! ```
+
+use typenum::U0;
+
+use crate::gpio::v2::{AnyPin, FloatingDisabled, Pin, PA00, PA01};
+use crate::pac::{self, OSC32KCTRL};
+use crate::time::Hertz;
+use crate::typelevel::Sealed;
+
+use super::{Enabled, Source};
+
+pub type Xosc32kStartup = pac::osc32kctrl::xosc32k::STARTUP_A;
+
+/// Type-level identifier of the XOSC32K
+pub enum Xosc32kId {}
+
+impl Sealed for Xosc32kId {}
+
+/// Singleton token granting access to the XOSC32K registers
+pub struct Xosc32kToken {
+    _private: (),
+}
+
+impl Xosc32kToken {
+    /// Create a new token
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one token.
+    #[inline]
+    pub(super) unsafe fn new() -> Self {
+        Xosc32kToken { _private: () }
+    }
+
+    #[inline]
+    fn osc32kctrl(&self) -> &pac::osc32kctrl::RegisterBlock {
+        // SAFETY: The token grants exclusive access to the XOSC32K and
+        // CFDCTRL registers, and the STATUS register is only read
+        unsafe { &*OSC32KCTRL::ptr() }
+    }
+}
+
+/// The external 32kHz oscillator, owning its token and pins
+pub struct Xosc32k {
+    token: Xosc32kToken,
+    xin32: Pin<PA00, FloatingDisabled>,
+    xout32: Option<Pin<PA01, FloatingDisabled>>,
+    startup: Xosc32kStartup,
+    high_speed: bool,
+    on_demand: bool,
+    run_standby: bool,
+    cfd: Option<bool>,
+}
+
+impl Xosc32k {
+    /// Create an oscillator driving a 32.768kHz crystal connected between
+    /// `xin32` and `xout32`
+    #[inline]
+    pub fn from_crystal<I, O>(token: Xosc32kToken, xin32: I, xout32: O) -> Self
+    where
+        I: AnyPin<Id = PA00>,
+        O: AnyPin<Id = PA01>,
+    {
+        Self::new(
+            token,
+            xin32.into().into_floating_disabled(),
+            Some(xout32.into().into_floating_disabled()),
+        )
+    }
+
+    /// Create an oscillator taking an external 32.768kHz clock signal on
+    /// `xin32`
+    #[inline]
+    pub fn from_clock<I>(token: Xosc32kToken, xin32: I) -> Self
+    where
+        I: AnyPin<Id = PA00>,
+    {
+        Self::new(token, xin32.into().into_floating_disabled(), None)
+    }
+
+    #[inline]
+    fn new(
+        token: Xosc32kToken,
+        xin32: Pin<PA00, FloatingDisabled>,
+        xout32: Option<Pin<PA01, FloatingDisabled>>,
+    ) -> Self {
+        Xosc32k {
+            token,
+            xin32,
+            xout32,
+            startup: Xosc32kStartup::CYCLE2048,
+            high_speed: false,
+            on_demand: false,
+            run_standby: false,
+            cfd: None,
+        }
+    }
+
+    /// Release the token and the pins. The XOUT32 pin is only returned if
+    /// the oscillator was created with [`Xosc32k::from_crystal`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn free(
+        self,
+    ) -> (
+        Xosc32kToken,
+        Pin<PA00, FloatingDisabled>,
+        Option<Pin<PA01, FloatingDisabled>>,
+    ) {
+        (self.token, self.xin32, self.xout32)
+    }
+
+    /// Set the start-up time, in oscillator cycles
+    #[inline]
+    pub fn startup(mut self, startup: Xosc32kStartup) -> Self {
+        self.startup = startup;
+        self
+    }
+
+    /// Use the high-speed control gain mode, for crystals that fail to start
+    /// in the standard mode
+    #[inline]
+    pub fn high_speed(mut self, enable: bool) -> Self {
+        self.high_speed = enable;
+        self
+    }
+
+    /// Only run the oscillator when it is requested by a consumer
+    #[inline]
+    pub fn on_demand(mut self, enable: bool) -> Self {
+        self.on_demand = enable;
+        self
+    }
+
+    /// Keep the oscillator running in standby sleep mode
+    #[inline]
+    pub fn run_standby(mut self, enable: bool) -> Self {
+        self.run_standby = enable;
+        self
+    }
+
+    /// Enable the clock failure detector. If the oscillator stops, its
+    /// outputs are switched to a safe clock, the OSCULP32K, divided by two
+    /// if `prescale` is `true`.
+    #[inline]
+    pub fn clock_failure_detection(mut self, prescale: bool) -> Self {
+        self.cfd = Some(prescale);
+        self
+    }
+
+    /// Enable the oscillator, with its 32kHz and 1kHz outputs. Unless it runs
+    /// on demand, this waits until it is ready.
+    #[inline]
+    pub fn enable(self) -> Enabled<Self> {
+        let osc32kctrl = self.token.osc32kctrl();
+        osc32kctrl.xosc32k.write(|w| {
+            w.startup().variant(self.startup);
+            w.xtalen().bit(self.xout32.is_some());
+            if self.high_speed {
+                w.cgm().hs();
+            } else {
+                w.cgm().xt();
+            }
+            w.en32k().set_bit();
+            w.en1k().set_bit();
+            w.ondemand().bit(self.on_demand);
+            w.runstdby().bit(self.run_standby);
+            w.enable().set_bit()
+        });
+        if !self.on_demand {
+            while osc32kctrl.status.read().xosc32krdy().bit_is_clear() {}
+        }
+        if let Some(prescale) = self.cfd {
+            osc32kctrl.cfdctrl.write(|w| {
+                w.cfdpresc().bit(prescale);
+                w.cfden().set_bit()
+            });
+        }
+        Enabled::new(self)
+    }
+}
+
+impl Enabled<Xosc32k, U0> {
+    /// Disable the oscillator
+    #[inline]
+    pub fn disable(self) -> Xosc32k {
+        let osc32kctrl = self.clock.token.osc32kctrl();
+        osc32kctrl.cfdctrl.write(|w| w.cfden().clear_bit());
+        osc32kctrl.xosc32k.modify(|_, w| w.enable().clear_bit());
+        self.clock
+    }
+}
+
+impl<N> Enabled<Xosc32k, N> {
+    /// Returns `true` if the clock failure detector currently reports a
+    /// failure of the oscillator
+    #[inline]
+    pub fn has_failed(&self) -> bool {
+        let osc32kctrl = self.clock.token.osc32kctrl();
+        osc32kctrl.status.read().xosc32kfail().bit_is_set()
+    }
+
+    /// Returns `true` if the outputs of the oscillator have been switched to
+    /// the safe clock after a failure
+    #[inline]
+    pub fn is_switched(&self) -> bool {
+        let osc32kctrl = self.clock.token.osc32kctrl();
+        osc32kctrl.status.read().xosc32ksw().bit_is_set()
+    }
+
+    /// Switch back from the safe clock to the oscillator
+    ///
+    /// Unlike XOSC0 and XOSC1, the XOSC32K does not switch back
+    /// automatically once it recovers.
+    #[inline]
+    pub fn switch_back(&mut self) {
+        let osc32kctrl = self.clock.token.osc32kctrl();
+        osc32kctrl.cfdctrl.modify(|_, w| w.swback().set_bit());
+    }
+}
+
+impl<N> Source for Enabled<Xosc32k, N> {
+    type Id = Xosc32kId;
+
+    #[inline]
+    fn freq(&self) -> Hertz {
+        Hertz(32_768)
+    }
+}
@@ -1076,8 +1076,8 @@ impl Icm {
 
     /// Set the user hashing algorithm
     #[inline]
-    pub fn set_user_algorithm(self, algo: icm_algorithm) {
-        self.cfg().write(|w| w.ualgo().variant(algo));
+    pub fn set_user_algorithm(&self, algo: icm_algorithm) {
+        self.cfg().modify(|_, w| w.ualgo().variant(algo));
     }
 
     /// Activate user hash mode
@@ -1092,7 +1092,8 @@ impl Icm {
     /// Set hash algorithm via [`Icm::set_user_algorithm()`]
     #[inline]
     pub fn set_user_configurable_hash(&self, user_configurable_hash: bool) {
-        self.cfg().write(|w| w.uihash().bit(user_configurable_hash));
+        self.cfg()
+            .modify(|_, w| w.uihash().bit(user_configurable_hash));
     }
 
     /// Control dual input buffer
@@ -1101,7 +1102,7 @@ impl Icm {
     /// at the cost of higher bandwith requirements on the system bus
     #[inline]
     pub fn set_dual_input_buffer(&self, dualbuffer: bool) {
-        self.cfg().write(|w| w.dualbuff().bit(dualbuffer));
+        self.cfg().modify(|_, w| w.dualbuff().bit(dualbuffer));
     }
 
     /// Automatic switch to Compare Digest
@@ -1113,7 +1114,7 @@ impl Icm {
     /// `1` needs to be written to End of Monitoring (`RCFG.EOM`)
     #[inline]
     pub fn set_ascd(&self, automaticswitch: bool) {
-        self.cfg().write(|w| w.ascd().bit(automaticswitch));
+        self.cfg().modify(|_, w| w.ascd().bit(automaticswitch));
     }
 
     /// Bus burden control
@@ -1125,7 +1126,7 @@ impl Icm {
     /// Maximum delay is 32768 cycles
     #[inline]
     pub fn set_busburden(&self, busburden: u8) {
-        self.cfg().write(|w| unsafe { w.bbc().bits(busburden) });
+        self.cfg().modify(|_, w| unsafe { w.bbc().bits(busburden) });
     }
 
     /// Secondary List Branching Disable
@@ -1136,7 +1137,7 @@ impl Icm {
     #[inline]
     pub fn set_slbdis(&self, disable_secondary_lists: bool) {
         self.cfg()
-            .write(|w| w.slbdis().bit(disable_secondary_lists));
+            .modify(|_, w| w.slbdis().bit(disable_secondary_lists));
     }
 
     /// End of Monitoring Disable
@@ -1146,7 +1147,7 @@ impl Icm {
     ///   has no effect.
     #[inline]
     pub fn set_eomdis(&self, disable_eom: bool) {
-        self.cfg().write(|w| w.eomdis().bit(disable_eom));
+        self.cfg().modify(|_, w| w.eomdis().bit(disable_eom));
     }
 
     /// Write Back Disable
@@ -1157,7 +1158,7 @@ impl Icm {
     ///   The `CDWBN` bit of the `RCFG` structure member has no effect.
     #[inline]
     pub fn set_wbdis(&self, disable_eom: bool) {
-        self.cfg().write(|w| w.wbdis().bit(disable_eom));
+        self.cfg().modify(|_, w| w.wbdis().bit(disable_eom));
     }
 
     // Security and tamper settings
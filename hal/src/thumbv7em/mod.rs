@@ -28,5 +28,8 @@ pub mod watchdog;
 #[cfg(feature = "unproven")]
 pub mod icm;
 
+#[cfg(feature = "unproven")]
+pub mod secure_boot;
+
 pub mod dsu;
 pub mod nvm;
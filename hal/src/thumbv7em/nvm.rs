@@ -18,9 +18,12 @@
 //! Module features:
 //! - Erase & write over non-volatile memory in a device.
 //! - Swap banks
+//! - Flash regions for `embedded-storage` (More in [`storage`] module)
+//! - Lock & unlock regions
 #![warn(missing_docs)]
 
 pub mod smart_eeprom;
+pub mod storage;
 
 pub use crate::target_device::nvmctrl::ctrla::PRM_A;
 use crate::target_device::nvmctrl::ctrlb::CMD_AW;
@@ -67,6 +70,12 @@ pub const PAGESIZE: u32 = 512;
 /// Size of one block
 pub const BLOCKSIZE: u32 = 512 * 16;
 
+/// Number of lock regions
+pub const REGIONS: u32 = 32;
+
+/// Address of the user page
+const USER_PAGE_ADDRESS: u32 = 0x0080_4000;
+
 /// Non-volatile memory controller
 pub struct Nvm {
     /// PAC peripheral
@@ -108,6 +117,8 @@ pub enum Error {
     Dsu(super::dsu::Error),
     /// An alignment requirement was not fulfilled
     Alignment,
+    /// Memory region contains the running program
+    ProgramArea,
 }
 
 /// Physical flash banks
@@ -191,6 +202,64 @@ impl Nvm {
         !self.nvm.status.read().bpdis().bit()
     }
 
+    /// Flash area protected by the BOOTPROT fuses, empty if boot protection is
+    /// disabled
+    #[inline]
+    pub fn boot_protected_area(&self) -> Range<u32> {
+        if !self.is_boot_protected() {
+            return 0..0;
+        }
+        // Calculate size that is protected for bootloader
+        //   * 15 = no bootprotection, default value
+        //   * 0 = max bootprotection, 15 * 8Kibyte = 120KiB
+        //   * (15 - bootprot) * 8KiB = protected size
+        let bootprot = self.nvm.status.read().bootprot().bits();
+        let bp_space = 8 * 1024 * (15 - bootprot) as u32;
+        Bank::Active.address()..Bank::Active.address() + bp_space
+    }
+
+    /// Size of a lock region in bytes
+    #[inline]
+    pub fn region_size(&self) -> u32 {
+        retrieve_flash_size() / REGIONS
+    }
+
+    /// Lock the region containing `address` against erase and write
+    ///
+    /// The lock only lasts until reset. Locks set in the user page are
+    /// restored on reset, see [`Userpage::set_nvm_locks`].
+    #[inline]
+    pub fn lock_region(&mut self, address: u32) -> Result<()> {
+        self.region_command(CMD_AW::LR, address)
+    }
+
+    /// Unlock the region containing `address`
+    #[inline]
+    pub fn unlock_region(&mut self, address: u32) -> Result<()> {
+        self.region_command(CMD_AW::UR, address)
+    }
+
+    /// Check if the region containing `address` is locked
+    #[inline]
+    pub fn is_region_locked(&self, address: u32) -> Result<bool> {
+        if address >= retrieve_flash_size() {
+            return Err(Error::NonFlash);
+        }
+        let region = address / self.region_size();
+        // A cleared bit means the region is locked
+        Ok(self.nvm.runlock.read().runlock().bits() & (1 << region) == 0)
+    }
+
+    fn region_command(&mut self, command: CMD_AW, address: u32) -> Result<()> {
+        if address >= retrieve_flash_size() {
+            return Err(Error::NonFlash);
+        }
+        while !self.is_ready() {}
+        self.set_address(address);
+        self.command_sync(command);
+        self.manage_error_states()
+    }
+
     /// Get first bank
     #[inline]
     pub fn first_bank(&self) -> PhysicalBank {
@@ -257,7 +326,7 @@ impl Nvm {
     #[inline]
     pub fn user_page(&self) -> Userpage {
         let mut buffer = 0_u128;
-        let base_addr: *const u8 = 0x0080_4000 as *const u8;
+        let base_addr: *const u8 = USER_PAGE_ADDRESS as *const u8;
 
         for i in 0..16 {
             buffer |= unsafe { core::ptr::read_volatile(base_addr.offset(i as isize)) as u128 }
@@ -267,6 +336,70 @@ impl Nvm {
         Userpage(buffer)
     }
 
+    /// Write the fuses of the user page
+    ///
+    /// The user page is read, updated with the writable fields of
+    /// `user_page`, then erased and written back. The reserved and factory
+    /// calibration bits, and the rest of the page, are kept. Nothing is
+    /// written if the fuses are unchanged, which is reported by returning
+    /// `false`.
+    ///
+    /// The fuses are loaded at reset, see
+    /// [`Nvm::write_user_page_and_reset`]. The page is blank between the
+    /// erase and the write, so the power supply must not be interrupted.
+    pub fn write_user_page(&mut self, user_page: Userpage) -> Result<bool> {
+        let mut page = [0u32; PAGESIZE as usize / 4];
+        for (i, word) in page.iter_mut().enumerate() {
+            *word = unsafe { core::ptr::read_volatile((USER_PAGE_ADDRESS as *const u32).add(i)) };
+        }
+
+        let current = page[..4]
+            .iter()
+            .rev()
+            .fold(0u128, |acc, word| acc << 32 | *word as u128);
+        let fuses = (current & !Userpage::WRITABLE) | (user_page.0 & Userpage::WRITABLE);
+        if fuses == current {
+            return Ok(false);
+        }
+        for (i, word) in page[..4].iter_mut().enumerate() {
+            *word = (fuses >> (32 * i)) as u32;
+        }
+
+        while !self.is_ready() {}
+        self.set_address(USER_PAGE_ADDRESS);
+        self.command_sync(CMD_AW::EP);
+        self.manage_error_states()?;
+
+        // The user page can only be written a quad-word at a time
+        for (i, quad_word) in page.chunks(4).enumerate() {
+            if quad_word.iter().all(|word| *word == u32::MAX) {
+                continue;
+            }
+            let address = USER_PAGE_ADDRESS + i as u32 * 16;
+            while !self.is_ready() {}
+            self.command_sync(CMD_AW::PBC);
+            for (j, word) in quad_word.iter().enumerate() {
+                unsafe { core::ptr::write_volatile((address as *mut u32).add(j), *word) };
+            }
+            self.set_address(address);
+            self.command_sync(CMD_AW::WQW);
+            self.manage_error_states()?;
+        }
+        Ok(true)
+    }
+
+    /// Write the fuses of the user page, then reset the device so that they
+    /// take effect
+    ///
+    /// See [`Nvm::write_user_page`]. The device is not reset if the fuses are
+    /// unchanged.
+    pub fn write_user_page_and_reset(&mut self, user_page: Userpage) -> Result<()> {
+        if self.write_user_page(user_page)? {
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        Ok(())
+    }
+
     /// Read the calibration area
     #[inline]
     pub fn calibration_area(&self) -> CalibrationArea {
@@ -284,15 +417,7 @@ impl Nvm {
     /// Read the calibration area for temperatures
     #[inline]
     pub fn temperatures_calibration_area(&self) -> TemperaturesCalibrationArea {
-        let mut buffer = 0_u128;
-        let base_addr: *const u8 = 0x0080_0100 as *const u8;
-
-        for i in 0..11 {
-            buffer |= unsafe { core::ptr::read_volatile(base_addr.offset(i as isize)) as u128 }
-                << (i * 8);
-        }
-
-        TemperaturesCalibrationArea(buffer)
+        read_temperatures_calibration_area()
     }
 
     /// Enable/disable boot protection on/off
@@ -453,15 +578,7 @@ impl Nvm {
     }
 
     fn contains_bootprotected(&self, input: &Range<u32>) -> bool {
-        // Calculate size that is protected for bootloader
-        //   * 15 = no bootprotection, default value
-        //   * 0 = max bootprotection, 15 * 8Kibyte = 120KiB
-        //   * (15 - bootprot) * 8KiB = protected size
-        let bootprot = self.nvm.status.read().bootprot().bits();
-        let bp_space = 8 * 1024 * (15 - bootprot) as u32;
-
-        let boot = &(Bank::Active.address()..(Bank::Active.address() + bp_space));
-        self.is_boot_protected() && range_overlap(input, boot)
+        range_overlap(input, &self.boot_protected_area())
     }
 
     fn contains_smart_eeprom(&self, input: &Range<u32>) -> bool {
@@ -481,6 +598,21 @@ impl Nvm {
     pub fn smart_eeprom(&mut self) -> smart_eeprom::Result {
         smart_eeprom::SmartEepromMode::retrieve(self)
     }
+
+    /// Reserve `length` bytes at `offset` in `bank` for data storage
+    ///
+    /// `offset` and `length` must be multiples of [`BLOCKSIZE`]. The region
+    /// must not overlap the running program, the boot protected area or the
+    /// SmartEEPROM.
+    #[inline]
+    pub fn flash_region(
+        &mut self,
+        bank: Bank,
+        offset: u32,
+        length: u32,
+    ) -> Result<storage::FlashRegion<'_>> {
+        storage::FlashRegion::new(self, bank, offset, length)
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -508,6 +640,22 @@ impl EraseGranularity {
     }
 }
 
+/// Read the calibration area for temperatures
+///
+/// The calibration area is readable without owning the [`NVMCTRL`] peripheral,
+/// which lets other drivers (e.g. the ADC) use the factory values directly.
+pub(crate) fn read_temperatures_calibration_area() -> TemperaturesCalibrationArea {
+    let mut buffer = 0_u128;
+    let base_addr: *const u8 = 0x0080_0100 as *const u8;
+
+    for i in 0..11 {
+        buffer |=
+            unsafe { core::ptr::read_volatile(base_addr.offset(i as isize)) as u128 } << (i * 8);
+    }
+
+    TemperaturesCalibrationArea(buffer)
+}
+
 fn range_overlap(a: &Range<u32>, b: &Range<u32>) -> bool {
     a.start < b.end && b.start < a.end
 }
@@ -518,25 +666,46 @@ bitfield! {
     pub struct Userpage(u128);
     impl Debug;
     u32;
-    bod33_disable, _: 0;
-    bod33_level, _: 8, 1;
-    bod33_action, _: 10, 9;
-    bod33_hysteresis, _: 14, 11;
-    bod12_calibration_parameters, _: 25, 12;
-    nvm_bootloader_size, _: 29, 26;
-    see_sblk, _: 35, 32;
-    see_psz, _: 38, 36;
-    ram_ecc_disable, _: 39;
-    wdt_enable, _: 48;
-    wdt_always_on, _: 49;
-    wdt_period, _: 53, 50;
-    wdt_window, _: 57, 54;
-    wdt_ewoffset, _: 61, 58;
-    wdt_wen, _: 62;
-    nvm_locks, _: 95, 64;
+    /// BOD33 disabled at power-on
+    pub bod33_disable, set_bod33_disable: 0;
+    /// BOD33 threshold level at power-on
+    pub bod33_level, set_bod33_level: 8, 1;
+    /// BOD33 action at power-on
+    pub bod33_action, set_bod33_action: 10, 9;
+    /// BOD33 hysteresis at power-on
+    pub bod33_hysteresis, set_bod33_hysteresis: 14, 11;
+    bod12_calibration_parameters, _: 25, 15;
+    /// Size of the boot protected area (BOOTPROT), `(15 - value) * 8` KiB
+    pub nvm_bootloader_size, set_nvm_bootloader_size: 29, 26;
+    /// Number of blocks used by the SmartEEPROM (SBLK)
+    pub see_sblk, set_see_sblk: 35, 32;
+    /// Size of a SmartEEPROM virtual page (PSZ)
+    pub see_psz, set_see_psz: 38, 36;
+    /// RAM ECC disabled
+    pub ram_ecc_disable, set_ram_ecc_disable: 39;
+    /// WDT enabled at power-on
+    pub wdt_enable, set_wdt_enable: 48;
+    /// WDT always-on at power-on
+    pub wdt_always_on, set_wdt_always_on: 49;
+    /// WDT period at power-on
+    pub wdt_period, set_wdt_period: 53, 50;
+    /// WDT window at power-on
+    pub wdt_window, set_wdt_window: 57, 54;
+    /// WDT early warning offset at power-on
+    pub wdt_ewoffset, set_wdt_ewoffset: 61, 58;
+    /// WDT window mode enabled at power-on
+    pub wdt_wen, set_wdt_wen: 62;
+    /// NVM region locks at power-on, a cleared bit locks a region
+    pub nvm_locks, set_nvm_locks: 95, 64;
     user_page, _: 127, 96;
 }
 
+impl Userpage {
+    /// Bits of the fuses that [`Nvm::write_user_page`] writes, the others are
+    /// reserved or factory calibration
+    const WRITABLE: u128 = 0x7fff | 0xf << 26 | 0xff << 32 | 0x7fff << 48 | 0xffff_ffff << 64;
+}
+
 bitfield! {
     #[derive(Copy, Clone, Default)]
     /// POD-style struct representing NVM calibration area
@@ -562,12 +731,20 @@ bitfield! {
     pub struct TemperaturesCalibrationArea(u128);
     impl Debug;
     u32;
-    tli, _: 7, 0;
-    tld, _: 11, 8;
-    thi, _: 19, 12;
-    thd, _: 23, 20;
-    vpl, _: 51, 40;
-    vph, _: 63, 52;
-    vcl, _: 75, 63;
-    vch, _: 87, 76;
+    /// Integer part of the room temperature in °C
+    pub tli, _: 7, 0;
+    /// Decimal part of the room temperature
+    pub tld, _: 11, 8;
+    /// Integer part of the hot temperature in °C
+    pub thi, _: 19, 12;
+    /// Decimal part of the hot temperature
+    pub thd, _: 23, 20;
+    /// PTAT reading at room temperature
+    pub vpl, _: 51, 40;
+    /// PTAT reading at hot temperature
+    pub vph, _: 63, 52;
+    /// CTAT reading at room temperature
+    pub vcl, _: 75, 64;
+    /// CTAT reading at hot temperature
+    pub vch, _: 87, 76;
 }
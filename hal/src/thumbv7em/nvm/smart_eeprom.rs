@@ -21,13 +21,26 @@
 //! populated from proper bits in NVM controller user page on power-on-reset. By
 //! default, `SBLK` property is set to `0`, effectively disabling SmartEEPROM.
 //!
-//! One of possible safe ways to change user page content is to use `OpenOCD`
-//! custom commmands. `atsame5x`'s `OpenOCD` driver supports `atsame5 userpage`
-//! command. To access it from GDB, it has to be preceded with a `monitor`
-//! clause.
+//! The user page can be changed at runtime with [`Nvm::write_user_page`]:
+//!
+//! ```no_run
+//! let mut user_page = nvm.user_page();
+//! user_page.set_see_sblk(1);
+//! user_page.set_see_psz(1);
+//! nvm.write_user_page_and_reset(user_page)?;
+//! ```
+//!
+//! It can also be changed with `OpenOCD` custom commmands. `atsame5x`'s
+//! `OpenOCD` driver supports `atsame5 userpage` command. To access it from GDB,
+//! it has to be preceded with a `monitor` clause.
 //!
 //! To access [`SmartEeprom`] struct, call [`Nvm::smart_eeprom`] method to
 //! retrieve its instance.
+//!
+//! The [`store`] module provides a key-value store for typed records on top of
+//! [`SmartEeprom`].
+
+pub mod store;
 
 use core::marker::PhantomData;
 
@@ -0,0 +1,520 @@
+//! # Key-value store
+//!
+//! [`Store`] keeps typed, versioned records in the SmartEEPROM, so that
+//! applications do not have to define their own layout for persistent
+//! settings:
+//!
+//! ```no_run
+//! struct Settings {
+//!     brightness: u8,
+//! }
+//!
+//! impl Record for Settings {
+//!     const KEY: u16 = 1;
+//!     const VERSION: u8 = 1;
+//!
+//!     fn serialize(&self, buffer: &mut [u8]) -> usize {
+//!         buffer[0] = self.brightness;
+//!         1
+//!     }
+//!
+//!     fn deserialize(_version: u8, data: &[u8]) -> Option<Self> {
+//!         Some(Settings {
+//!             brightness: *data.first()?,
+//!         })
+//!     }
+//! }
+//!
+//! if let SmartEepromMode::Unlocked(mut eeprom) = nvm.smart_eeprom()? {
+//!     let mut store = Store::new(&mut eeprom);
+//!     if !store.is_formatted() {
+//!         store.format()?;
+//!     }
+//!     store.set(&Settings { brightness: 10 })?;
+//!     store.flush()?;
+//!     let settings: Option<Settings> = store.get()?;
+//! }
+//! ```
+//!
+//! Each record has two copies, protected by a CRC and a sequence number. An
+//! update overwrites the older copy, so that an update interrupted by a reset
+//! leaves the previous value readable.
+//!
+//! Writes use the buffered mode of the SmartEEPROM: the NVM page buffer is
+//! written to flash when a write moves to another SmartEEPROM page, or when
+//! [`Store::flush`] is called. Buffered mode is enabled on the first write and
+//! disabled when the store is dropped, after a last flush.
+//!
+//! The store is read-only on a [`Locked`](super::Locked) SmartEEPROM. The space
+//! of removed records is reused by records of the same size, and is otherwise
+//! only reclaimed by [`Store::format`].
+
+use super::{wait_if_busy, SmartEeprom, SmartEepromState, Unlocked};
+use crate::pac::nvmctrl::ctrlb::CMD_AW;
+
+/// Marks a formatted store, "SEKV"
+const MAGIC: u32 = 0x564b_4553;
+
+/// Size of the store header, holding [`MAGIC`]
+const STORE_HEADER: usize = 4;
+
+/// Size of an entry header, holding the key and the length of the record
+const ENTRY_HEADER: usize = 4;
+
+/// Size of a copy header, holding the sequence number, the version and the CRC
+const COPY_HEADER: usize = 8;
+
+/// Key of the entry following the last one
+const KEY_END: u16 = 0xffff;
+
+/// Key of a removed entry
+const KEY_REMOVED: u16 = 0;
+
+/// Maximum size of a record handled by [`Store::get`] and [`Store::set`]
+pub const MAX_RECORD_SIZE: usize = 256;
+
+/// Errors of the key-value store
+#[derive(Debug)]
+pub enum Error {
+    /// The SmartEEPROM does not hold a store, see [`Store::format`]
+    NotFormatted,
+    /// Keys `0` and `0xffff` are reserved
+    InvalidKey,
+    /// The record does not fit in the buffer or is larger than the store
+    TooLarge,
+    /// There is not enough space left for the record
+    Full,
+    /// Both copies of a record, or the store directory, are corrupted
+    Corrupted,
+    /// [`Record::deserialize`] rejected the stored data
+    Deserialize,
+    /// The active SmartEEPROM sector overflowed and a write was discarded
+    Overflow,
+}
+
+/// Key-value store result type
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// A typed record of the store
+pub trait Record: Sized {
+    /// Key of the record, neither `0` nor `0xffff`
+    const KEY: u16;
+    /// Version of the serialized format, stored with the record
+    const VERSION: u8;
+
+    /// Serialize the record to `buffer`, which is [`MAX_RECORD_SIZE`] bytes
+    /// long, returning the number of bytes used
+    fn serialize(&self, buffer: &mut [u8]) -> usize;
+
+    /// Deserialize a record stored with `version`
+    ///
+    /// Older versions can be migrated here. `None` is reported as
+    /// [`Error::Deserialize`].
+    fn deserialize(version: u8, data: &[u8]) -> Option<Self>;
+}
+
+/// Version and length of a stored record
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RecordInfo {
+    /// Version given when the record was written
+    pub version: u8,
+    /// Length of the record in bytes
+    pub len: usize,
+}
+
+/// Entry of the store directory, followed by the two copies of a record
+#[derive(Copy, Clone)]
+struct Entry {
+    offset: usize,
+    key: u16,
+    len: usize,
+}
+
+impl Entry {
+    fn copy_size(&self) -> usize {
+        COPY_HEADER + ((self.len + 3) & !3)
+    }
+
+    fn size(&self) -> usize {
+        ENTRY_HEADER + 2 * self.copy_size()
+    }
+
+    fn copy_offset(&self, copy: usize) -> usize {
+        self.offset + ENTRY_HEADER + copy * self.copy_size()
+    }
+}
+
+/// Valid copy of a record
+#[derive(Copy, Clone)]
+struct RecordCopy {
+    index: usize,
+    sequence: u16,
+    version: u8,
+}
+
+/// Outcome of a walk through the directory
+enum Scan {
+    Found(Entry),
+    /// Offset following the last entry
+    End(usize),
+}
+
+/// Check whether sequence number `a` is newer than `b`, allowing wrapping
+fn is_newer(a: u16, b: u16) -> bool {
+    (a.wrapping_sub(b) as i16) > 0
+}
+
+/// Update a CRC-32 (IEEE 802.3) with `bytes`
+///
+/// Start with `!0` and invert the result.
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Key-value store on a SmartEEPROM
+pub struct Store<'s, 'a, T: SmartEepromState> {
+    eeprom: &'s mut SmartEeprom<'a, T>,
+}
+
+impl<'s, 'a, T: SmartEepromState> Store<'s, 'a, T> {
+    /// Create a store on `eeprom`
+    ///
+    /// The content is not checked, see [`Store::is_formatted`].
+    pub fn new(eeprom: &'s mut SmartEeprom<'a, T>) -> Self {
+        Self { eeprom }
+    }
+
+    /// Size of the store in bytes
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.eeprom.virtual_size
+    }
+
+    /// Check whether the SmartEEPROM holds a store
+    pub fn is_formatted(&self) -> bool {
+        self.read_u32(0) == MAGIC
+    }
+
+    /// Number of bytes left for new records
+    ///
+    /// A record of length `len` takes `4 + 2 * (8 + len)` bytes, rounding
+    /// `len` up to a multiple of 4.
+    pub fn free_space(&self) -> Result<usize> {
+        match self.scan(|_| false)? {
+            Scan::Found(_) => unreachable!(),
+            Scan::End(end) => Ok(self.capacity() - end),
+        }
+    }
+
+    /// Read the record stored with `key` to `buffer`
+    ///
+    /// Returns `None` if there is no such record.
+    pub fn read(&self, key: u16, buffer: &mut [u8]) -> Result<Option<RecordInfo>> {
+        check_key(key)?;
+        let entry = match self.scan(|entry| entry.key == key)? {
+            Scan::Found(entry) => entry,
+            Scan::End(_) => return Ok(None),
+        };
+        if buffer.len() < entry.len {
+            return Err(Error::TooLarge);
+        }
+        let copy = self.newest_copy(&entry).ok_or(Error::Corrupted)?;
+        let data = &mut buffer[..entry.len];
+        self.read_bytes(entry.copy_offset(copy.index) + COPY_HEADER, data);
+        Ok(Some(RecordInfo {
+            version: copy.version,
+            len: entry.len,
+        }))
+    }
+
+    /// Read a typed record
+    ///
+    /// Returns `None` if there is no such record.
+    pub fn get<R: Record>(&self) -> Result<Option<R>> {
+        let mut buffer = [0; MAX_RECORD_SIZE];
+        match self.read(R::KEY, &mut buffer)? {
+            Some(info) => R::deserialize(info.version, &buffer[..info.len])
+                .map(Some)
+                .ok_or(Error::Deserialize),
+            None => Ok(None),
+        }
+    }
+
+    fn read_bytes(&self, offset: usize, buffer: &mut [u8]) {
+        self.eeprom.get(offset, buffer);
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        let mut bytes = [0; 2];
+        self.read_bytes(offset, &mut bytes);
+        u16::from_le_bytes(bytes)
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        self.read_bytes(offset, &mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Walk through the directory until `f` accepts an entry
+    fn scan(&self, mut f: impl FnMut(&Entry) -> bool) -> Result<Scan> {
+        if !self.is_formatted() {
+            return Err(Error::NotFormatted);
+        }
+        let mut offset = STORE_HEADER;
+        loop {
+            if offset + ENTRY_HEADER > self.capacity() {
+                return Err(Error::Corrupted);
+            }
+            let key = self.read_u16(offset);
+            if key == KEY_END {
+                return Ok(Scan::End(offset));
+            }
+            let entry = Entry {
+                offset,
+                key,
+                len: self.read_u16(offset + 2) as usize,
+            };
+            if offset + entry.size() > self.capacity() {
+                return Err(Error::Corrupted);
+            }
+            if f(&entry) {
+                return Ok(Scan::Found(entry));
+            }
+            offset += entry.size();
+        }
+    }
+
+    /// Check the CRC of a copy of a record
+    fn copy(&self, entry: &Entry, index: usize) -> Option<RecordCopy> {
+        let offset = entry.copy_offset(index);
+        let mut header = [0; COPY_HEADER];
+        self.read_bytes(offset, &mut header);
+
+        let mut crc = crc32(!0, &entry.key.to_le_bytes());
+        crc = crc32(crc, &header[..3]);
+        let mut chunk = [0; 16];
+        let mut done = 0;
+        while done < entry.len {
+            let length = chunk.len().min(entry.len - done);
+            self.read_bytes(offset + COPY_HEADER + done, &mut chunk[..length]);
+            crc = crc32(crc, &chunk[..length]);
+            done += length;
+        }
+
+        let stored = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if !crc == stored {
+            Some(RecordCopy {
+                index,
+                sequence: u16::from_le_bytes([header[0], header[1]]),
+                version: header[2],
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Find the valid copy of a record with the newest sequence number
+    fn newest_copy(&self, entry: &Entry) -> Option<RecordCopy> {
+        match (self.copy(entry, 0), self.copy(entry, 1)) {
+            (Some(a), Some(b)) if is_newer(b.sequence, a.sequence) => Some(b),
+            (Some(a), _) => Some(a),
+            (None, b) => b,
+        }
+    }
+}
+
+impl<'s, 'a> Store<'s, 'a, Unlocked> {
+    /// Create an empty store, discarding the content of the SmartEEPROM
+    pub fn format(&mut self) -> Result<()> {
+        self.begin_write();
+        self.write_bytes(STORE_HEADER, &[0xff; ENTRY_HEADER]);
+        self.write_bytes(0, &MAGIC.to_le_bytes());
+        self.flush()
+    }
+
+    /// Write `data` as the record stored with `key`
+    ///
+    /// The record is replaced if it exists. If its length changes, the new
+    /// record is written before the old one is removed.
+    pub fn write(&mut self, key: u16, version: u8, data: &[u8]) -> Result<()> {
+        check_key(key)?;
+        if data.len() > self.capacity() {
+            return Err(Error::TooLarge);
+        }
+        match self.scan(|entry| entry.key == key)? {
+            Scan::Found(entry) if entry.len == data.len() => {
+                // Overwrite the older copy
+                let (index, sequence) = match self.newest_copy(&entry) {
+                    Some(copy) => (1 - copy.index, copy.sequence.wrapping_add(1)),
+                    None => (0, 0),
+                };
+                self.begin_write();
+                self.write_copy(&entry, index, sequence, version, data);
+                return self.check_overflow();
+            }
+            _ => {}
+        }
+
+        let mut entry = Entry {
+            offset: 0,
+            key,
+            len: data.len(),
+        };
+        let end = match self.scan(|other| other.key == KEY_REMOVED && other.len == entry.len)? {
+            Scan::Found(removed) => {
+                entry.offset = removed.offset;
+                None
+            }
+            Scan::End(end) => {
+                if end + entry.size() + ENTRY_HEADER > self.capacity() {
+                    return Err(Error::Full);
+                }
+                entry.offset = end;
+                Some(end + entry.size())
+            }
+        };
+
+        // The entry only becomes visible once both copies and the following
+        // end marker are written
+        self.begin_write();
+        self.write_copy(&entry, 0, 0, version, data);
+        self.write_bytes(entry.copy_offset(1), &[0xff; COPY_HEADER]);
+        if let Some(end) = end {
+            self.write_bytes(end, &[0xff; ENTRY_HEADER]);
+        }
+        let mut header = [0; ENTRY_HEADER];
+        header[..2].copy_from_slice(&key.to_le_bytes());
+        header[2..].copy_from_slice(&(entry.len as u16).to_le_bytes());
+        self.write_bytes(entry.offset, &header);
+
+        self.remove_entries(key, Some(entry.offset))?;
+        self.check_overflow()
+    }
+
+    /// Write a typed record
+    pub fn set<R: Record>(&mut self, record: &R) -> Result<()> {
+        let mut buffer = [0; MAX_RECORD_SIZE];
+        let len = record.serialize(&mut buffer);
+        self.write(R::KEY, R::VERSION, &buffer[..len])
+    }
+
+    /// Remove the record stored with `key`
+    ///
+    /// Returns `false` if there is no such record.
+    pub fn remove(&mut self, key: u16) -> Result<bool> {
+        check_key(key)?;
+        self.begin_write();
+        let removed = self.remove_entries(key, None)?;
+        self.check_overflow()?;
+        Ok(removed)
+    }
+
+    /// Remove the entries of `key` except the one at `keep`
+    ///
+    /// An interrupted length change can leave several entries for a key.
+    fn remove_entries(&mut self, key: u16, keep: Option<usize>) -> Result<bool> {
+        let mut removed = false;
+        while let Scan::Found(entry) =
+            self.scan(|entry| entry.key == key && Some(entry.offset) != keep)?
+        {
+            self.write_bytes(entry.offset, &KEY_REMOVED.to_le_bytes());
+            removed = true;
+        }
+        Ok(removed)
+    }
+
+    /// Write the buffered data to flash
+    pub fn flush(&mut self) -> Result<()> {
+        wait_if_busy();
+        self.eeprom.nvm.command_sync(CMD_AW::SEEFLUSH);
+        self.check_overflow()
+    }
+
+    fn write_bytes(&mut self, offset: usize, bytes: &[u8]) {
+        self.eeprom.set(offset, bytes);
+    }
+
+    fn write_copy(&mut self, entry: &Entry, index: usize, sequence: u16, version: u8, data: &[u8]) {
+        let mut header = [0; COPY_HEADER];
+        header[..2].copy_from_slice(&sequence.to_le_bytes());
+        header[2] = version;
+        header[3] = 0xff;
+        let mut crc = crc32(!0, &entry.key.to_le_bytes());
+        crc = crc32(crc, &header[..3]);
+        crc = crc32(crc, data);
+        header[4..].copy_from_slice(&(!crc).to_le_bytes());
+
+        let offset = entry.copy_offset(index);
+        self.write_bytes(offset + COPY_HEADER, data);
+        self.write_bytes(offset, &header);
+    }
+
+    fn begin_write(&mut self) {
+        let seecfg = &self.eeprom.nvm.nvm.seecfg;
+        if seecfg.read().wmode().is_unbuffered() {
+            wait_if_busy();
+            seecfg.modify(|_, w| w.wmode().buffered());
+        }
+    }
+
+    fn check_overflow(&mut self) -> Result<()> {
+        let intflag = &self.eeprom.nvm.nvm.intflag;
+        if intflag.read().seesovf().bit_is_set() {
+            intflag.write(|w| w.seesovf().set_bit());
+            Err(Error::Overflow)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<T: SmartEepromState> Drop for Store<'_, '_, T> {
+    fn drop(&mut self) {
+        let nvm = &mut self.eeprom.nvm;
+        if nvm.nvm.seecfg.read().wmode().is_buffered() {
+            wait_if_busy();
+            nvm.command_sync(CMD_AW::SEEFLUSH);
+            wait_if_busy();
+            nvm.nvm.seecfg.modify(|_, w| w.wmode().unbuffered());
+        }
+    }
+}
+
+fn check_key(key: u16) -> Result<()> {
+    if key == KEY_REMOVED || key == KEY_END {
+        Err(Error::InvalidKey)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(!crc32(!0, b"123456789"), 0xcbf4_3926);
+        let crc = crc32(!0, b"1234");
+        assert_eq!(!crc32(crc, b"56789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_sequence_wrapping() {
+        assert!(is_newer(1, 0));
+        assert!(!is_newer(0, 1));
+        assert!(!is_newer(5, 5));
+        assert!(is_newer(0, 0xffff));
+    }
+}
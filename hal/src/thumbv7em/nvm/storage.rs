@@ -0,0 +1,186 @@
+//! # Flash storage
+//!
+//! A [`FlashRegion`] is a range of blocks of a flash bank, reserved for data
+//! storage. It implements the [`embedded_storage`] NOR flash traits, so that
+//! storage crates such as `sequential-storage` can use the on-chip flash:
+//!
+//! ```no_run
+//! let mut nvm = Nvm::new(peripherals.NVMCTRL);
+//! // The last 4 blocks of the inactive bank
+//! let length = 4 * BLOCKSIZE;
+//! let mut region = nvm.flash_region(Bank::Inactive, Bank::Inactive.length() - length, length)?;
+//! region.erase(0, BLOCKSIZE)?;
+//! region.write(0, &[0x55; 16])?;
+//! ```
+//!
+//! Regions are checked when they are created: they cannot overlap the
+//! running program, the boot protected area or the SmartEEPROM. The program
+//! extent is taken from the `cortex-m-rt` linker symbols.
+//!
+//! The flash is protected by ECC, which is computed when a quad-word is
+//! written. A quad-word can therefore only be written once between erases, and
+//! [`MultiwriteNorFlash`](embedded_storage::nor_flash::MultiwriteNorFlash) is
+//! not implemented.
+
+use super::{Bank, EraseGranularity, Error, Nvm, Result, BLOCKSIZE, PAGESIZE};
+use core::ops::Range;
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// Size of a quad-word, the write unit of the flash
+const QUAD_WORD: u32 = 16;
+
+/// Flash range of the running program, from the start of the active bank to
+/// the end of the initial values of `.data`
+fn program_area() -> Range<u32> {
+    extern "C" {
+        static __sidata: u32;
+        static __sdata: u32;
+        static __edata: u32;
+    }
+    // Taking the address of an extern static is only safe on recent compilers
+    #[allow(unused_unsafe)]
+    let (sidata, sdata, edata) = unsafe {
+        (
+            core::ptr::addr_of!(__sidata) as u32,
+            core::ptr::addr_of!(__sdata) as u32,
+            core::ptr::addr_of!(__edata) as u32,
+        )
+    };
+    Bank::Active.address()..sidata + (edata - sdata)
+}
+
+/// Flash region reserved for data storage
+pub struct FlashRegion<'a> {
+    nvm: &'a mut Nvm,
+    /// Absolute address range
+    range: Range<u32>,
+}
+
+impl<'a> FlashRegion<'a> {
+    pub(super) fn new(nvm: &'a mut Nvm, bank: Bank, offset: u32, length: u32) -> Result<Self> {
+        if length == 0 || offset % BLOCKSIZE != 0 || length % BLOCKSIZE != 0 {
+            return Err(Error::Alignment);
+        }
+        if offset
+            .checked_add(length)
+            .map_or(true, |end| end > bank.length())
+        {
+            return Err(Error::NonFlash);
+        }
+
+        let start = bank.address() + offset;
+        let range = start..start + length;
+        if nvm.contains_bootprotected(&range) {
+            Err(Error::Protected)
+        } else if nvm.contains_smart_eeprom(&range) {
+            Err(Error::SmartEepromArea)
+        } else if super::range_overlap(&range, &program_area()) {
+            Err(Error::ProgramArea)
+        } else {
+            Ok(Self { nvm, range })
+        }
+    }
+
+    /// Absolute address of the region
+    #[inline]
+    pub fn address(&self) -> u32 {
+        self.range.start
+    }
+
+    /// Length of the region in bytes
+    #[inline]
+    pub fn length(&self) -> u32 {
+        self.range.end - self.range.start
+    }
+
+    /// Check that `offset..offset + length` is in the region and aligned to
+    /// `align`
+    fn check(&self, offset: u32, length: usize, align: u32) -> Result<()> {
+        if offset % align != 0 || length as u32 % align != 0 {
+            Err(Error::Alignment)
+        } else if offset as u64 + length as u64 > self.length() as u64 {
+            Err(Error::NonFlash)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl NorFlashError for Error {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Error::Alignment => NorFlashErrorKind::NotAligned,
+            Error::NonFlash => NorFlashErrorKind::OutOfBounds,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorType for FlashRegion<'_> {
+    type Error = Error;
+}
+
+impl ReadNorFlash for FlashRegion<'_> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<()> {
+        self.check(offset, bytes.len(), 1)?;
+        while !self.nvm.is_ready() {}
+        let source = (self.range.start + offset) as *const u8;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile(source.add(i)) };
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.length() as usize
+    }
+}
+
+impl NorFlash for FlashRegion<'_> {
+    const WRITE_SIZE: usize = QUAD_WORD as usize;
+    const ERASE_SIZE: usize = BLOCKSIZE as usize;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<()> {
+        if to < from {
+            return Err(Error::NonFlash);
+        }
+        self.check(from, (to - from) as usize, BLOCKSIZE)?;
+        let blocks = (to - from) / BLOCKSIZE;
+        if blocks == 0 {
+            return Ok(());
+        }
+        // Safety: the region does not overlap the program
+        unsafe {
+            self.nvm
+                .erase(self.range.start + from, blocks, EraseGranularity::Block)
+        }
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<()> {
+        self.check(offset, bytes.len(), QUAD_WORD)?;
+
+        // `Nvm::write` needs word-aligned data, so copy each page through a
+        // buffer
+        let mut buffer = [0u32; PAGESIZE as usize / 4];
+        let mut address = self.range.start + offset;
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let length = bytes.len().min((PAGESIZE - address % PAGESIZE) as usize);
+            for (word, chunk) in buffer.iter_mut().zip(bytes[..length].chunks(4)) {
+                *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            }
+            // Safety: the region does not overlap the program
+            unsafe {
+                self.nvm
+                    .write(address, buffer.as_ptr() as u32, length as u32 / 4)?;
+            }
+            address += length as u32;
+            bytes = &bytes[length..];
+        }
+        Ok(())
+    }
+}
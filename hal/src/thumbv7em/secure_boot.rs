@@ -0,0 +1,324 @@
+//! # Secure boot
+//!
+//! This module allows a first-stage loader to verify the signature of an
+//! application image before jumping to it.
+//!
+//! An image slot is a word-aligned flash range holding the image at its start
+//! and a trailer in its last [`TRAILER_SIZE`] bytes:
+//!
+//! ```text
+//! [ image | unused | magic | length | crc | reserved | signature ]
+//!                  ^ slot.end - TRAILER_SIZE                     ^ slot.end
+//! ```
+//!
+//! - `magic`: [`TRAILER_MAGIC`], little endian
+//! - `length`: length of the image in bytes, a multiple of 4, little endian
+//! - `crc`: CRC32 of the image as computed by [`Dsu::crc32`], little endian
+//! - `reserved`: 4 bytes, ignored
+//! - `signature`: P-256 ECDSA signature of the SHA-256 digest of the image,
+//!   `r` then `s`, big endian
+//!
+//! [`SecureBoot::verify`] checks the CRC with the DSU, hashes the image with
+//! the ICM SHA-256 engine and verifies the signature with the PUKCC:
+//!
+//! ```no_run
+//! // Stored in the bootloader, inside the boot protected area
+//! static PUBLIC_KEY: [u8; 64] = [0; 64];
+//!
+//! let pukcc = Pukcc::enable(&mut peripherals.MCLK).unwrap();
+//! let icm = Icm::new(peripherals.ICM);
+//! let dsu = Dsu::new(peripherals.DSU, &peripherals.PAC).unwrap();
+//! let nvm = Nvm::new(peripherals.NVMCTRL);
+//!
+//! let mut secure_boot = SecureBoot::new(pukcc, icm, dsu, nvm, &PUBLIC_KEY).unwrap();
+//! match secure_boot.verify(0x4000..0x8_0000).unwrap() {
+//!     Verdict::Valid { .. } => unsafe { cortex_m::asm::bootload(0x4000 as *const u32) },
+//!     verdict => panic!("{:?}", verdict),
+//! }
+//! ```
+//!
+//! The public key must lie in the flash area protected by the BOOTPROT fuses,
+//! see [`Userpage::set_nvm_bootloader_size`](nvm::Userpage::set_nvm_bootloader_size).
+//! The NVM regions holding it are also locked until the next reset.
+//!
+//! The ICM APB clock must be enabled.
+//!
+//! ## WARNING!
+//! This module has not been evaluated for correctness nor suitability for any
+//! use-case, see the warning of the [`pukcc`](crate::pukcc) module.
+#![warn(missing_docs)]
+
+use core::ops::Range;
+
+use crate::dsu::{self, Dsu};
+use crate::icm::{icm_algorithm, HashArea, Icm, RegionControl, RegionDesc, Regions};
+use crate::nvm::{self, Nvm};
+use crate::pukcc::{
+    curves::Nist256p, EcdsaSignatureVerificationFailure, Pukcc, PukclReturnCode,
+    PukclReturnCodeWarning,
+};
+
+/// Size of the trailer at the end of an image slot
+pub const TRAILER_SIZE: u32 = 80;
+
+/// Marks the trailer of a signed image, "SIGN"
+pub const TRAILER_MAGIC: u32 = 0x4e47_4953;
+
+/// Size of a SHA-256 block
+const BLOCK: u32 = 64;
+
+/// Length of the longest range the ICM can hash in one region, 65536 blocks
+const MAX_LENGTH: u32 = 0x40_0000;
+
+/// Outcome of the verification of an image slot
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The image is authentic
+    Valid {
+        /// Length of the image in bytes
+        length: u32,
+    },
+    /// There is no trailer, the slot is empty or the image is not signed
+    Missing,
+    /// The trailer describes an image that does not fit in the slot
+    Malformed,
+    /// The image does not match its CRC, e.g. after an interrupted update
+    Corrupted,
+    /// The signature does not match the image and the public key
+    Rejected,
+}
+
+impl Verdict {
+    /// Check if the image is authentic
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Verdict::Valid { .. })
+    }
+}
+
+/// Errors preventing a verification
+#[derive(Debug)]
+pub enum Error {
+    /// The public key is not inside the boot protected area
+    UnprotectedKey,
+    /// The slot is not word aligned, is smaller than a trailer or is not
+    /// inside the flash
+    InvalidSlot,
+    /// The range to hash is 4 MiB or longer
+    TooLong,
+    /// The NVM failed to lock the public key
+    Nvm(nvm::Error),
+    /// The DSU failed to compute the CRC
+    Dsu(dsu::Error),
+    /// The ICM reported a bus error while hashing
+    IcmBusError,
+    /// The PUKCC failed to verify the signature
+    Pukcc(EcdsaSignatureVerificationFailure),
+}
+
+/// Secure boot result type
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// RAM buffer holding the padded end of a message
+#[repr(C, align(64))]
+struct Tail([u8; 2 * BLOCK as usize]);
+
+/// Image verification service
+pub struct SecureBoot {
+    pukcc: Pukcc,
+    icm: Icm,
+    dsu: Dsu,
+    nvm: Nvm,
+    public_key: &'static [u8; 64],
+}
+
+impl SecureBoot {
+    /// Create the service with a P-256 public key, `x` then `y`, big endian
+    ///
+    /// The key must be inside the boot protected area. The NVM regions
+    /// holding it are locked.
+    pub fn new(
+        pukcc: Pukcc,
+        icm: Icm,
+        dsu: Dsu,
+        mut nvm: Nvm,
+        public_key: &'static [u8; 64],
+    ) -> Result<Self> {
+        let start = public_key.as_ptr() as u32;
+        let end = start + public_key.len() as u32;
+        let protected = nvm.boot_protected_area();
+        if start < protected.start || end > protected.end {
+            return Err(Error::UnprotectedKey);
+        }
+
+        let region_size = nvm.region_size();
+        for region in start / region_size..=(end - 1) / region_size {
+            nvm.lock_region(region * region_size).map_err(Error::Nvm)?;
+        }
+
+        Ok(Self {
+            pukcc,
+            icm,
+            dsu,
+            nvm,
+            public_key,
+        })
+    }
+
+    /// Release the peripherals
+    ///
+    /// The NVM regions holding the public key stay locked.
+    pub fn free(self) -> (Pukcc, Icm, Dsu, Nvm) {
+        (self.pukcc, self.icm, self.dsu, self.nvm)
+    }
+
+    /// Verify the image in `slot`
+    pub fn verify(&mut self, slot: Range<u32>) -> Result<Verdict> {
+        let min_end = slot
+            .start
+            .checked_add(TRAILER_SIZE)
+            .ok_or(Error::InvalidSlot)?;
+        if slot.start % 4 != 0
+            || slot.end % 4 != 0
+            || slot.end < min_end
+            || slot.end > nvm::retrieve_flash_size()
+        {
+            return Err(Error::InvalidSlot);
+        }
+        let trailer_address = slot.end - TRAILER_SIZE;
+        // Safety: the trailer is in flash, which is always readable
+        let trailer = unsafe {
+            core::slice::from_raw_parts(trailer_address as *const u8, TRAILER_SIZE as usize)
+        };
+        let word = |offset: usize| {
+            u32::from_le_bytes([
+                trailer[offset],
+                trailer[offset + 1],
+                trailer[offset + 2],
+                trailer[offset + 3],
+            ])
+        };
+
+        if word(0) != TRAILER_MAGIC {
+            return Ok(Verdict::Missing);
+        }
+        let length = word(4);
+        if length == 0 || length % 4 != 0 || length > trailer_address - slot.start {
+            return Ok(Verdict::Malformed);
+        }
+        let crc = self.dsu.crc32(slot.start, length).map_err(Error::Dsu)?;
+        if crc != word(8) {
+            return Ok(Verdict::Corrupted);
+        }
+
+        // Safety: the image is in flash, before the trailer
+        let hash = unsafe { self.sha256(slot.start, length)? };
+        match self.pukcc.zp_ecdsa_verify_signature::<Nist256p>(
+            &trailer[16..],
+            &hash,
+            self.public_key,
+        ) {
+            Ok(()) => Ok(Verdict::Valid { length }),
+            Err(EcdsaSignatureVerificationFailure::ServiceFailure(PukclReturnCode::Warning(
+                PukclReturnCodeWarning::WrongSignature,
+            ))) => Ok(Verdict::Rejected),
+            Err(e) => Err(Error::Pukcc(e)),
+        }
+    }
+
+    /// Compute the SHA-256 digest of a memory range with the ICM
+    ///
+    /// Returns [`Error::TooLong`] if `length` is 4 MiB or more.
+    ///
+    /// # Safety
+    ///
+    /// The `length` bytes at `address` must be readable by the CPU and by the
+    /// ICM.
+    pub unsafe fn sha256(&mut self, address: u32, length: u32) -> Result<[u8; 32]> {
+        if length >= MAX_LENGTH {
+            return Err(Error::TooLong);
+        }
+        let blocks = length / BLOCK;
+
+        // The ICM does not pad messages, so the last bytes are copied to RAM
+        // and padded there
+        let mut tail = Tail([0; 2 * BLOCK as usize]);
+        let tail_length = (length % BLOCK) as usize;
+        // Safety: the caller provides a readable range
+        let source =
+            core::slice::from_raw_parts((address + blocks * BLOCK) as *const u8, tail_length);
+        tail.0[..tail_length].copy_from_slice(source);
+        tail.0[tail_length] = 0x80;
+        let tail_blocks = if tail_length + 9 <= BLOCK as usize {
+            1
+        } else {
+            2
+        };
+        let tail_end = tail_blocks * BLOCK as usize;
+        tail.0[tail_end - 8..tail_end].copy_from_slice(&(length as u64 * 8).to_be_bytes());
+
+        // The intermediate digest of the whole blocks is the initial value of
+        // the pass over the tail
+        let state = if blocks > 0 {
+            Some(self.icm_pass(address, blocks, None)?)
+        } else {
+            None
+        };
+        let digest = self.icm_pass(tail.0.as_ptr() as u32, tail_blocks as u32, state)?;
+
+        // The ICM writes the digest bytes in order
+        let mut hash = [0; 32];
+        for (bytes, word) in hash.chunks_mut(4).zip(digest.iter()) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(hash)
+    }
+
+    /// Hash `blocks` blocks at `address` with the ICM, starting from `state`
+    /// or from the SHA-256 initial value
+    fn icm_pass(&mut self, address: u32, blocks: u32, state: Option<[u32; 8]>) -> Result<[u32; 8]> {
+        let mut regions = Regions::default();
+        let hash = HashArea::default();
+
+        let region = &mut regions.region0;
+        region.set_region_address(address as *const u8);
+        region.rcfg.set_algo(icm_algorithm::SHA256);
+        // Stop after this region, and report its completion and bus errors
+        region.rcfg.set_eom(true);
+        region.rcfg.set_rhien(false);
+        region.rcfg.set_beien(false);
+        region.rctrl = RegionControl {
+            trsize: (blocks - 1) as u16,
+        };
+
+        let icm = &mut self.icm;
+        icm.swrst();
+        icm.set_user_configurable_hash(state.is_some());
+        if let Some(state) = state {
+            // The digest is written in the same byte order as the initial
+            // value is expected
+            icm.set_user_algorithm(icm_algorithm::SHA256);
+            icm.set_user_initial_hash_value(state);
+        }
+        icm.set_hash_addr(&hash);
+        icm.set_dscr_addr(&regions.region0);
+        let mut region0 = icm.enable_region0();
+        region0.enable_monitoring();
+        icm.enable();
+
+        let status = loop {
+            let status = region0.get_interrupt_status();
+            if status.get_rhc_int() || status.get_rbe_int() {
+                break status;
+            }
+        };
+        icm.disable();
+
+        if status.get_rbe_int() {
+            Err(Error::IcmBusError)
+        } else {
+            // Safety: the ICM is done writing the hash area
+            Ok(unsafe { core::ptr::read_volatile(&hash.region0) })
+        }
+    }
+}
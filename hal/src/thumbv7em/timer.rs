@@ -10,6 +10,7 @@ use crate::pac::{TC4, TC5};
 use crate::timer_traits::InterruptDrivenTimer;
 
 use crate::clock;
+use crate::reclock::Reclock;
 use crate::time::{Hertz, Nanoseconds};
 use void::Void;
 
@@ -133,6 +134,14 @@ where
     }
 }
 
+impl<TC> Reclock for TimerCounter<TC> {
+    /// Update the frequency of the timer clock. The new frequency is used
+    /// to compute the prescaler and period on the next call to `start`.
+    fn clock_changed(&mut self, freq: Hertz) {
+        self.freq = freq;
+    }
+}
+
 macro_rules! tc {
     ($($TYPE:ident: ($TC:ident, $mclk:ident, $clock:ident, $apmask:ident),)+) => {
         $(
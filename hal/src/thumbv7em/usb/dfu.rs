@@ -0,0 +1,474 @@
+//! USB Device Firmware Upgrade (DFU 1.1)
+//!
+//! Firmware updates are written to the inactive flash bank while the
+//! application keeps running from the active bank, then the banks are swapped.
+//! No separate bootloader is needed, the application provides both DFU
+//! interfaces:
+//!
+//! - [`DfuRuntime`] is added to the classes of the normal device. The host
+//!   sends it a `DFU_DETACH` request, after which the application resets into
+//!   its DFU mode.
+//! - [`Dfu`] is the only class of the device in DFU mode. It receives the
+//!   image into the inactive bank through [`Nvm::write`], checks it with
+//!   [`Dsu::crc32`], and is ready to swap the banks once the host ends the
+//!   download.
+//!
+//! The image must be a multiple of 4 bytes long, and end with the CRC-32 of
+//! the rest of the image, as computed by [`Dsu::crc32`], in little endian.
+//!
+//! ```no_run
+//! let mut dfu = Dfu::new(&bus_allocator, nvm, dsu);
+//! let mut usb_dev = UsbDeviceBuilder::new(&bus_allocator, UsbVidPid(0x1209, 0x0001)).build();
+//! loop {
+//!     usb_dev.poll(&mut [&mut dfu]);
+//!     if dfu.manifested() {
+//!         // Let the status stage of the last request complete
+//!         delay.delay_ms(10u8);
+//!         unsafe { dfu.swap_banks() };
+//!     }
+//! }
+//! ```
+
+use usb_device::bus::{InterfaceNumber, UsbBus, UsbBusAllocator};
+use usb_device::class::{ControlIn, ControlOut, UsbClass};
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::descriptor::DescriptorWriter;
+use usb_device::Result as UsbResult;
+
+use crate::dsu::Dsu;
+use crate::nvm::{self, Bank, EraseGranularity, Nvm, BLOCKSIZE, PAGESIZE};
+
+/// Maximum size of the data of a `DFU_DNLOAD` request
+///
+/// This matches the default control buffer size of `usb-device`.
+pub const TRANSFER_SIZE: u16 = 128;
+
+const CLASS_APPLICATION_SPECIFIC: u8 = 0xFE;
+const SUBCLASS_DFU: u8 = 0x01;
+const PROTOCOL_RUNTIME: u8 = 0x01;
+const PROTOCOL_DFU_MODE: u8 = 0x02;
+
+const DESC_DFU_FUNCTIONAL: u8 = 0x21;
+
+const DFU_DETACH: u8 = 0;
+const DFU_DNLOAD: u8 = 1;
+const DFU_GETSTATUS: u8 = 3;
+const DFU_CLRSTATUS: u8 = 4;
+const DFU_GETSTATE: u8 = 5;
+const DFU_ABORT: u8 = 6;
+
+const ATTR_CAN_DNLOAD: u8 = 1 << 0;
+const ATTR_WILL_DETACH: u8 = 1 << 3;
+
+/// Time the host waits for the application to reset into DFU mode, in ms
+const DETACH_TIMEOUT: u16 = 1000;
+
+/// DFU state, as reported by `DFU_GETSTATE`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum State {
+    /// Running the application
+    AppIdle = 0,
+    /// The host requested a detach, the application must reset into DFU mode
+    AppDetach = 1,
+    /// DFU mode, waiting for requests
+    DfuIdle = 2,
+    /// A block has been received
+    DfuDnloadSync = 3,
+    /// A block is being programmed
+    DfuDnBusy = 4,
+    /// Waiting for the next block
+    DfuDnloadIdle = 5,
+    /// The download is complete, the image is checked on the next status
+    /// request
+    DfuManifestSync = 6,
+    /// The image is being manifested
+    DfuManifest = 7,
+    /// The image is valid, the banks are swapped on the next reset
+    DfuManifestWaitReset = 8,
+    /// Uploading
+    DfuUploadIdle = 9,
+    /// An error occurred, the host must clear the status
+    DfuError = 10,
+}
+
+/// DFU status, as reported by `DFU_GETSTATUS`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Status {
+    /// No error
+    Ok = 0x00,
+    /// The image is not for this device
+    Target = 0x01,
+    /// The image fails a vendor check
+    File = 0x02,
+    /// Programming failed
+    Write = 0x03,
+    /// Erasing failed
+    Erase = 0x04,
+    /// Erase check failed
+    CheckErased = 0x05,
+    /// Program memory function failed
+    Prog = 0x06,
+    /// The CRC of the image does not match
+    Verify = 0x07,
+    /// The image does not fit in the inactive bank
+    Address = 0x08,
+    /// The download ended before the image was complete
+    NotDone = 0x09,
+    /// The firmware is corrupt
+    Firmware = 0x0A,
+    /// Vendor specific error
+    Vendor = 0x0B,
+    /// Unexpected USB reset
+    UsbReset = 0x0C,
+    /// Unexpected power on reset
+    PowerOnReset = 0x0D,
+    /// Unknown error
+    Unknown = 0x0E,
+    /// Unexpected request
+    StalledPacket = 0x0F,
+}
+
+fn write_functional_descriptor(writer: &mut DescriptorWriter, attributes: u8) -> UsbResult<()> {
+    writer.write(
+        DESC_DFU_FUNCTIONAL,
+        &[
+            attributes,
+            DETACH_TIMEOUT as u8,
+            (DETACH_TIMEOUT >> 8) as u8,
+            TRANSFER_SIZE as u8,
+            (TRANSFER_SIZE >> 8) as u8,
+            // bcdDFUVersion 1.1
+            0x10,
+            0x01,
+        ],
+    )
+}
+
+fn is_dfu_request(req: &Request, iface: InterfaceNumber) -> bool {
+    req.request_type == RequestType::Class
+        && req.recipient == Recipient::Interface
+        && req.index == u8::from(iface) as u16
+}
+
+/// DFU interface of the application
+///
+/// Once the host sent `DFU_DETACH`, [`DfuRuntime::detach_requested`] is true
+/// and the application must reset into DFU mode within 1 s, e.g. by leaving a
+/// flag in uninitialized RAM for the next boot.
+pub struct DfuRuntime {
+    iface: InterfaceNumber,
+    state: State,
+}
+
+impl DfuRuntime {
+    /// Allocate the DFU runtime interface
+    pub fn new<B: UsbBus>(alloc: &UsbBusAllocator<B>) -> Self {
+        Self {
+            iface: alloc.interface(),
+            state: State::AppIdle,
+        }
+    }
+
+    /// Whether the host requested to switch to DFU mode
+    pub fn detach_requested(&self) -> bool {
+        self.state == State::AppDetach
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for DfuRuntime {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> UsbResult<()> {
+        writer.interface(
+            self.iface,
+            CLASS_APPLICATION_SPECIFIC,
+            SUBCLASS_DFU,
+            PROTOCOL_RUNTIME,
+        )?;
+        write_functional_descriptor(writer, ATTR_CAN_DNLOAD | ATTR_WILL_DETACH)
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !is_dfu_request(&req, self.iface) {
+            return;
+        }
+
+        match req.request {
+            DFU_DETACH => {
+                self.state = State::AppDetach;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !is_dfu_request(&req, self.iface) {
+            return;
+        }
+
+        match req.request {
+            DFU_GETSTATUS => {
+                xfer.accept_with(&[Status::Ok as u8, 0, 0, 0, self.state as u8, 0])
+                    .ok();
+            }
+            DFU_GETSTATE => {
+                xfer.accept_with(&[self.state as u8]).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
+
+/// Page buffer, aligned for [`Nvm::write`]
+#[repr(align(4))]
+struct PageBuffer([u8; PAGESIZE as usize]);
+
+/// DFU interface of the device in DFU mode
+///
+/// The image is written to the inactive bank one page at a time, erasing
+/// each block before its first page is written. The active bank, which runs
+/// the current firmware, is never modified.
+pub struct Dfu {
+    iface: InterfaceNumber,
+    nvm: Nvm,
+    dsu: Dsu,
+    state: State,
+    status: Status,
+    /// Offset of the page being received in the inactive bank
+    offset: u32,
+    page: PageBuffer,
+    /// Number of bytes in `page`
+    fill: usize,
+}
+
+impl Dfu {
+    /// Allocate the DFU mode interface
+    pub fn new<B: UsbBus>(alloc: &UsbBusAllocator<B>, nvm: Nvm, dsu: Dsu) -> Self {
+        Self {
+            iface: alloc.interface(),
+            nvm,
+            dsu,
+            state: State::DfuIdle,
+            status: Status::Ok,
+            offset: 0,
+            page: PageBuffer([0xFF; PAGESIZE as usize]),
+            fill: 0,
+        }
+    }
+
+    /// Current DFU state
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Whether a valid image has been downloaded to the inactive bank
+    pub fn manifested(&self) -> bool {
+        self.state == State::DfuManifestWaitReset
+    }
+
+    /// Swap the flash banks to run the downloaded image
+    ///
+    /// # Safety
+    /// The image passed its CRC check once [`Dfu::manifested`] is true, but
+    /// the CRC cannot tell whether it is a working, memory safe program for
+    /// this device.
+    pub unsafe fn swap_banks(&mut self) -> ! {
+        self.nvm.bank_swap()
+    }
+
+    /// Release the peripherals
+    pub fn free(self) -> (Nvm, Dsu) {
+        (self.nvm, self.dsu)
+    }
+
+    fn restart(&mut self) {
+        self.offset = 0;
+        self.fill = 0;
+    }
+
+    fn fail(&mut self, status: Status) {
+        self.state = State::DfuError;
+        self.status = status;
+    }
+
+    /// Store received data, programming each page once it is full
+    fn download(&mut self, mut data: &[u8]) -> Result<(), Status> {
+        while !data.is_empty() {
+            let len = data.len().min(self.page.0.len() - self.fill);
+            self.page.0[self.fill..self.fill + len].copy_from_slice(&data[..len]);
+            self.fill += len;
+            data = &data[len..];
+
+            if self.fill == self.page.0.len() {
+                self.program_page()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Program the page buffer, padded with the erased value
+    fn program_page(&mut self) -> Result<(), Status> {
+        if self.offset + PAGESIZE > Bank::Inactive.length() {
+            return Err(Status::Address);
+        }
+        let address = Bank::Inactive.address() + self.offset;
+
+        if self.offset % BLOCKSIZE == 0 {
+            unsafe { self.nvm.erase(address, 1, EraseGranularity::Block) }
+                .map_err(|e| nvm_status(e, Status::Erase))?;
+        }
+
+        self.page.0[self.fill..].fill(0xFF);
+        unsafe {
+            self.nvm
+                .write(address, self.page.0.as_ptr() as u32, PAGESIZE / 4)
+        }
+        .map_err(|e| nvm_status(e, Status::Write))?;
+
+        self.offset += PAGESIZE;
+        self.fill = 0;
+        Ok(())
+    }
+
+    /// Program the last page, then check the CRC at the end of the image
+    fn finish(&mut self) -> Result<(), Status> {
+        let length = self.offset + self.fill as u32;
+        if self.fill != 0 {
+            self.program_page()?;
+        }
+        if length < 4 || length % 4 != 0 {
+            return Err(Status::File);
+        }
+
+        let address = Bank::Inactive.address();
+        let crc = self
+            .dsu
+            .crc32(address, length - 4)
+            .map_err(|_| Status::Verify)?;
+        let expected = unsafe { core::ptr::read_volatile((address + length - 4) as *const u32) };
+        if crc != expected {
+            return Err(Status::Verify);
+        }
+        Ok(())
+    }
+}
+
+fn nvm_status(error: nvm::Error, peripheral: Status) -> Status {
+    match error {
+        nvm::Error::Peripheral(_) => peripheral,
+        _ => Status::Address,
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for Dfu {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> UsbResult<()> {
+        writer.interface(
+            self.iface,
+            CLASS_APPLICATION_SPECIFIC,
+            SUBCLASS_DFU,
+            PROTOCOL_DFU_MODE,
+        )?;
+        write_functional_descriptor(writer, ATTR_CAN_DNLOAD)
+    }
+
+    fn reset(&mut self) {
+        // An interrupted download leaves the active bank untouched, so it is
+        // simply restarted
+        if self.state != State::DfuManifestWaitReset {
+            self.state = State::DfuIdle;
+            self.status = Status::Ok;
+            self.restart();
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !is_dfu_request(&req, self.iface) {
+            return;
+        }
+
+        match (req.request, self.state) {
+            (DFU_DNLOAD, State::DfuIdle) if req.length > 0 => {
+                self.restart();
+                match self.download(xfer.data()) {
+                    Ok(()) => self.state = State::DfuDnloadSync,
+                    Err(status) => self.fail(status),
+                }
+                xfer.accept().ok();
+            }
+            (DFU_DNLOAD, State::DfuDnloadIdle) => {
+                if req.length > 0 {
+                    match self.download(xfer.data()) {
+                        Ok(()) => self.state = State::DfuDnloadSync,
+                        Err(status) => self.fail(status),
+                    }
+                } else {
+                    self.state = State::DfuManifestSync;
+                }
+                xfer.accept().ok();
+            }
+            (DFU_CLRSTATUS, State::DfuError) => {
+                self.state = State::DfuIdle;
+                self.status = Status::Ok;
+                self.restart();
+                xfer.accept().ok();
+            }
+            (DFU_ABORT, State::DfuIdle | State::DfuDnloadIdle | State::DfuManifestSync) => {
+                self.state = State::DfuIdle;
+                self.restart();
+                xfer.accept().ok();
+            }
+            _ => {
+                self.fail(Status::StalledPacket);
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !is_dfu_request(&req, self.iface) {
+            return;
+        }
+
+        match req.request {
+            DFU_GETSTATUS => {
+                let state = match self.state {
+                    State::DfuDnloadSync => {
+                        // Blocks are programmed before the download request
+                        // completes
+                        self.state = State::DfuDnloadIdle;
+                        self.state
+                    }
+                    State::DfuManifestSync => match self.finish() {
+                        Ok(()) => {
+                            // The banks can only be swapped with a reset, so
+                            // the device is not manifestation tolerant
+                            self.state = State::DfuManifestWaitReset;
+                            State::DfuManifest
+                        }
+                        Err(status) => {
+                            self.fail(status);
+                            self.state
+                        }
+                    },
+                    state => state,
+                };
+                xfer.accept_with(&[self.status as u8, 0, 0, 0, state as u8, 0])
+                    .ok();
+            }
+            DFU_GETSTATE => {
+                xfer.accept_with(&[self.state as u8]).ok();
+            }
+            _ => {
+                self.fail(Status::StalledPacket);
+                xfer.reject().ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,692 @@
+//! USB Host support
+//!
+//! [`UsbHost`] runs the USB peripheral in host mode, with a single device
+//! attached directly to the port. VBUS must be supplied to the device by the
+//! board.
+//!
+//! The driver is polled. [`UsbHost::poll`] reports the attachment and
+//! detachment of the device, after which the port is reset and the device
+//! enumerated:
+//!
+//! ```
+//! let mut host = UsbHost::new(&usb_clock, &mut peripherals.MCLK, pins.pa24, pins.pa25, peripherals.USB);
+//! loop {
+//!     match host.poll() {
+//!         Some(HostEvent::Attached) => {
+//!             host.reset_port(&mut delay).unwrap();
+//!             let (device, descriptor) = host.enumerate(1).unwrap();
+//!             let mut buf = [0; 256];
+//!             let len = host.get_configuration(&device, 0, &mut buf).unwrap();
+//!             // Find the interfaces and endpoints in `buf[..len]`, then
+//!             host.set_configuration(&device, 1).unwrap();
+//!             let pipe = host.alloc_pipe(&device, &endpoint).unwrap();
+//!         }
+//!         Some(HostEvent::Detached) => (),
+//!         None => (),
+//!     }
+//! }
+//! ```
+//!
+//! Eight pipes are available, each with a buffer of 64 bytes. Pipe 0 is used
+//! for control transfers to every device. Transfers are split in packets of
+//! the maximum packet size of the pipe, so isochronous pipes are limited to
+//! packets of 64 bytes.
+
+use bitflags::bitflags;
+use cortex_m::singleton;
+
+use crate::calibration::{usb_transn_cal, usb_transp_cal, usb_trim_cal};
+use crate::clock;
+use crate::ehal::blocking::delay::DelayMs;
+use crate::gpio::v2::{AlternateH, AnyPin, Pin, PA24, PA25};
+use crate::pac;
+use crate::pac::usb::HOST;
+use crate::pac::{MCLK, USB};
+
+mod pipedesc;
+use self::pipedesc::{PipeDescBank, PipeDescriptors};
+
+pub mod descriptors;
+pub use self::descriptors::*;
+
+/// Number of pipes
+const NUM_PIPES: usize = 8;
+
+/// Size of the buffer of each pipe
+const PIPE_BUFFER_SIZE: usize = 64;
+
+/// Number of errors allowed before a transfer fails
+const MAX_ERRORS: u8 = 3;
+
+#[repr(C, align(4))]
+struct PipeBuffers([[u8; PIPE_BUFFER_SIZE]; NUM_PIPES]);
+
+/// Speed of the attached device
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Speed {
+    Full,
+    Low,
+}
+
+/// Change of state of the port
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HostEvent {
+    /// A device was attached, and the port must be reset with
+    /// [`UsbHost::reset_port`]
+    Attached,
+    /// The device was detached. All pipes except pipe 0 were freed.
+    Detached,
+}
+
+bitflags! {
+    /// Errors reported by the pipe status of a failed transfer
+    pub struct PipeErrors: u8 {
+        const DATA_TOGGLE = 1;
+        const DATA_PID = 2;
+        const PID = 4;
+        const TIMEOUT = 8;
+        const CRC16 = 16;
+    }
+}
+
+/// USB host errors
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HostError {
+    /// No device is attached, or the port has not been reset
+    Detached,
+    /// The device answered with a STALL handshake
+    Stall,
+    /// The transfer did not complete within the timeout
+    Timeout,
+    /// The transfer failed after too many errors
+    Pipe(PipeErrors),
+    /// All pipes are in use
+    NoPipe,
+    /// The endpoint does not fit the pipe buffers, or is not of the
+    /// expected type
+    InvalidEndpoint,
+    /// The device returned a malformed descriptor
+    InvalidDescriptor,
+}
+
+/// Token of a pipe transaction
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token {
+    Setup = 0,
+    In = 1,
+    Out = 2,
+}
+
+/// Address, speed and default pipe size of an enumerated device
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Device {
+    pub address: u8,
+    pub max_packet_size0: u8,
+    pub speed: Speed,
+}
+
+/// A pipe allocated to an endpoint of a device
+///
+/// Returned by [`UsbHost::alloc_pipe`], and released with
+/// [`UsbHost::free_pipe`].
+#[derive(Debug)]
+pub struct Pipe {
+    index: usize,
+}
+
+impl Pipe {
+    /// Index of the pipe
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PipeConfig {
+    transfer_type: TransferType,
+    token: Token,
+    max_packet_size: u16,
+    armed: bool,
+}
+
+/// Generate a method that allows returning the pipe register
+/// for a given pipe index.
+macro_rules! pipe {
+    ($name:ident, $type:ident) => {
+        #[allow(unused)]
+        #[inline]
+        fn $name(&self, pipe: usize) -> &pac::usb::host::host_pipe::$type {
+            match pipe {
+                0 => &self.usb().host_pipe0.$name,
+                1 => &self.usb().host_pipe1.$name,
+                2 => &self.usb().host_pipe2.$name,
+                3 => &self.usb().host_pipe3.$name,
+                4 => &self.usb().host_pipe4.$name,
+                5 => &self.usb().host_pipe5.$name,
+                6 => &self.usb().host_pipe6.$name,
+                7 => &self.usb().host_pipe7.$name,
+                _ => unreachable!(),
+            }
+        }
+    };
+}
+
+/// USB host driver
+pub struct UsbHost {
+    _usb: USB,
+    _dm_pad: Pin<PA24, AlternateH>,
+    _dp_pad: Pin<PA25, AlternateH>,
+    desc: &'static mut PipeDescriptors,
+    buffers: &'static mut PipeBuffers,
+    pipes: [Option<PipeConfig>; NUM_PIPES],
+    speed: Option<Speed>,
+    timeout_ms: u16,
+}
+
+impl UsbHost {
+    /// Enable the USB peripheral in host mode
+    ///
+    /// Panics if called more than once, as the pipe descriptors and buffers
+    /// are statically allocated.
+    pub fn new(
+        _clock: &clock::UsbClock,
+        mclk: &mut MCLK,
+        dm_pad: impl AnyPin<Id = PA24>,
+        dp_pad: impl AnyPin<Id = PA25>,
+        usb: USB,
+    ) -> Self {
+        mclk.ahbmask.modify(|_, w| w.usb_().set_bit());
+        mclk.apbbmask.modify(|_, w| w.usb_().set_bit());
+
+        let desc = singleton!(: PipeDescriptors = PipeDescriptors::new()).unwrap();
+        let buffers =
+            singleton!(: PipeBuffers = PipeBuffers([[0; PIPE_BUFFER_SIZE]; NUM_PIPES])).unwrap();
+
+        let mut host = Self {
+            _usb: usb,
+            _dm_pad: dm_pad.into().into_mode::<AlternateH>(),
+            _dp_pad: dp_pad.into().into_mode::<AlternateH>(),
+            desc,
+            buffers,
+            pipes: [None; NUM_PIPES],
+            speed: None,
+            timeout_ms: 500,
+        };
+        host.enable();
+        host
+    }
+
+    fn usb(&self) -> &HOST {
+        unsafe { (*USB::ptr()).host() }
+    }
+
+    pipe!(pcfg, PCFG);
+    pipe!(binterval, BINTERVAL);
+    pipe!(pstatus, PSTATUS);
+    pipe!(pstatusset, PSTATUSSET);
+    pipe!(pstatusclr, PSTATUSCLR);
+    pipe!(pintflag, PINTFLAG);
+
+    fn enable(&mut self) {
+        let usb = self.usb();
+        usb.ctrla.modify(|_, w| w.swrst().set_bit());
+        while usb.syncbusy.read().swrst().bit_is_set() {}
+
+        let addr = self.desc.address();
+        let usb = self.usb();
+        usb.descadd.write(|w| unsafe { w.descadd().bits(addr) });
+        usb.padcal.modify(|_, w| unsafe {
+            w.transn().bits(usb_transn_cal());
+            w.transp().bits(usb_transp_cal());
+            w.trim().bits(usb_trim_cal())
+        });
+        usb.qosctrl.modify(|_, w| unsafe {
+            w.dqos().bits(0b11);
+            w.cqos().bits(0b11)
+        });
+        usb.ctrla.modify(|_, w| {
+            w.mode().host();
+            w.runstdby().set_bit()
+        });
+        // Full speed and low speed devices
+        usb.ctrlb.modify(|_, w| {
+            w.spdconf().normal();
+            w.vbusok().set_bit()
+        });
+
+        usb.ctrla.modify(|_, w| w.enable().set_bit());
+        while usb.syncbusy.read().enable().bit_is_set() {}
+
+        // Clear pending.
+        usb.intflag
+            .write(|w| unsafe { w.bits(usb.intflag.read().bits()) });
+
+        self.configure_pipe(0, TransferType::Control, Token::Setup, 8, 0);
+    }
+
+    /// Set the timeout of blocking transfers, 500 ms by default
+    ///
+    /// The timeout is measured with the 11-bit frame number, so it is
+    /// limited to 2047 ms. Longer timeouts are clamped.
+    pub fn set_timeout(&mut self, timeout_ms: u16) {
+        self.timeout_ms = timeout_ms.min(0x7FF);
+    }
+
+    /// Speed of the attached device, once the port has been reset
+    pub fn speed(&self) -> Option<Speed> {
+        self.speed
+    }
+
+    /// Check for the attachment or detachment of a device
+    pub fn poll(&mut self) -> Option<HostEvent> {
+        let flags = self.usb().intflag.read();
+        if flags.ddisc().bit_is_set() {
+            self.usb().intflag.write(|w| {
+                w.ddisc().set_bit();
+                w.dconn().set_bit()
+            });
+            self.usb().ctrlb.modify(|_, w| w.sofe().clear_bit());
+            self.speed = None;
+            for index in 1..NUM_PIPES {
+                self.release_pipe(index);
+            }
+            return Some(HostEvent::Detached);
+        }
+        if flags.dconn().bit_is_set() {
+            self.usb().intflag.write(|w| w.dconn().set_bit());
+            return Some(HostEvent::Attached);
+        }
+        None
+    }
+
+    /// Reset the port after a device was attached, and start sending
+    /// start-of-frame packets
+    ///
+    /// Waits 100 ms for the connection to settle before the reset, and 20 ms
+    /// for the device to recover after it. Returns the speed of the device.
+    pub fn reset_port<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<Speed, HostError> {
+        delay.delay_ms(100);
+        self.usb().ctrlb.modify(|_, w| w.busreset().set_bit());
+        loop {
+            let flags = self.usb().intflag.read();
+            if flags.ddisc().bit_is_set() {
+                return Err(HostError::Detached);
+            }
+            if flags.rst().bit_is_set() {
+                break;
+            }
+        }
+        self.usb().intflag.write(|w| w.rst().set_bit());
+        self.usb().ctrlb.modify(|_, w| w.sofe().set_bit());
+
+        let speed = match self.usb().status.read().speed().bits() {
+            1 => Speed::Low,
+            _ => Speed::Full,
+        };
+        self.speed = Some(speed);
+        delay.delay_ms(20);
+        Ok(speed)
+    }
+
+    /// Current frame number
+    fn frame(&self) -> u16 {
+        self.usb().fnum.read().fnum().bits()
+    }
+
+    /// Number of frames since `start`
+    fn frames_since(&self, start: u16) -> u16 {
+        self.frame().wrapping_sub(start) & 0x7FF
+    }
+
+    /// Wait for `count` frames, 1 ms each
+    fn wait_frames(&self, count: u16) -> Result<(), HostError> {
+        let start = self.frame();
+        while self.frames_since(start) < count {
+            if self.usb().intflag.read().ddisc().bit_is_set() {
+                return Err(HostError::Detached);
+            }
+        }
+        Ok(())
+    }
+
+    fn configure_pipe(
+        &mut self,
+        index: usize,
+        transfer_type: TransferType,
+        token: Token,
+        max_packet_size: u16,
+        interval: u8,
+    ) {
+        let buffer = self.buffers.0[index].as_mut_ptr();
+        let bank = self.desc.bank(index, 0);
+        bank.set_address(buffer);
+        bank.set_pipe_size(max_packet_size);
+        bank.set_byte_count(0);
+        bank.set_multi_packet_size(0);
+        bank.set_max_errors(MAX_ERRORS);
+        bank.clear_errors();
+
+        self.pcfg(index).write(|w| unsafe {
+            w.ptype().bits(transfer_type as u8);
+            w.ptoken().bits(token as u8);
+            w.bk().clear_bit()
+        });
+        self.binterval(index)
+            .write(|w| unsafe { w.bitinterval().bits(interval) });
+        self.pstatusset(index).write(|w| w.pfreeze().set_bit());
+        self.pstatusclr(index).write(|w| w.dtgl().set_bit());
+
+        self.pipes[index] = Some(PipeConfig {
+            transfer_type,
+            token,
+            max_packet_size,
+            armed: false,
+        });
+    }
+
+    fn release_pipe(&mut self, index: usize) {
+        self.pstatusset(index).write(|w| w.pfreeze().set_bit());
+        self.pcfg(index).write(|w| unsafe { w.ptype().bits(0) });
+        self.pipes[index] = None;
+    }
+
+    /// Allocate a pipe to an endpoint of a device
+    ///
+    /// Endpoints with a maximum packet size above 64 bytes are not supported.
+    pub fn alloc_pipe(
+        &mut self,
+        device: &Device,
+        endpoint: &EndpointDescriptor,
+    ) -> Result<Pipe, HostError> {
+        if endpoint.max_packet_size as usize > PIPE_BUFFER_SIZE || endpoint.max_packet_size == 0 {
+            return Err(HostError::InvalidEndpoint);
+        }
+        let index = (1..NUM_PIPES)
+            .find(|&index| self.pipes[index].is_none())
+            .ok_or(HostError::NoPipe)?;
+        let token = if endpoint.is_in() {
+            Token::In
+        } else {
+            Token::Out
+        };
+        self.configure_pipe(
+            index,
+            endpoint.transfer_type(),
+            token,
+            endpoint.max_packet_size,
+            endpoint.interval,
+        );
+        self.desc
+            .bank(index, 0)
+            .set_target(device.address, endpoint.number());
+        Ok(Pipe { index })
+    }
+
+    /// Release a pipe
+    pub fn free_pipe(&mut self, pipe: Pipe) {
+        self.release_pipe(pipe.index);
+    }
+
+    fn bank(&mut self, index: usize) -> &mut PipeDescBank {
+        self.desc.bank(index, 0)
+    }
+
+    fn config(&self, index: usize) -> Result<PipeConfig, HostError> {
+        self.pipes[index].ok_or(HostError::Detached)
+    }
+
+    /// Start a transaction on a pipe. For OUT and SETUP tokens, the data must
+    /// already be in the pipe buffer.
+    fn start(&mut self, index: usize, token: Token, len: usize) {
+        let bank = self.bank(index);
+        bank.set_byte_count(if token == Token::In { 0 } else { len as u16 });
+        bank.set_multi_packet_size(0);
+        bank.clear_errors();
+
+        self.pcfg(index)
+            .modify(|_, w| unsafe { w.ptoken().bits(token as u8) });
+        self.pintflag(index).write(|w| unsafe { w.bits(0x3F) });
+        if token == Token::In {
+            self.pstatusclr(index).write(|w| w.bk0rdy().set_bit());
+        } else {
+            self.pstatusset(index).write(|w| w.bk0rdy().set_bit());
+        }
+        self.pstatusclr(index).write(|w| w.pfreeze().set_bit());
+    }
+
+    /// Check the completion of a transaction started with `start`
+    fn check(&mut self, index: usize, token: Token) -> nb::Result<usize, HostError> {
+        let flags = self.pintflag(index).read();
+        let result = if (token == Token::Setup && flags.txstp().bit_is_set())
+            || (token != Token::Setup && flags.trcpt0().bit_is_set())
+        {
+            Ok(self.bank(index).get_byte_count() as usize)
+        } else if flags.stall().bit_is_set() {
+            Err(HostError::Stall)
+        } else if flags.perr().bit_is_set() || flags.trfail().bit_is_set() {
+            let errors = PipeErrors::from_bits_truncate(self.bank(index).errors());
+            Err(HostError::Pipe(errors))
+        } else if self.usb().intflag.read().ddisc().bit_is_set() {
+            Err(HostError::Detached)
+        } else {
+            return Err(nb::Error::WouldBlock);
+        };
+        self.pstatusset(index).write(|w| w.pfreeze().set_bit());
+        self.pintflag(index).write(|w| unsafe { w.bits(0x3F) });
+        result.map_err(nb::Error::Other)
+    }
+
+    /// Run a single transaction on a pipe, and wait for its completion
+    fn transaction(&mut self, index: usize, token: Token, len: usize) -> Result<usize, HostError> {
+        self.start(index, token, len);
+        let start = self.frame();
+        loop {
+            match self.check(index, token) {
+                Ok(count) => return Ok(count),
+                Err(nb::Error::Other(e)) => return Err(e),
+                Err(nb::Error::WouldBlock) => {
+                    if self.frames_since(start) >= self.timeout_ms {
+                        self.pstatusset(index).write(|w| w.pfreeze().set_bit());
+                        return Err(HostError::Timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Receive packets on a pipe until a short packet or a full buffer
+    fn read_packets(&mut self, index: usize, buf: &mut [u8]) -> Result<usize, HostError> {
+        let max_packet_size = self.config(index)?.max_packet_size as usize;
+        let mut received = 0;
+        loop {
+            let count = self.transaction(index, Token::In, 0)?;
+            let count = count.min(buf.len() - received);
+            buf[received..received + count].copy_from_slice(&self.buffers.0[index][..count]);
+            received += count;
+            if count < max_packet_size || received == buf.len() {
+                return Ok(received);
+            }
+        }
+    }
+
+    /// Send packets on a pipe
+    fn write_packets(&mut self, index: usize, data: &[u8]) -> Result<(), HostError> {
+        let max_packet_size = self.config(index)?.max_packet_size as usize;
+        for chunk in data.chunks(max_packet_size) {
+            self.buffers.0[index][..chunk.len()].copy_from_slice(chunk);
+            self.transaction(index, Token::Out, chunk.len())?;
+        }
+        Ok(())
+    }
+
+    /// Configure pipe 0 for a control transfer to `device`, and send the
+    /// SETUP packet
+    fn setup(&mut self, device: &Device, setup: &SetupPacket) -> Result<(), HostError> {
+        if self.speed.is_none() {
+            return Err(HostError::Detached);
+        }
+        let max_packet_size = device.max_packet_size0.clamp(8, 64) as u16;
+        self.configure_pipe(0, TransferType::Control, Token::Setup, max_packet_size, 0);
+        self.bank(0).set_target(device.address, 0);
+        self.buffers.0[0][..8].copy_from_slice(&setup.to_bytes());
+        self.transaction(0, Token::Setup, 8)?;
+        // The data and status stages start with DATA1
+        self.pstatusset(0).write(|w| w.dtgl().set_bit());
+        Ok(())
+    }
+
+    /// Perform a control transfer with a data stage from the device
+    ///
+    /// Returns the number of bytes received.
+    pub fn control_in(
+        &mut self,
+        device: &Device,
+        setup: &SetupPacket,
+        buf: &mut [u8],
+    ) -> Result<usize, HostError> {
+        self.setup(device, setup)?;
+        let len = buf.len().min(setup.length as usize);
+        let received = self.read_packets(0, &mut buf[..len])?;
+        self.pstatusset(0).write(|w| w.dtgl().set_bit());
+        self.transaction(0, Token::Out, 0)?;
+        Ok(received)
+    }
+
+    /// Perform a control transfer with an optional data stage to the device
+    pub fn control_out(
+        &mut self,
+        device: &Device,
+        setup: &SetupPacket,
+        data: &[u8],
+    ) -> Result<(), HostError> {
+        self.setup(device, setup)?;
+        self.write_packets(0, data)?;
+        self.pstatusset(0).write(|w| w.dtgl().set_bit());
+        self.transaction(0, Token::In, 0)?;
+        Ok(())
+    }
+
+    fn check_pipe(
+        &self,
+        pipe: &Pipe,
+        transfer_type: TransferType,
+        token: Token,
+    ) -> Result<(), HostError> {
+        let config = self.config(pipe.index)?;
+        if config.token != token || config.transfer_type != transfer_type {
+            return Err(HostError::InvalidEndpoint);
+        }
+        Ok(())
+    }
+
+    /// Receive data from a bulk IN pipe, until a short packet or a full
+    /// buffer
+    pub fn bulk_in(&mut self, pipe: &Pipe, buf: &mut [u8]) -> Result<usize, HostError> {
+        self.check_pipe(pipe, TransferType::Bulk, Token::In)?;
+        self.read_packets(pipe.index, buf)
+    }
+
+    /// Send data on a bulk OUT pipe
+    ///
+    /// No zero-length packet is sent after data that fills the last packet.
+    pub fn bulk_out(&mut self, pipe: &Pipe, data: &[u8]) -> Result<(), HostError> {
+        self.check_pipe(pipe, TransferType::Bulk, Token::Out)?;
+        self.write_packets(pipe.index, data)
+    }
+
+    /// Receive a packet from an interrupt IN pipe
+    ///
+    /// The first call starts polling the endpoint at the interval of its
+    /// descriptor. Returns [`WouldBlock`](nb::Error::WouldBlock) until the
+    /// device sends a packet.
+    pub fn interrupt_in(&mut self, pipe: &Pipe, buf: &mut [u8]) -> nb::Result<usize, HostError> {
+        let index = pipe.index;
+        self.check_pipe(pipe, TransferType::Interrupt, Token::In)?;
+        let pipes = &mut self.pipes;
+        let config = pipes[index].as_mut().ok_or(HostError::Detached)?;
+        if !config.armed {
+            config.armed = true;
+            self.start(index, Token::In, 0);
+            return Err(nb::Error::WouldBlock);
+        }
+        let result = self.check(index, Token::In);
+        if !matches!(result, Err(nb::Error::WouldBlock)) {
+            if let Some(config) = self.pipes[index].as_mut() {
+                config.armed = false;
+            }
+        }
+        let count = result?.min(buf.len());
+        buf[..count].copy_from_slice(&self.buffers.0[index][..count]);
+        Ok(count)
+    }
+
+    /// Send a packet on an interrupt OUT pipe
+    pub fn interrupt_out(&mut self, pipe: &Pipe, data: &[u8]) -> Result<(), HostError> {
+        self.check_pipe(pipe, TransferType::Interrupt, Token::Out)?;
+        self.write_packets(pipe.index, data)
+    }
+
+    /// Read a descriptor from a device
+    pub fn get_descriptor(
+        &mut self,
+        device: &Device,
+        descriptor_type: u8,
+        index: u8,
+        buf: &mut [u8],
+    ) -> Result<usize, HostError> {
+        let setup = SetupPacket::get_descriptor(descriptor_type, index, 0, buf.len() as u16);
+        self.control_in(device, &setup, buf)
+    }
+
+    /// Enumerate the device attached to the reset port, and give it an
+    /// address
+    pub fn enumerate(&mut self, address: u8) -> Result<(Device, DeviceDescriptor), HostError> {
+        let speed = self.speed.ok_or(HostError::Detached)?;
+        let mut device = Device {
+            address: 0,
+            max_packet_size0: 8,
+            speed,
+        };
+
+        // Read the size of the default pipe first
+        let mut buf = [0; DeviceDescriptor::LENGTH];
+        if self.get_descriptor(&device, DEVICE_DESCRIPTOR, 0, &mut buf[..8])? < 8 {
+            return Err(HostError::InvalidDescriptor);
+        }
+        device.max_packet_size0 = buf[7];
+
+        self.control_out(&device, &SetupPacket::set_address(address), &[])?;
+        self.wait_frames(2)?;
+        device.address = address;
+
+        let len = self.get_descriptor(&device, DEVICE_DESCRIPTOR, 0, &mut buf)?;
+        let descriptor =
+            DeviceDescriptor::from_bytes(&buf[..len]).ok_or(HostError::InvalidDescriptor)?;
+        Ok((device, descriptor))
+    }
+
+    /// Read a complete configuration descriptor, with its interface and
+    /// endpoint descriptors
+    ///
+    /// The descriptor is truncated to the size of `buf`. Returns the number
+    /// of bytes read. The packed descriptors can be walked with a
+    /// [`DescriptorIter`].
+    pub fn get_configuration(
+        &mut self,
+        device: &Device,
+        index: u8,
+        buf: &mut [u8],
+    ) -> Result<usize, HostError> {
+        let mut header = [0; ConfigurationDescriptor::LENGTH];
+        let len = self.get_descriptor(device, CONFIGURATION_DESCRIPTOR, index, &mut header)?;
+        let config = ConfigurationDescriptor::from_bytes(&header[..len])
+            .ok_or(HostError::InvalidDescriptor)?;
+        let len = buf.len().min(config.total_length as usize);
+        self.get_descriptor(device, CONFIGURATION_DESCRIPTOR, index, &mut buf[..len])
+    }
+
+    /// Select the configuration of a device
+    pub fn set_configuration(&mut self, device: &Device, value: u8) -> Result<(), HostError> {
+        self.control_out(device, &SetupPacket::set_configuration(value), &[])
+    }
+}
@@ -0,0 +1,280 @@
+//! Standard USB requests and descriptors, as seen from the host
+
+use core::convert::TryInto;
+
+/// Descriptor type of a device descriptor
+pub const DEVICE_DESCRIPTOR: u8 = 1;
+/// Descriptor type of a configuration descriptor
+pub const CONFIGURATION_DESCRIPTOR: u8 = 2;
+/// Descriptor type of a string descriptor
+pub const STRING_DESCRIPTOR: u8 = 3;
+/// Descriptor type of an interface descriptor
+pub const INTERFACE_DESCRIPTOR: u8 = 4;
+/// Descriptor type of an endpoint descriptor
+pub const ENDPOINT_DESCRIPTOR: u8 = 5;
+
+const GET_DESCRIPTOR: u8 = 6;
+const SET_ADDRESS: u8 = 5;
+const SET_CONFIGURATION: u8 = 9;
+
+/// The 8-byte packet sent in the SETUP stage of a control transfer
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    /// Standard GET_DESCRIPTOR request
+    pub fn get_descriptor(descriptor_type: u8, index: u8, language: u16, length: u16) -> Self {
+        Self {
+            request_type: 0x80,
+            request: GET_DESCRIPTOR,
+            value: (descriptor_type as u16) << 8 | index as u16,
+            index: language,
+            length,
+        }
+    }
+
+    /// Standard SET_ADDRESS request
+    pub fn set_address(address: u8) -> Self {
+        Self {
+            request_type: 0x00,
+            request: SET_ADDRESS,
+            value: address as u16,
+            index: 0,
+            length: 0,
+        }
+    }
+
+    /// Standard SET_CONFIGURATION request
+    pub fn set_configuration(value: u8) -> Self {
+        Self {
+            request_type: 0x00,
+            request: SET_CONFIGURATION,
+            value: value as u16,
+            index: 0,
+            length: 0,
+        }
+    }
+
+    /// Returns `true` for device-to-host requests
+    pub fn is_in(&self) -> bool {
+        self.request_type & 0x80 != 0
+    }
+
+    /// Serialize the packet, as sent on the bus
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let value = self.value.to_le_bytes();
+        let index = self.index.to_le_bytes();
+        let length = self.length.to_le_bytes();
+        [
+            self.request_type,
+            self.request,
+            value[0],
+            value[1],
+            index[0],
+            index[1],
+            length[0],
+            length[1],
+        ]
+    }
+}
+
+/// A parsed device descriptor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceDescriptor {
+    pub usb_version: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub max_packet_size0: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub device_version: u16,
+    pub manufacturer: u8,
+    pub product: u8,
+    pub serial_number: u8,
+    pub num_configurations: u8,
+}
+
+impl DeviceDescriptor {
+    /// Length of a device descriptor
+    pub const LENGTH: usize = 18;
+
+    /// Parse a device descriptor
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::LENGTH || bytes[1] != DEVICE_DESCRIPTOR {
+            return None;
+        }
+        Some(Self {
+            usb_version: u16_at(bytes, 2),
+            class: bytes[4],
+            subclass: bytes[5],
+            protocol: bytes[6],
+            max_packet_size0: bytes[7],
+            vendor_id: u16_at(bytes, 8),
+            product_id: u16_at(bytes, 10),
+            device_version: u16_at(bytes, 12),
+            manufacturer: bytes[14],
+            product: bytes[15],
+            serial_number: bytes[16],
+            num_configurations: bytes[17],
+        })
+    }
+}
+
+/// A parsed configuration descriptor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConfigurationDescriptor {
+    pub total_length: u16,
+    pub num_interfaces: u8,
+    pub value: u8,
+    pub attributes: u8,
+    pub max_power: u8,
+}
+
+impl ConfigurationDescriptor {
+    /// Length of a configuration descriptor, without the descriptors that
+    /// follow it
+    pub const LENGTH: usize = 9;
+
+    /// Parse a configuration descriptor
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::LENGTH || bytes[1] != CONFIGURATION_DESCRIPTOR {
+            return None;
+        }
+        Some(Self {
+            total_length: u16_at(bytes, 2),
+            num_interfaces: bytes[4],
+            value: bytes[5],
+            attributes: bytes[7],
+            max_power: bytes[8],
+        })
+    }
+}
+
+/// A parsed interface descriptor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InterfaceDescriptor {
+    pub number: u8,
+    pub alternate_setting: u8,
+    pub num_endpoints: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+}
+
+impl InterfaceDescriptor {
+    /// Parse an interface descriptor
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 9 || bytes[1] != INTERFACE_DESCRIPTOR {
+            return None;
+        }
+        Some(Self {
+            number: bytes[2],
+            alternate_setting: bytes[3],
+            num_endpoints: bytes[4],
+            class: bytes[5],
+            subclass: bytes[6],
+            protocol: bytes[7],
+        })
+    }
+}
+
+/// Transfer type of an endpoint
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransferType {
+    Control = 1,
+    Isochronous = 2,
+    Bulk = 3,
+    Interrupt = 4,
+}
+
+/// A parsed endpoint descriptor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EndpointDescriptor {
+    pub address: u8,
+    pub attributes: u8,
+    pub max_packet_size: u16,
+    pub interval: u8,
+}
+
+impl EndpointDescriptor {
+    /// Descriptor of the default control endpoint of a device
+    pub fn control(max_packet_size: u8) -> Self {
+        Self {
+            address: 0,
+            attributes: 0,
+            max_packet_size: max_packet_size as u16,
+            interval: 0,
+        }
+    }
+
+    /// Parse an endpoint descriptor
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 7 || bytes[1] != ENDPOINT_DESCRIPTOR {
+            return None;
+        }
+        Some(Self {
+            address: bytes[2],
+            attributes: bytes[3],
+            max_packet_size: u16_at(bytes, 4),
+            interval: bytes[6],
+        })
+    }
+
+    /// Endpoint number, without the direction bit
+    pub fn number(&self) -> u8 {
+        self.address & 0x0F
+    }
+
+    /// Returns `true` for device-to-host endpoints
+    pub fn is_in(&self) -> bool {
+        self.address & 0x80 != 0
+    }
+
+    /// Transfer type of the endpoint
+    pub fn transfer_type(&self) -> TransferType {
+        match self.attributes & 0b11 {
+            0 => TransferType::Control,
+            1 => TransferType::Isochronous,
+            2 => TransferType::Bulk,
+            _ => TransferType::Interrupt,
+        }
+    }
+}
+
+/// Iterator over the descriptors packed in a configuration descriptor
+///
+/// Each item is a complete descriptor, starting with its length and type.
+pub struct DescriptorIter<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> DescriptorIter<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+}
+
+impl<'a> Iterator for DescriptorIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let length = *self.bytes.first()? as usize;
+        if length < 2 || length > self.bytes.len() {
+            return None;
+        }
+        let (descriptor, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Some(descriptor)
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
@@ -0,0 +1,162 @@
+use bitfield::bitfield;
+use core::mem;
+use core::ptr::null_mut;
+
+bitfield! {
+    struct PckSize(u32);
+    impl Debug;
+    pub byte_count, set_byte_count: 13, 0;
+    pub multi_packet_size, set_multi_packet_size: 27, 14;
+    pub size, set_size: 30, 28;
+    pub auto_zlp, set_auto_zlp : 31;
+}
+
+bitfield! {
+    struct CtrlPipe(u16);
+    impl Debug;
+    pub pdaddr, set_pdaddr: 6, 0;
+    pub pepnum, set_pepnum: 11, 8;
+    pub permax, set_permax: 15, 12;
+}
+
+bitfield! {
+    struct StatusPipe(u16);
+    impl Debug;
+    pub errors, set_errors: 4, 0;
+    pub ercnt, set_ercnt: 7, 5;
+}
+
+/// Host mode descriptor of one bank of a pipe
+#[repr(C)]
+#[derive(Debug)]
+pub struct PipeDescBank {
+    /// pipe data buffer, must be 32-bit aligned
+    addr: *mut u8,
+    pcksize: PckSize,
+    extreg: u16,
+    status_bk: u8,
+    _reserved: u8,
+    ctrl_pipe: CtrlPipe,
+    status_pipe: StatusPipe,
+}
+
+impl PipeDescBank {
+    fn new() -> Self {
+        debug_assert_eq!(16, mem::size_of::<PipeDescBank>());
+        Self {
+            addr: null_mut(),
+            pcksize: PckSize(0),
+            extreg: 0,
+            status_bk: 0,
+            _reserved: 0,
+            ctrl_pipe: CtrlPipe(0),
+            status_pipe: StatusPipe(0),
+        }
+    }
+
+    pub fn set_address(&mut self, address: *mut u8) {
+        self.addr = address;
+    }
+
+    /// These bits contains the maximum packet size of the pipe.
+    ///
+    /// Rounds up to the lowest size value which will accommodate `size`.
+    /// Panics if a `size` > 1023 is supplied.
+    pub fn set_pipe_size(&mut self, size: u16) {
+        let size = match size {
+            1..=8 => 0u32,
+            9..=16 => 1,
+            17..=32 => 2,
+            33..=64 => 3,
+            65..=128 => 4,
+            129..=256 => 5,
+            257..=512 => 6,
+            513..=1023 => 7,
+            _ => unreachable!(),
+        };
+        self.pcksize.set_size(size);
+    }
+
+    /// For OUT and SETUP pipes, BYTE_COUNT holds the number of bytes to be
+    /// sent in the next transaction. For IN pipes, it holds the number of
+    /// bytes received in the last transaction.
+    pub fn set_byte_count(&mut self, size: u16) {
+        self.pcksize.set_byte_count(size.into());
+    }
+
+    pub fn get_byte_count(&self) -> u16 {
+        self.pcksize.byte_count() as u16
+    }
+
+    pub fn set_multi_packet_size(&mut self, size: u16) {
+        self.pcksize.set_multi_packet_size(size.into());
+    }
+
+    /// Set the address and endpoint number of the device targeted by the
+    /// pipe
+    pub fn set_target(&mut self, device_address: u8, endpoint: u8) {
+        self.ctrl_pipe.set_pdaddr(device_address.into());
+        self.ctrl_pipe.set_pepnum(endpoint.into());
+    }
+
+    /// Set the number of errors allowed before the pipe is stopped
+    pub fn set_max_errors(&mut self, count: u8) {
+        self.ctrl_pipe.set_permax(count.into());
+    }
+
+    /// Return the error flags of the last transfer: data toggle, data PID,
+    /// PID, timeout and CRC16 errors, from bit 0 to 4
+    pub fn errors(&self) -> u8 {
+        self.status_pipe.errors() as u8
+    }
+
+    /// Clear the error flags and counter
+    pub fn clear_errors(&mut self) {
+        self.status_pipe = StatusPipe(0);
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct PipeDescriptor {
+    bank: [PipeDescBank; 2],
+}
+
+impl PipeDescriptor {
+    fn new() -> Self {
+        Self {
+            bank: [PipeDescBank::new(), PipeDescBank::new()],
+        }
+    }
+}
+
+pub struct PipeDescriptors {
+    desc: [PipeDescriptor; 8],
+}
+
+impl PipeDescriptors {
+    pub fn new() -> Self {
+        Self {
+            desc: [
+                PipeDescriptor::new(),
+                PipeDescriptor::new(),
+                PipeDescriptor::new(),
+                PipeDescriptor::new(),
+                PipeDescriptor::new(),
+                PipeDescriptor::new(),
+                PipeDescriptor::new(),
+                PipeDescriptor::new(),
+            ],
+        }
+    }
+
+    pub fn address(&self) -> u32 {
+        &self.desc as *const _ as u32
+    }
+
+    pub fn bank(&mut self, idx: usize, bank: usize) -> &mut PipeDescBank {
+        &mut self.desc[idx].bank[bank]
+    }
+}
+
+unsafe impl Send for PipeDescBank {}
@@ -1,15 +1,24 @@
-//! USB Device support
+//! USB Device and Host support
 
 use crate::gpio;
 
 pub use usb_device;
 
 mod bus;
-pub use self::bus::UsbBus;
+pub use self::bus::{LinkState, UsbBus};
+
+mod feedback;
+pub use self::feedback::Feedback;
 
 mod devicedesc;
 use self::devicedesc::Descriptors;
 
+pub mod host;
+pub use self::host::UsbHost;
+
+pub mod dfu;
+pub use self::dfu::{Dfu, DfuRuntime};
+
 /// Default SOF pad
 #[allow(deprecated)]
 pub type SofPad = gpio::v1::Pa23<gpio::v1::PfH>;
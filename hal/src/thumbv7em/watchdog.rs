@@ -1,5 +1,11 @@
 use crate::ehal::watchdog;
-use crate::pac::WDT;
+use crate::pac::{RSTC, WDT};
+use crate::time::Milliseconds;
+
+use super::{reset_cause, ResetCause};
+
+/// Frequency of the watchdog clock, derived from the OSCULP32K
+pub const WDT_CLOCK_FREQ: u32 = 1024;
 
 /// WatchdogTimeout enumerates usable values for configuring
 /// the timeout of the watchdog peripheral.
@@ -20,13 +26,165 @@ pub enum WatchdogTimeout {
     Cycles16K,
 }
 
+impl WatchdogTimeout {
+    /// Returns the shortest timeout that lasts at least `ms`, saturating
+    /// at [`WatchdogTimeout::Cycles16K`] (16 seconds)
+    pub fn from_ms(ms: impl Into<Milliseconds>) -> Self {
+        let ms = ms.into().0 as u64;
+        let cycles = (ms * WDT_CLOCK_FREQ as u64 + 999) / 1000;
+        let mut bits = 0;
+        while bits < Self::Cycles16K as u8 && (8_u64 << bits) < cycles {
+            bits += 1;
+        }
+        Self::from(bits)
+    }
+
+    /// Returns the number of watchdog clock cycles
+    pub fn cycles(self) -> u32 {
+        8 << self as u8
+    }
+
+    /// Returns the duration of the timeout, rounded down to the millisecond
+    pub fn ms(self) -> Milliseconds {
+        Milliseconds(self.cycles() * 1000 / WDT_CLOCK_FREQ)
+    }
+}
+
+/// Converts a raw `PER` register value. Values above the longest timeout
+/// saturate at [`WatchdogTimeout::Cycles16K`].
+impl From<u8> for WatchdogTimeout {
+    fn from(bits: u8) -> Self {
+        use WatchdogTimeout::*;
+        match bits {
+            0 => Cycles8,
+            1 => Cycles16,
+            2 => Cycles32,
+            3 => Cycles64,
+            4 => Cycles128,
+            5 => Cycles256,
+            6 => Cycles512,
+            7 => Cycles1K,
+            8 => Cycles2K,
+            9 => Cycles4K,
+            10 => Cycles8K,
+            _ => Cycles16K,
+        }
+    }
+}
+
+impl From<Milliseconds> for WatchdogTimeout {
+    fn from(ms: Milliseconds) -> Self {
+        Self::from_ms(ms)
+    }
+}
+
+/// Returns `true` if the last reset was caused by the watchdog timer.
+pub fn watchdog_reset(rstc: &RSTC) -> bool {
+    matches!(reset_cause(rstc), ResetCause::Watchdog)
+}
+
+/// The watchdog timer (WDT)
+///
+/// In normal mode, started through [`WatchdogEnable`](watchdog::WatchdogEnable),
+/// the watchdog resets the processor if it isn't fed within the timeout. In
+/// window mode, started with [`Watchdog::start_window`], feeding it too
+/// early also resets the processor.
+///
+/// An early-warning interrupt can be raised some time before the watchdog
+/// expires, for example to save state before the reset.
 pub struct Watchdog {
     wdt: WDT,
+    early_warning: Option<WatchdogTimeout>,
 }
 
 impl Watchdog {
     pub fn new(wdt: WDT) -> Self {
-        Self { wdt }
+        Self {
+            wdt,
+            early_warning: None,
+        }
+    }
+
+    /// Set the offset of the early-warning interrupt, counted from the start
+    /// of the timeout in normal mode, or from the end of the closed window
+    /// in window mode. The offset must be shorter than the timeout.
+    ///
+    /// The offset is applied the next time the watchdog is started.
+    pub fn set_early_warning(&mut self, offset: impl Into<WatchdogTimeout>) {
+        self.early_warning = Some(offset.into());
+    }
+
+    /// Enable the early-warning interrupt
+    pub fn enable_early_warning_interrupt(&mut self) {
+        self.wdt.intenset.write(|w| w.ew().set_bit());
+    }
+
+    /// Disable the early-warning interrupt
+    pub fn disable_early_warning_interrupt(&mut self) {
+        self.wdt.intenclr.write(|w| w.ew().set_bit());
+    }
+
+    /// Returns `true` if the early-warning offset has elapsed
+    pub fn is_early_warning(&self) -> bool {
+        self.wdt.intflag.read().ew().bit_is_set()
+    }
+
+    /// Clear the early-warning flag
+    pub fn clear_early_warning(&mut self) {
+        self.wdt.intflag.write(|w| w.ew().set_bit());
+    }
+
+    /// Starts the watchdog timer in window mode.
+    ///
+    /// Feeding the watchdog during the closed `window` resets the processor,
+    /// as does not feeding it within the following `timeout`.
+    pub fn start_window(
+        &mut self,
+        window: impl Into<WatchdogTimeout>,
+        timeout: impl Into<WatchdogTimeout>,
+    ) {
+        self.configure(timeout.into(), Some(window.into()));
+        self.wdt.ctrla.write(|w| {
+            w.wen().set_bit();
+            w.enable().set_bit()
+        });
+        while self.wdt.syncbusy.read().enable().bit_is_set()
+            || self.wdt.syncbusy.read().wen().bit_is_set()
+        {}
+    }
+
+    /// Starts the watchdog timer in always-on mode, in window mode if a
+    /// `window` is given.
+    ///
+    /// Once started in always-on mode, the watchdog cannot be disabled, and
+    /// its configuration cannot be changed until the next reset.
+    pub fn start_always_on(
+        &mut self,
+        window: Option<WatchdogTimeout>,
+        timeout: impl Into<WatchdogTimeout>,
+    ) {
+        self.configure(timeout.into(), window);
+        self.wdt.ctrla.write(|w| {
+            w.wen().bit(window.is_some());
+            w.alwayson().set_bit()
+        });
+        while self.wdt.syncbusy.read().alwayson().bit_is_set()
+            || self.wdt.syncbusy.read().wen().bit_is_set()
+        {}
+    }
+
+    /// Write the timeout, window and early-warning offset, which can only be
+    /// changed while the watchdog is disabled.
+    fn configure(&mut self, timeout: WatchdogTimeout, window: Option<WatchdogTimeout>) {
+        let window = window.unwrap_or(WatchdogTimeout::Cycles16K);
+        let offset = self.early_warning.unwrap_or(WatchdogTimeout::Cycles16K);
+        self.wdt.config.write(|w| unsafe {
+            w.per().bits(timeout as u8);
+            w.window().bits(window as u8)
+        });
+        self.wdt
+            .ewctrl
+            .write(|w| unsafe { w.ewoffset().bits(offset as u8) });
     }
 }
 
@@ -49,18 +207,19 @@ impl watchdog::WatchdogDisable for Watchdog {
 }
 
 impl watchdog::WatchdogEnable for Watchdog {
-    type Time = u8;
+    type Time = WatchdogTimeout;
 
     /// Enables a watchdog timer to reset the processor if software is frozen
     /// or stalled.
+    ///
+    /// The timeout can be given as a [`WatchdogTimeout`], a raw `PER` value
+    /// or a duration in [`Milliseconds`].
     fn start<T>(&mut self, period: T)
     where
         T: Into<Self::Time>,
     {
         // Write the timeout configuration.
-        self.wdt
-            .config
-            .write(|w| unsafe { w.per().bits(period.into()) });
+        self.configure(period.into(), None);
         // Enable the watchdog timer.
         self.wdt.ctrla.write(|w| w.enable().set_bit());
         // Wait for watchdog timer to be enabled.
@@ -644,9 +644,27 @@ mod private {
     impl Sealed for u32 {}
     impl Sealed for i32 {}
     impl Sealed for f32 {}
+
+    /// Implementation of [`Increment`](super::Increment), hidden so that
+    /// counts can only be changed within the HAL
+    pub trait PrivateIncrement {
+        /// The type of `Self` with the incremented count
+        type Inc;
+        /// Increment the count
+        fn inc(self) -> Self::Inc;
+    }
+
+    /// Implementation of [`Decrement`](super::Decrement), hidden so that
+    /// counts can only be changed within the HAL
+    pub trait PrivateDecrement {
+        /// The type of `Self` with the decremented count
+        type Dec;
+        /// Decrement the count
+        fn dec(self) -> Self::Dec;
+    }
 }
 
-pub(crate) use private::Sealed;
+pub(crate) use private::{PrivateDecrement, PrivateIncrement, Sealed};
 
 /// Type-level version of the [None] variant
 #[derive(Default)]
@@ -707,3 +725,21 @@ impl Sealed for U0 {}
 
 /// Implement `Sealed` for all type-level, [`Unsigned`] integers *except* [`U0`]
 impl<U: Unsigned, B: Bit> Sealed for UInt<U, B> {}
+
+/// Type-level counter that can be incremented
+///
+/// Implementors are types that hold a count in the form of an [`Unsigned`]
+/// type parameter, such as the clock tokens of the SAMD5x/E5x `clock::v2`
+/// module. The count can only be changed within the HAL, but the trait can be
+/// used as a bound, and the incremented type is available as `T::Inc`.
+pub trait Increment: PrivateIncrement {}
+
+impl<T: PrivateIncrement> Increment for T {}
+
+/// Type-level counter that can be decremented
+///
+/// This is the inverse of [`Increment`]. It is only implemented when the
+/// count is non-zero, and the decremented type is available as `T::Dec`.
+pub trait Decrement: PrivateDecrement {}
+
+impl<T: PrivateDecrement> Decrement for T {}